@@ -1,3 +1,5 @@
+/// Search for the largest accepted value in `min..=max`, assuming everything below it is
+/// accepted and everything above it is rejected.
 #[derive(Debug)]
 pub struct Bisect {
     min: u64,
@@ -9,6 +11,7 @@ impl Bisect {
         Self { min, max }
     }
 
+    /// Value to test next, `None` when done.
     pub fn next(&self) -> Option<u64> {
         if self.min == self.max {
             None
@@ -33,12 +36,9 @@ impl Bisect {
         }
     }
 
-    pub fn finish(&self) -> Result<u64, ()> {
-        if self.min == self.max {
-            Ok(self.min)
-        } else {
-            Err(())
-        }
+    /// The learned value, `None` when not done.
+    pub fn finish(&self) -> Option<u64> {
+        (self.min == self.max).then_some(self.min)
     }
 
     // ----- Getter & Setter
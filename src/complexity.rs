@@ -0,0 +1,388 @@
+use std::{
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::{
+    session::{Outcome, Session},
+    transport::Tls,
+};
+
+/// Exponents of the fitted growth (time ~ size^exponent) from this value on are super-linear.
+pub const SUPER_LINEAR: f64 = 1.5;
+/// Responses faster than this (at the largest size) are too noisy to judge the growth.
+const MIN_MICROS: u64 = 10_000;
+/// Smallest size of each series; sizes double from there.
+const MIN_SIZE: usize = 16;
+
+/// A command whose cost may grow with the number of its elements.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Shape {
+    /// `SEARCH` with `size` keys.
+    SearchKeys,
+    /// `LIST` with a pattern of `size` wildcards (matched by backtracking in naive
+    /// implementations).
+    ListWildcards,
+    /// `UID SEARCH UID` with a sequence set of `size` ranges.
+    SequenceSet,
+}
+
+impl Shape {
+    pub const ALL: [Self; 3] = [Self::SearchKeys, Self::ListWildcards, Self::SequenceSet];
+
+    /// The command with `size` elements (without tag and CRLF).
+    pub fn command(self, size: usize) -> String {
+        match self {
+            Self::SearchKeys => {
+                let keys = (0..size)
+                    .map(|index| format!("SUBJECT k{index}"))
+                    .collect::<Vec<_>>();
+                format!("SEARCH {}", keys.join(" "))
+            }
+            Self::ListWildcards => format!("LIST \"\" \"{}b\"", "*a".repeat(size)),
+            Self::SequenceSet => {
+                let ranges = (0..size)
+                    .map(|index| format!("{}:{}", 3 * index + 1, 3 * index + 2))
+                    .collect::<Vec<_>>();
+                format!("UID SEARCH UID {}", ranges.join(","))
+            }
+        }
+    }
+}
+
+impl FromStr for Shape {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "search_keys" => Ok(Self::SearchKeys),
+            "list_wildcards" => Ok(Self::ListWildcards),
+            "sequence_set" => Ok(Self::SequenceSet),
+            _ => Err(format!(
+                "unknown shape `{s}` (expected `search_keys`, `list_wildcards`, or `sequence_set`)"
+            )),
+        }
+    }
+}
+
+/// Fastest of the repeated responses at one size.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Measurement {
+    pub size: usize,
+    pub micros: u64,
+    pub outcome: Outcome,
+    /// The server didn't respond in time, so `micros` is only a lower bound.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub lower_bound: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Series {
+    pub shape: Shape,
+    pub measurements: Vec<Measurement>,
+    /// Exponent of the fitted growth, time ~ size^exponent (see [`exponent`]).
+    pub exponent: Option<f64>,
+    /// Why the series ended before the largest size.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stopped: Option<String>,
+}
+
+impl Series {
+    /// Whether the response time grows super-linearly, and enough to tell it from noise.
+    pub fn super_linear(&self) -> bool {
+        let slowest = self
+            .measurements
+            .iter()
+            .map(|measurement| measurement.micros)
+            .max()
+            .unwrap_or_default();
+
+        self.exponent
+            .is_some_and(|exponent| exponent >= SUPER_LINEAR)
+            && slowest >= MIN_MICROS
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Complexity {
+    pub series: Vec<Series>,
+}
+
+pub struct Options {
+    pub shapes: Vec<Shape>,
+    /// Largest size (sizes double from 16).
+    pub max_size: usize,
+    /// Measurements per size (the fastest counts).
+    pub repetitions: usize,
+    /// Upper bound for all series together.
+    pub budget: Duration,
+    /// Upper bound for each response.
+    pub timeout: Duration,
+}
+
+/// Least-squares fit of `log(time) = exponent * log(size) + c` over `(size, time)` points.
+///
+/// Needs at least three points with positive values.
+pub fn exponent(points: &[(f64, f64)]) -> Option<f64> {
+    let logs = points
+        .iter()
+        .filter(|(size, time)| *size > 0.0 && *time > 0.0)
+        .map(|(size, time)| (size.ln(), time.ln()))
+        .collect::<Vec<_>>();
+    if logs.len() < 3 {
+        return None;
+    }
+
+    let n = logs.len() as f64;
+    let mean_x = logs.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = logs.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let covariance = logs
+        .iter()
+        .map(|(x, y)| (x - mean_x) * (y - mean_y))
+        .sum::<f64>();
+    let variance = logs.iter().map(|(x, _)| (x - mean_x).powi(2)).sum::<f64>();
+
+    (variance > 0.0).then(|| covariance / variance)
+}
+
+async fn series(
+    session: &mut Session,
+    shape: Shape,
+    options: &Options,
+    deadline: Instant,
+) -> Series {
+    let mut series = Series {
+        shape,
+        measurements: Vec::new(),
+        exponent: None,
+        stopped: None,
+    };
+
+    let mut size = MIN_SIZE;
+    'sizes: while size <= options.max_size {
+        let line = shape.command(size);
+        let mut fastest: Option<Measurement> = None;
+
+        for repetition in 0..options.repetitions.max(1) {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                series.stopped = Some("time budget exhausted".into());
+                break 'sizes;
+            }
+
+            let tag = format!("C{size}.{repetition}");
+            let started = Instant::now();
+            if let Err(error) = session
+                .write_raw(format!("{tag} {line}\r\n").as_bytes())
+                .await
+            {
+                series.stopped = Some(format!("write failed: {error}"));
+                break 'sizes;
+            }
+            // Untagged data keeps `wait` going past its timeout; the budget still ends it.
+            let outcome = match tokio::time::timeout(remaining, session.wait(&tag)).await {
+                Ok(reply) => reply.outcome,
+                Err(_) => Outcome::Timeout,
+            };
+            let micros = started.elapsed().as_micros() as u64;
+
+            if outcome == Outcome::Timeout {
+                // Unless an earlier repetition answered, the time out is the most telling
+                // measurement of the series.
+                series
+                    .measurements
+                    .push(fastest.take().unwrap_or(Measurement {
+                        size,
+                        micros,
+                        outcome,
+                        lower_bound: true,
+                    }));
+                series.stopped = Some(if Instant::now() >= deadline {
+                    "time budget exhausted".into()
+                } else {
+                    format!("{size} element(s): no response within {micros} us")
+                });
+                break 'sizes;
+            }
+
+            if !matches!(outcome, Outcome::Ok | Outcome::No) {
+                // Rejected (e.g., line too long): larger sizes don't reach the expensive part.
+                series.stopped = Some(format!("{size} element(s): {outcome:?}"));
+                break 'sizes;
+            }
+
+            if fastest
+                .as_ref()
+                .is_none_or(|fastest| micros < fastest.micros)
+            {
+                fastest = Some(Measurement {
+                    size,
+                    micros,
+                    outcome,
+                    lower_bound: false,
+                });
+            }
+        }
+
+        if let Some(fastest) = fastest {
+            info!(?shape, size, micros = fastest.micros);
+            series.measurements.push(fastest);
+        }
+        size *= 2;
+    }
+
+    let points = series
+        .measurements
+        .iter()
+        .map(|measurement| (measurement.size as f64, measurement.micros as f64))
+        .collect::<Vec<_>>();
+    series.exponent = exponent(&points);
+
+    series
+}
+
+/// Measure the response time of every shape at increasing sizes (in the selected state) and
+/// fit the growth.
+///
+/// A shape ends early when the server rejects it or the budget is exhausted (a response still
+/// pending then counts as a lower bound); a broken connection is replaced for the next shape.
+pub async fn complexity(
+    host: &str,
+    tls: Tls,
+    username: &str,
+    password: &str,
+    options: &Options,
+) -> Result<Complexity, Box<dyn std::error::Error>> {
+    let deadline = Instant::now() + options.budget;
+    let mut result = Complexity { series: Vec::new() };
+
+    for shape in &options.shapes {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let connect = Session::connect_and_select(host, tls, username, password, options.timeout);
+        let Ok(session) = tokio::time::timeout(remaining, connect).await else {
+            result.series.push(Series {
+                shape: *shape,
+                measurements: Vec::new(),
+                exponent: None,
+                stopped: Some("time budget exhausted".into()),
+            });
+            continue;
+        };
+        let mut session = session?;
+        result
+            .series
+            .push(series(&mut session, *shape, options, deadline).await);
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{complexity, exponent, Options, Shape};
+    use crate::{
+        mock::{self, Config, Quirks},
+        transport::Tls,
+    };
+
+    #[test]
+    fn test_exponent() {
+        let linear = [(16.0, 160.0), (32.0, 320.0), (64.0, 640.0)];
+        assert!((exponent(&linear).unwrap() - 1.0).abs() < 1e-9);
+
+        let quadratic = [(16.0, 1.0), (32.0, 4.0), (64.0, 16.0), (128.0, 64.0)];
+        assert!((exponent(&quadratic).unwrap() - 2.0).abs() < 1e-9);
+
+        assert_eq!(exponent(&[(16.0, 1.0), (32.0, 2.0)]), None);
+        assert_eq!(exponent(&[(16.0, 1.0), (16.0, 2.0), (16.0, 3.0)]), None);
+    }
+
+    #[test]
+    fn test_command() {
+        assert_eq!(Shape::SearchKeys.command(2), "SEARCH SUBJECT k0 SUBJECT k1");
+        assert_eq!(Shape::ListWildcards.command(2), "LIST \"\" \"*a*ab\"");
+        assert_eq!(Shape::SequenceSet.command(2), "UID SEARCH UID 1:2,4:5");
+    }
+
+    #[tokio::test]
+    async fn test_complexity() {
+        let options = Options {
+            shapes: vec![Shape::SearchKeys],
+            max_size: 256,
+            repetitions: 1,
+            budget: Duration::from_secs(30),
+            timeout: Duration::from_secs(5),
+        };
+
+        let host = mock::spawn(Config::default()).await;
+        let result = complexity(&host, Tls::None, "alice", "password", &options)
+            .await
+            .unwrap();
+        assert_eq!(result.series[0].measurements.len(), 5);
+        assert!(!result.series[0].super_linear());
+
+        let host = mock::spawn(Config {
+            quirks: Quirks {
+                quadratic_delay: Duration::from_micros(1),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .await;
+        let result = complexity(&host, Tls::None, "alice", "password", &options)
+            .await
+            .unwrap();
+        assert!(result.series[0].super_linear());
+
+        // Lines longer than the server accepts end the series.
+        let host = mock::spawn(Config {
+            max_command: 1024,
+            ..Default::default()
+        })
+        .await;
+        let result = complexity(&host, Tls::None, "alice", "password", &options)
+            .await
+            .unwrap();
+        assert!(result.series[0].stopped.is_some());
+
+        assert!(complexity(&host, Tls::None, "alice", "wrong", &options)
+            .await
+            .is_err());
+
+        // The budget ends a pending response and skips the remaining shapes.
+        let host = mock::spawn(Config {
+            quirks: Quirks {
+                quadratic_delay: Duration::from_millis(1),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .await;
+        let started = Instant::now();
+        let result = complexity(
+            &host,
+            Tls::None,
+            "alice",
+            "password",
+            &Options {
+                shapes: vec![Shape::SearchKeys, Shape::SequenceSet],
+                budget: Duration::from_millis(100),
+                ..options
+            },
+        )
+        .await
+        .unwrap();
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert!(result.series[0].measurements[0].lower_bound);
+        assert!(result.series[1].measurements.is_empty());
+        for series in &result.series {
+            assert_eq!(series.stopped.as_deref(), Some("time budget exhausted"));
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use std::{collections::HashMap, error::Error, path::Path};
+
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::{
+    probe::{Guard, Target},
+    scan,
+    targets::{Entry, Source},
+};
+
+/// Configuration file (TOML), e.g.,
+///
+/// ```toml
+/// timeout = 10
+/// parallel = 8
+/// probes = ["info", "max_tag", "max_literal"]
+/// allow = ["*.staging.example.org"]
+///
+/// [[targets]]
+/// host = "imap.example.org:143"
+/// tls = "starttls"
+/// credentials = "monitoring"
+///
+/// [credentials.monitoring]
+/// username = "monitor"
+/// password_env = "MONITORING_PASSWORD"
+///
+/// [parameters.max_literal]
+/// max = 1048576
+/// ```
+///
+/// Everything is optional. Command line flags take precedence.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Seconds to wait for a response.
+    pub timeout: Option<u64>,
+    /// Targets to scan at the same time.
+    pub parallel: Option<usize>,
+    /// Also run dangerous probes.
+    #[serde(default)]
+    pub dangerous: bool,
+    /// Probes to scan instead of the full profile.
+    pub probes: Option<Vec<String>>,
+    /// Targets dangerous probes may run against (see [`Guard`]).
+    #[serde(default)]
+    pub allow: Vec<String>,
+    #[serde(default)]
+    pub targets: Vec<Entry>,
+    /// Sources of credentials by name.
+    #[serde(default)]
+    pub credentials: HashMap<String, Source>,
+    /// Parameters by probe.
+    #[serde(default)]
+    pub parameters: HashMap<String, Map<String, Value>>,
+}
+
+impl Config {
+    pub fn parse(data: &str) -> Result<Self, Box<dyn Error>> {
+        Ok(toml::from_str(data)?)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        Self::parse(&std::fs::read_to_string(path)?)
+            .map_err(|error| format!("{}: {error}", path.display()).into())
+    }
+
+    /// Configured entry of `host`, or one without TLS and credentials.
+    pub fn entry(&self, host: &str) -> Entry {
+        self.targets
+            .iter()
+            .find(|entry| entry.host == host)
+            .cloned()
+            .unwrap_or_else(|| Entry::new(host))
+    }
+
+    /// Target `host` as configured, with `username` and `password` instead of the configured
+    /// credentials (if given).
+    pub fn target(
+        &self,
+        host: &str,
+        username: Option<String>,
+        password: Option<String>,
+    ) -> Result<Target, Box<dyn Error>> {
+        let entry = self.entry(host);
+
+        match username {
+            Some(_) => Ok(Target {
+                host: host.to_string(),
+                username,
+                password,
+                tls: entry.tls,
+            }),
+            None => Ok(entry.target(&self.credentials)?),
+        }
+    }
+
+    /// Configured parameters of `probe`.
+    pub fn parameters(&self, probe: &str) -> Map<String, Value> {
+        self.parameters.get(probe).cloned().unwrap_or_default()
+    }
+
+    /// Guard with the allowlist of the file. The confirmation only comes from the command line.
+    pub fn guard(&self, understood: bool) -> Guard {
+        Guard {
+            understood,
+            allowlist: self.allow.clone(),
+        }
+    }
+
+    /// Scan options, before command line flags.
+    pub fn options(&self) -> scan::Options {
+        let default = scan::Options::default();
+
+        scan::Options {
+            dangerous: self.dangerous,
+            timeout: self.timeout.unwrap_or(default.timeout),
+            probes: self.probes.clone().unwrap_or_default(),
+            parallel: self.parallel.unwrap_or(default.parallel),
+            credentials: self.credentials.clone(),
+            parameters: self.parameters.clone(),
+            guard: self.guard(false),
+            supervisor: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::Config;
+    use crate::transport::Tls;
+
+    #[test]
+    fn test_parse() {
+        let config = Config::parse(
+            r#"
+            timeout = 10
+            probes = ["info", "max_literal"]
+
+            [[targets]]
+            host = "imap.example.org:993"
+            tls = "implicit"
+            credentials = "monitoring"
+
+            [credentials.monitoring]
+            username = "monitor"
+            password_env = "MONITORING_PASSWORD"
+
+            [parameters.max_literal]
+            max = 1048576
+            "#,
+        )
+        .unwrap();
+
+        let options = config.options();
+        assert_eq!(options.timeout, 10);
+        assert_eq!(options.parallel, 4);
+        assert_eq!(options.probes, ["info", "max_literal"]);
+        assert_eq!(options.parameters["max_literal"]["max"], json!(1048576));
+
+        let entry = config.entry("imap.example.org:993");
+        assert_eq!(entry.tls, Tls::Implicit);
+        assert_eq!(entry.credentials.as_deref(), Some("monitoring"));
+        assert_eq!(config.entry("localhost:143").tls, Tls::None);
+
+        let target = config
+            .target("imap.example.org:993", Some("alice".into()), None)
+            .unwrap();
+        assert_eq!(target.tls, Tls::Implicit);
+        assert_eq!(target.username.as_deref(), Some("alice"));
+        assert_eq!(target.password, None);
+
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+        // Passwords don't belong into the file.
+        assert!(Config::parse("[credentials.monitoring]\npassword = \"secret\"").is_err());
+        assert!(Config::parse("timeout = \"10\"").is_err());
+    }
+}
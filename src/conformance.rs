@@ -0,0 +1,658 @@
+use std::{
+    borrow::Cow,
+    collections::hash_map::RandomState,
+    error::Error,
+    hash::{BuildHasher, Hasher},
+    time::Duration,
+};
+
+use imap_codec::{encode::Encoder, CommandCodec};
+use imap_next::client::Event;
+use imap_types::{
+    auth::AuthMechanism,
+    command::{Command, CommandBody},
+    core::Vec1,
+    extensions::enable::CapabilityEnable,
+    fetch::Macro,
+    flag::{Flag, StoreResponse, StoreType},
+    mailbox::Mailbox,
+    search::SearchKey,
+    secret::Secret,
+    status::StatusDataItemName,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{
+    session::{Outcome, Session},
+    transport::Tls,
+};
+
+/// Protocol state a command is sent in.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum State {
+    NotAuthenticated,
+    Authenticated,
+    Selected,
+    /// After a completed LOGOUT.
+    Logout,
+    /// While an IDLE command is in progress.
+    Idle,
+    /// Pipelined directly after LOGOUT, i.e., after the server sent its BYE.
+    Bye,
+}
+
+/// Earliest state a command is valid in (RFC 3501, section 6).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Valid {
+    Any,
+    NotAuthenticated,
+    Authenticated,
+    Selected,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Cell {
+    pub state: State,
+    pub command: String,
+    pub outcome: Outcome,
+    pub conforming: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Matrix {
+    pub cells: Vec<Cell>,
+    /// States that were not tested because no credentials were given (or the scratch mailbox
+    /// could not be created).
+    pub skipped: Vec<State>,
+    /// Mailbox the probe created for the selected state and deleted afterwards.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mailbox: Option<String>,
+}
+
+/// All commands under test.
+///
+/// APPEND is not included because its literal can't be sent while the server is not expected to
+/// process the command.
+fn commands() -> Vec<(&'static str, Valid, CommandBody<'static>)> {
+    vec![
+        ("CAPABILITY", Valid::Any, CommandBody::Capability),
+        ("NOOP", Valid::Any, CommandBody::Noop),
+        ("LOGOUT", Valid::Any, CommandBody::Logout),
+        ("STARTTLS", Valid::NotAuthenticated, CommandBody::StartTLS),
+        (
+            "AUTHENTICATE",
+            Valid::NotAuthenticated,
+            CommandBody::Authenticate {
+                mechanism: AuthMechanism::Plain,
+                initial_response: Some(Secret::new(Cow::Borrowed(
+                    b"\x00imap-sec\x00imap-sec".as_ref(),
+                ))),
+            },
+        ),
+        (
+            "LOGIN",
+            Valid::NotAuthenticated,
+            CommandBody::login("imap-sec", "imap-sec").unwrap(),
+        ),
+        (
+            "SELECT",
+            Valid::Authenticated,
+            CommandBody::Select {
+                mailbox: Mailbox::Inbox,
+            },
+        ),
+        (
+            "EXAMINE",
+            Valid::Authenticated,
+            CommandBody::Examine {
+                mailbox: Mailbox::Inbox,
+            },
+        ),
+        (
+            "CREATE",
+            Valid::Authenticated,
+            CommandBody::create("imap-sec").unwrap(),
+        ),
+        (
+            "DELETE",
+            Valid::Authenticated,
+            CommandBody::delete("imap-sec").unwrap(),
+        ),
+        (
+            "RENAME",
+            Valid::Authenticated,
+            CommandBody::rename("imap-sec", "imap-sec2").unwrap(),
+        ),
+        (
+            "SUBSCRIBE",
+            Valid::Authenticated,
+            CommandBody::subscribe("imap-sec").unwrap(),
+        ),
+        (
+            "UNSUBSCRIBE",
+            Valid::Authenticated,
+            CommandBody::unsubscribe("imap-sec").unwrap(),
+        ),
+        (
+            "LIST",
+            Valid::Authenticated,
+            CommandBody::list("", "*").unwrap(),
+        ),
+        (
+            "LSUB",
+            Valid::Authenticated,
+            CommandBody::lsub("", "*").unwrap(),
+        ),
+        (
+            "STATUS",
+            Valid::Authenticated,
+            CommandBody::status("INBOX", vec![StatusDataItemName::Messages]).unwrap(),
+        ),
+        ("CHECK", Valid::Selected, CommandBody::Check),
+        ("CLOSE", Valid::Selected, CommandBody::Close),
+        ("EXPUNGE", Valid::Selected, CommandBody::Expunge),
+        (
+            "SEARCH",
+            Valid::Selected,
+            CommandBody::search(None, SearchKey::All.into(), false),
+        ),
+        (
+            "FETCH",
+            Valid::Selected,
+            CommandBody::fetch("1", Macro::Fast, false).unwrap(),
+        ),
+        (
+            "STORE",
+            Valid::Selected,
+            CommandBody::store(
+                "1",
+                StoreType::Add,
+                StoreResponse::Silent,
+                vec![Flag::Seen],
+                false,
+            )
+            .unwrap(),
+        ),
+        (
+            "COPY",
+            Valid::Selected,
+            CommandBody::copy("1", "imap-sec", false).unwrap(),
+        ),
+        (
+            "UID SEARCH",
+            Valid::Selected,
+            CommandBody::search(None, SearchKey::All.into(), true),
+        ),
+        (
+            "UID FETCH",
+            Valid::Selected,
+            CommandBody::fetch("1", Macro::Fast, true).unwrap(),
+        ),
+        (
+            "UID STORE",
+            Valid::Selected,
+            CommandBody::store(
+                "1",
+                StoreType::Add,
+                StoreResponse::Silent,
+                vec![Flag::Seen],
+                true,
+            )
+            .unwrap(),
+        ),
+        (
+            "UID COPY",
+            Valid::Selected,
+            CommandBody::copy("1", "imap-sec", true).unwrap(),
+        ),
+    ]
+}
+
+/// Commands of extensions, tested when the server advertises their capability (first).
+fn extension_commands() -> Vec<(&'static str, &'static str, Valid, CommandBody<'static>)> {
+    vec![
+        ("IDLE", "IDLE", Valid::Authenticated, CommandBody::Idle),
+        (
+            "ENABLE",
+            "ENABLE",
+            Valid::Authenticated,
+            CommandBody::Enable {
+                capabilities: Vec1::from(CapabilityEnable::CondStore),
+            },
+        ),
+        (
+            "UNSELECT",
+            "UNSELECT",
+            Valid::Selected,
+            CommandBody::Unselect,
+        ),
+        (
+            "MOVE",
+            "MOVE",
+            Valid::Selected,
+            CommandBody::Move {
+                sequence_set: "1".try_into().unwrap(),
+                mailbox: "imap-sec".try_into().unwrap(),
+                uid: false,
+            },
+        ),
+    ]
+}
+
+/// Commands that must not be accepted in `state`.
+fn invalid_in(state: State, valid: Valid) -> bool {
+    match state {
+        State::NotAuthenticated => matches!(valid, Valid::Authenticated | Valid::Selected),
+        State::Authenticated => matches!(valid, Valid::NotAuthenticated | Valid::Selected),
+        State::Selected => valid == Valid::NotAuthenticated,
+        State::Logout | State::Bye => matches!(valid, Valid::Any | Valid::NotAuthenticated),
+        State::Idle => matches!(valid, Valid::Any | Valid::Selected),
+    }
+}
+
+/// Whether `outcome` is what RFC 3501 (and RFC 2177 for IDLE) expects in `state`.
+fn conforming(state: State, outcome: Outcome) -> bool {
+    match state {
+        // The command must be rejected and the connection kept.
+        State::NotAuthenticated | State::Authenticated | State::Selected => {
+            matches!(outcome, Outcome::No | Outcome::Bad)
+        }
+        // The command must not be processed at all.
+        State::Logout | State::Bye => !matches!(outcome, Outcome::Ok | Outcome::No | Outcome::Bad),
+        // Anything but DONE terminates (or breaks) the IDLE command.
+        State::Idle => outcome != Outcome::Ok,
+    }
+}
+
+fn encode(tag: &str, body: CommandBody<'static>) -> Vec<u8> {
+    let command = Command::new(tag, body).unwrap();
+    CommandCodec::default().encode(&command).dump()
+}
+
+/// Name of a mailbox that doesn't exist yet.
+fn scratch_mailbox() -> String {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u8(0);
+    format!("imap-sec-{:016x}", hasher.finish())
+}
+
+/// CREATE (or DELETE) `mailbox` on its own connection.
+async fn manage(
+    host: &str,
+    tls: Tls,
+    (username, password): (&str, &str),
+    mailbox: &str,
+    create: bool,
+    timeout: Duration,
+) -> Result<(), Box<dyn Error>> {
+    let body = if create {
+        CommandBody::create(mailbox.to_string())?
+    } else {
+        CommandBody::delete(mailbox.to_string())?
+    };
+
+    let mut session = Session::connect_and_login(host, tls, username, password, timeout).await?;
+    let reply = session.command("M", body).await;
+    session.command("Z", CommandBody::Logout).await;
+
+    match reply.outcome {
+        Outcome::Ok => Ok(()),
+        _ => Err(reply.line().into()),
+    }
+}
+
+/// Enter `state`. The selected state (and IDLE) is entered on the scratch `mailbox`.
+async fn enter(
+    host: &str,
+    tls: Tls,
+    state: State,
+    credentials: Option<(&str, &str)>,
+    mailbox: Option<&str>,
+    timeout: Duration,
+) -> Result<Session, Box<dyn Error>> {
+    match state {
+        State::NotAuthenticated | State::Bye => Ok(Session::connect(host, tls, timeout).await?.0),
+        State::Logout => {
+            let (mut session, _) = Session::connect(host, tls, timeout).await?;
+            session.command("Z", CommandBody::Logout).await;
+            Ok(session)
+        }
+        State::Authenticated | State::Selected | State::Idle => {
+            let (username, password) = credentials.ok_or("credentials required")?;
+            let mut session =
+                Session::connect_and_login(host, tls, username, password, timeout).await?;
+
+            if state == State::Authenticated {
+                return Ok(session);
+            }
+
+            let mailbox = mailbox.ok_or("scratch mailbox required")?;
+            let reply = session
+                .command(
+                    "S",
+                    CommandBody::Select {
+                        mailbox: Mailbox::try_from(mailbox.to_string())?,
+                    },
+                )
+                .await;
+            if reply.outcome != Outcome::Ok {
+                return Err(format!("SELECT failed ({:?})", reply.outcome).into());
+            }
+
+            if state == State::Selected {
+                return Ok(session);
+            }
+
+            session.write_raw(b"I IDLE\r\n").await?;
+            loop {
+                match session.next().await? {
+                    Some(Event::ContinuationRequestReceived { .. }) => break,
+                    Some(event) => warn!(?event, "unexpected event"),
+                    None => return Err("IDLE not accepted".into()),
+                }
+            }
+
+            Ok(session)
+        }
+    }
+}
+
+async fn probe(
+    host: &str,
+    tls: Tls,
+    state: State,
+    credentials: Option<(&str, &str)>,
+    mailbox: Option<&str>,
+    body: CommandBody<'static>,
+    timeout: Duration,
+) -> Result<Outcome, Box<dyn Error>> {
+    let mut session = enter(host, tls, state, credentials, mailbox, timeout).await?;
+
+    let outcome = match state {
+        State::NotAuthenticated | State::Authenticated | State::Selected => {
+            session.command("T", body).await.outcome
+        }
+        State::Logout | State::Idle => match session.write_raw(&encode("T", body)).await {
+            Ok(()) => session.wait("T").await.outcome,
+            Err(_) => Outcome::Closed,
+        },
+        State::Bye => {
+            let mut data = encode("Z", CommandBody::Logout);
+            data.extend(encode("T", body));
+            session.write_raw(&data).await?;
+            session.wait("Z").await;
+            session.wait("T").await.outcome
+        }
+    };
+
+    Ok(outcome)
+}
+
+/// Check that the server enforces the IMAP state machine.
+///
+/// Commands of extensions are included when their capability is in `extensions`. Every cell
+/// uses a fresh connection so that a wrongly accepted command can't influence the next one.
+///
+/// The selected state (and IDLE) is entered on a mailbox the probe creates and deletes, so
+/// that commands a server wrongly executes (e.g., EXPUNGE or MOVE during IDLE) can't touch
+/// the user's mail.
+pub async fn conformance(
+    host: &str,
+    tls: Tls,
+    username: Option<&str>,
+    password: Option<&str>,
+    extensions: &[String],
+    timeout: Duration,
+) -> Matrix {
+    let credentials = username.zip(password);
+
+    let mut matrix = Matrix {
+        cells: Vec::new(),
+        skipped: Vec::new(),
+        mailbox: None,
+    };
+
+    if let Some(credentials) = credentials {
+        let mailbox = scratch_mailbox();
+        match manage(host, tls, credentials, &mailbox, true, timeout).await {
+            Ok(()) => matrix.mailbox = Some(mailbox),
+            Err(error) => warn!(mailbox, %error, "could not create scratch mailbox"),
+        }
+    }
+
+    for state in [
+        State::NotAuthenticated,
+        State::Authenticated,
+        State::Selected,
+        State::Logout,
+        State::Idle,
+        State::Bye,
+    ] {
+        let skipped = match state {
+            State::Authenticated => credentials.is_none(),
+            State::Selected | State::Idle => matrix.mailbox.is_none(),
+            _ => false,
+        };
+        if skipped {
+            matrix.skipped.push(state);
+            continue;
+        }
+
+        let advertised = extension_commands()
+            .into_iter()
+            .filter(|(capability, ..)| {
+                extensions
+                    .iter()
+                    .any(|extension| extension.eq_ignore_ascii_case(capability))
+            })
+            .map(|(_, name, valid, body)| (name, valid, body));
+
+        for (name, valid, body) in commands().into_iter().chain(advertised) {
+            if !invalid_in(state, valid) {
+                continue;
+            }
+
+            let mailbox = matrix.mailbox.as_deref();
+            let outcome = match probe(host, tls, state, credentials, mailbox, body, timeout).await {
+                Ok(outcome) => outcome,
+                Err(error) => {
+                    warn!(?state, name, %error, "could not enter state");
+                    Outcome::Error
+                }
+            };
+
+            info!(?state, name, ?outcome);
+
+            matrix.cells.push(Cell {
+                state,
+                command: name.to_string(),
+                outcome,
+                conforming: conforming(state, outcome),
+            });
+        }
+    }
+
+    if let (Some(credentials), Some(mailbox)) = (credentials, &matrix.mailbox) {
+        if let Err(error) = manage(host, tls, credentials, mailbox, false, timeout).await {
+            warn!(mailbox, %error, "could not delete scratch mailbox");
+        }
+    }
+
+    matrix
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use imap_types::command::CommandBody;
+
+    use super::{commands, conformance, extension_commands, State};
+    use crate::{
+        mock::{self, Config, Quirks},
+        session::{Outcome, Session},
+        transport::Tls,
+    };
+
+    /// Name under which [`commands`] or [`extension_commands`] tests `body` (`None` if
+    /// untested).
+    ///
+    /// Exhaustive on purpose: a new `CommandBody` variant doesn't compile until it's either
+    /// tested or listed as untested here.
+    fn tested_as(body: &CommandBody) -> Option<&'static str> {
+        let name = match body {
+            CommandBody::Capability => "CAPABILITY",
+            CommandBody::Noop => "NOOP",
+            CommandBody::Logout => "LOGOUT",
+            CommandBody::StartTLS => "STARTTLS",
+            CommandBody::Authenticate { .. } => "AUTHENTICATE",
+            CommandBody::Login { .. } => "LOGIN",
+            CommandBody::Select { .. } => "SELECT",
+            CommandBody::Examine { .. } => "EXAMINE",
+            CommandBody::Create { .. } => "CREATE",
+            CommandBody::Delete { .. } => "DELETE",
+            CommandBody::Rename { .. } => "RENAME",
+            CommandBody::Subscribe { .. } => "SUBSCRIBE",
+            CommandBody::Unsubscribe { .. } => "UNSUBSCRIBE",
+            CommandBody::List { .. } => "LIST",
+            CommandBody::Lsub { .. } => "LSUB",
+            CommandBody::Status { .. } => "STATUS",
+            CommandBody::Check => "CHECK",
+            CommandBody::Close => "CLOSE",
+            CommandBody::Expunge => "EXPUNGE",
+            CommandBody::Search { uid: false, .. } => "SEARCH",
+            CommandBody::Fetch { uid: false, .. } => "FETCH",
+            CommandBody::Store { uid: false, .. } => "STORE",
+            CommandBody::Copy { uid: false, .. } => "COPY",
+            CommandBody::Search { uid: true, .. } => "UID SEARCH",
+            CommandBody::Fetch { uid: true, .. } => "UID FETCH",
+            CommandBody::Store { uid: true, .. } => "UID STORE",
+            CommandBody::Copy { uid: true, .. } => "UID COPY",
+            CommandBody::Idle => "IDLE",
+            CommandBody::Enable { .. } => "ENABLE",
+            CommandBody::Unselect => "UNSELECT",
+            CommandBody::Move { .. } => "MOVE",
+            // See `commands`.
+            CommandBody::Append { .. } => return None,
+            // Extensions without a test (yet).
+            CommandBody::Compress { .. }
+            | CommandBody::ExpungeUid { .. }
+            | CommandBody::GetQuota { .. }
+            | CommandBody::GetQuotaRoot { .. }
+            | CommandBody::SetQuota { .. }
+            | CommandBody::Id { .. }
+            | CommandBody::Sort { .. }
+            | CommandBody::Thread { .. }
+            | CommandBody::GetMetadata { .. }
+            | CommandBody::SetMetadata { .. } => return None,
+        };
+
+        Some(name)
+    }
+
+    #[test]
+    fn test_commands() {
+        let listed = commands()
+            .into_iter()
+            .map(|(name, _, body)| (name, body))
+            .chain(
+                extension_commands()
+                    .into_iter()
+                    .map(|(name, _, _, body)| (name, body)),
+            )
+            .collect::<Vec<_>>();
+
+        for (name, body) in &listed {
+            assert_eq!(tested_as(body), Some(*name));
+        }
+        for (index, (name, _)) in listed.iter().enumerate() {
+            assert!(listed[..index].iter().all(|(other, _)| other != name));
+        }
+    }
+
+    /// Mock that follows the state machine unless `lenient` is set.
+    async fn mock(lenient: bool) -> String {
+        mock::spawn(Config {
+            quirks: Quirks {
+                ignore_state: lenient,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_conforming_server() {
+        let host = mock(false).await;
+
+        let extensions = ["IDLE", "ENABLE", "UNSELECT", "MOVE"].map(String::from);
+        let matrix = conformance(
+            &host,
+            Tls::None,
+            Some("alice"),
+            Some("password"),
+            &extensions,
+            Duration::from_millis(500),
+        )
+        .await;
+
+        assert!(matrix.skipped.is_empty());
+        assert!(matrix
+            .cells
+            .iter()
+            .any(|cell| cell.state == State::Authenticated && cell.command == "UNSELECT"));
+        for cell in matrix.cells {
+            assert!(cell.conforming, "{cell:?}");
+        }
+
+        // The scratch mailbox is gone afterwards.
+        let mailbox = matrix.mailbox.unwrap();
+        assert!(mailbox.starts_with("imap-sec-"));
+        let mut session = Session::connect_and_login(
+            &host,
+            Tls::None,
+            "alice",
+            "password",
+            Duration::from_millis(500),
+        )
+        .await
+        .unwrap();
+        let reply = session
+            .command("D", CommandBody::delete(mailbox).unwrap())
+            .await;
+        assert_eq!(reply.outcome, Outcome::No);
+    }
+
+    #[tokio::test]
+    async fn test_lenient_server() {
+        let host = mock(true).await;
+
+        let matrix = conformance(
+            &host,
+            Tls::None,
+            None,
+            None,
+            &[],
+            Duration::from_millis(500),
+        )
+        .await;
+
+        assert_eq!(
+            matrix.skipped,
+            vec![State::Authenticated, State::Selected, State::Idle]
+        );
+        assert_eq!(matrix.mailbox, None);
+        let fetch = matrix
+            .cells
+            .iter()
+            .find(|cell| cell.state == State::NotAuthenticated && cell.command == "FETCH")
+            .unwrap();
+        assert_eq!(fetch.outcome, Outcome::Ok);
+        assert!(!fetch.conforming);
+        // Not advertised, so not tested.
+        assert!(!matrix.cells.iter().any(|cell| cell.command == "MOVE"));
+    }
+}
@@ -0,0 +1,352 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{finding::Finding, report::Report};
+
+/// Where capabilities are found in the outcome of a probe.
+const CAPABILITIES: &[(&str, &str)] = &[
+    ("info", "/greeting_capability"),
+    ("info", "/pre_auth_capability"),
+    ("info", "/post_auth_capability"),
+    ("fingerprint", "/features/capabilities"),
+];
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CapabilityChange {
+    pub probe: String,
+    pub field: String,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct LimitChange {
+    /// Probe name without the `max_` prefix, e.g., `tag` or `literal`.
+    pub limit: String,
+    pub before: u64,
+    pub after: u64,
+    /// The server accepts more than before.
+    pub regression: bool,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct TagCharacterChange {
+    /// Tag characters the server reflects now, but didn't before.
+    pub accepted: Vec<u8>,
+    /// Tag characters the server reflected before, but doesn't anymore.
+    pub rejected: Vec<u8>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Diff {
+    pub capabilities: Vec<CapabilityChange>,
+    pub limits: Vec<LimitChange>,
+    pub tag_characters: TagCharacterChange,
+    pub new_findings: Vec<Finding>,
+    /// Findings with a higher severity than before.
+    pub escalated_findings: Vec<Finding>,
+    pub resolved_findings: Vec<Finding>,
+    /// Probes that are only in one of the files.
+    pub unmatched: Vec<String>,
+    pub regression: bool,
+}
+
+/// Pair reports of the same probe and target. A probe that occurs once in each file is paired
+/// even when the target changed (e.g., a new build on another port).
+fn pairs<'a>(
+    before: &'a [Report<Value>],
+    after: &'a [Report<Value>],
+) -> (Vec<(&'a Report<Value>, &'a Report<Value>)>, Vec<String>) {
+    let mut pairs = Vec::new();
+    let mut unmatched = Vec::new();
+
+    let count = |reports: &[Report<Value>], probe: &str| {
+        reports
+            .iter()
+            .filter(|report| report.probe == probe)
+            .count()
+    };
+
+    for old in before {
+        let new = after
+            .iter()
+            .find(|new| new.probe == old.probe && new.target == old.target)
+            .or_else(|| {
+                if count(before, &old.probe) == 1 && count(after, &old.probe) == 1 {
+                    after.iter().find(|new| new.probe == old.probe)
+                } else {
+                    None
+                }
+            });
+
+        match new {
+            Some(new) => pairs.push((old, new)),
+            None => unmatched.push(format!("{} ({}, before only)", old.probe, old.target)),
+        }
+    }
+
+    for new in after {
+        if !pairs.iter().any(|(_, paired)| std::ptr::eq(*paired, new)) {
+            unmatched.push(format!("{} ({}, after only)", new.probe, new.target));
+        }
+    }
+
+    (pairs, unmatched)
+}
+
+fn capabilities(outcome: &Value, pointer: &str) -> Option<Vec<String>> {
+    Some(
+        outcome
+            .pointer(pointer)?
+            .as_array()?
+            .iter()
+            .map(|capability| match capability {
+                Value::String(capability) => capability.clone(),
+                capability => capability.to_string(),
+            })
+            .collect(),
+    )
+}
+
+/// Tag characters that were reflected by the server.
+fn accepted_tag_characters(outcome: &Value) -> Option<Vec<u8>> {
+    Some(
+        outcome
+            .as_array()?
+            .iter()
+            .filter(|test| test.get(2).and_then(Value::as_str) == Some("reflected"))
+            .filter_map(|test| test.get(0)?.as_u64()?.try_into().ok())
+            .collect(),
+    )
+}
+
+/// Compare the reports of two result files.
+pub fn diff(before: &[Report<Value>], after: &[Report<Value>]) -> Diff {
+    let (pairs, unmatched) = pairs(before, after);
+    let mut diff = Diff {
+        unmatched,
+        ..Default::default()
+    };
+
+    for (old, new) in pairs {
+        for (probe, pointer) in CAPABILITIES {
+            if old.probe != *probe {
+                continue;
+            }
+
+            let (Some(old_capabilities), Some(new_capabilities)) = (
+                capabilities(&old.outcome, pointer),
+                capabilities(&new.outcome, pointer),
+            ) else {
+                continue;
+            };
+
+            let added = new_capabilities
+                .iter()
+                .filter(|capability| !old_capabilities.contains(capability))
+                .cloned()
+                .collect::<Vec<_>>();
+            let removed = old_capabilities
+                .iter()
+                .filter(|capability| !new_capabilities.contains(capability))
+                .cloned()
+                .collect::<Vec<_>>();
+
+            if !added.is_empty() || !removed.is_empty() {
+                diff.capabilities.push(CapabilityChange {
+                    probe: probe.to_string(),
+                    field: pointer.trim_start_matches('/').to_string(),
+                    added,
+                    removed,
+                });
+            }
+        }
+
+        if let (Some(before), Some(after)) = (
+            old.outcome.get("limit").and_then(Value::as_u64),
+            new.outcome.get("limit").and_then(Value::as_u64),
+        ) {
+            if before != after {
+                diff.limits.push(LimitChange {
+                    limit: old.probe.trim_start_matches("max_").to_string(),
+                    before,
+                    after,
+                    regression: after > before,
+                });
+            }
+        }
+
+        if let (Some(before), Some(after)) = (
+            accepted_tag_characters(&old.outcome),
+            accepted_tag_characters(&new.outcome),
+        ) {
+            let tag_characters = &mut diff.tag_characters;
+            tag_characters
+                .accepted
+                .extend(after.iter().filter(|dec| !before.contains(dec)));
+            tag_characters
+                .rejected
+                .extend(before.iter().filter(|dec| !after.contains(dec)));
+        }
+
+        for finding in &new.findings {
+            match old.findings.iter().find(|old| old.id == finding.id) {
+                None => diff.new_findings.push(finding.clone()),
+                Some(old) if finding.severity > old.severity => {
+                    diff.escalated_findings.push(finding.clone())
+                }
+                Some(_) => {}
+            }
+        }
+
+        for finding in &old.findings {
+            if !new.findings.iter().any(|new| new.id == finding.id) {
+                diff.resolved_findings.push(finding.clone());
+            }
+        }
+    }
+
+    diff.regression = diff.limits.iter().any(|limit| limit.regression)
+        || !diff.tag_characters.accepted.is_empty()
+        || !diff.new_findings.is_empty()
+        || !diff.escalated_findings.is_empty();
+
+    diff
+}
+
+impl fmt::Display for Diff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in &self.capabilities {
+            writeln!(f, "{} {}:", change.probe, change.field)?;
+            for capability in &change.added {
+                writeln!(f, "  + {capability}")?;
+            }
+            for capability in &change.removed {
+                writeln!(f, "  - {capability}")?;
+            }
+        }
+
+        for limit in &self.limits {
+            writeln!(
+                f,
+                "max {}: {} -> {}{}",
+                limit.limit,
+                limit.before,
+                limit.after,
+                if limit.regression {
+                    " (regression)"
+                } else {
+                    ""
+                }
+            )?;
+        }
+
+        for (dec, change) in self
+            .tag_characters
+            .accepted
+            .iter()
+            .map(|dec| (dec, "accepted (regression)"))
+            .chain(
+                self.tag_characters
+                    .rejected
+                    .iter()
+                    .map(|dec| (dec, "rejected")),
+            )
+        {
+            writeln!(f, "tag character 0x{dec:02x}: now {change}")?;
+        }
+
+        for (findings, label) in [
+            (&self.new_findings, "new"),
+            (&self.escalated_findings, "escalated"),
+            (&self.resolved_findings, "resolved"),
+        ] {
+            for finding in findings {
+                writeln!(
+                    f,
+                    "{label} finding: [{}] {}: {}",
+                    finding.severity, finding.id, finding.title
+                )?;
+            }
+        }
+
+        for probe in &self.unmatched {
+            writeln!(f, "not compared: {probe}")?;
+        }
+
+        write!(
+            f,
+            "{}",
+            if self.regression {
+                "Regression detected"
+            } else {
+                "No regression"
+            }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::diff;
+    use crate::report::parse;
+
+    const BEFORE: &str = r#"
+        {"probe": "max_literal", "target": "a:143", "started_ms": 0, "finished_ms": 1,
+         "parameters": {}, "outcome": {"min": 0, "max": 100000, "limit": 4096, "steps": []}}
+        {"probe": "allowed_tag", "target": "a:143", "started_ms": 0, "finished_ms": 1,
+         "parameters": {}, "outcome": [[1, "\u0001", "bad"], [66, "B", "reflected"]]}
+        {"probe": "fingerprint", "target": "a:143", "started_ms": 0, "finished_ms": 1,
+         "parameters": {}, "outcome": {"features": {"capabilities": ["IMAP4rev1", "STARTTLS"]}},
+         "findings": [{"id": "old", "title": "Old", "severity": "low", "host": "a:143",
+                       "evidence": "", "remediation": ""}]}
+    "#;
+
+    const AFTER: &str = r#"[
+        {"probe": "max_literal", "target": "b:143", "started_ms": 0, "finished_ms": 1,
+         "parameters": {}, "outcome": {"min": 0, "max": 100000, "limit": 100000, "steps": []}},
+        {"probe": "allowed_tag", "target": "b:143", "started_ms": 0, "finished_ms": 1,
+         "parameters": {}, "outcome": [[1, "\u0001", "reflected"], [66, "B", "reflected"]]},
+        {"probe": "fingerprint", "target": "b:143", "started_ms": 0, "finished_ms": 1,
+         "parameters": {}, "outcome": {"features": {"capabilities": ["IMAP4rev1", "AUTH=PLAIN"]}},
+         "findings": [{"id": "new", "title": "New", "severity": "high", "host": "b:143",
+                       "evidence": "", "remediation": ""}]},
+        {"probe": "max_tag", "target": "b:143", "started_ms": 0, "finished_ms": 1,
+         "parameters": {}, "outcome": {"min": 0, "max": 10, "limit": 10, "steps": []}}
+    ]"#;
+
+    #[test]
+    fn test_diff() {
+        let before = parse(BEFORE).unwrap();
+        let after = parse(AFTER).unwrap();
+
+        let diff = diff(&before, &after);
+        assert!(diff.regression);
+
+        assert_eq!(diff.capabilities.len(), 1);
+        assert_eq!(diff.capabilities[0].added, ["AUTH=PLAIN"]);
+        assert_eq!(diff.capabilities[0].removed, ["STARTTLS"]);
+
+        assert_eq!(diff.limits.len(), 1);
+        assert_eq!(diff.limits[0].limit, "literal");
+        assert!(diff.limits[0].regression);
+
+        assert_eq!(diff.tag_characters.accepted, [1]);
+        assert!(diff.tag_characters.rejected.is_empty());
+
+        assert_eq!(diff.new_findings[0].id, "new");
+        assert_eq!(diff.resolved_findings[0].id, "old");
+        assert_eq!(diff.unmatched, ["max_tag (b:143, after only)"]);
+    }
+
+    #[test]
+    fn test_no_regression() {
+        let before = parse(BEFORE).unwrap();
+
+        let diff = diff(&before, &before);
+        assert!(!diff.regression);
+        assert!(diff.capabilities.is_empty() && diff.limits.is_empty());
+    }
+}
@@ -0,0 +1,382 @@
+use std::{
+    collections::hash_map::RandomState,
+    error::Error,
+    hash::{BuildHasher, Hasher},
+    time::{Duration, Instant},
+};
+
+use imap_types::response::Code;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{
+    session::{Outcome, Session},
+    throttle::{Mechanism, HARD_CAP},
+    transport::Tls,
+};
+
+/// Significance level for the latency comparison.
+const ALPHA: f64 = 0.01;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Sample {
+    pub username: String,
+    pub outcome: Outcome,
+    pub code: Option<Code<'static>>,
+    pub text: Option<String>,
+    pub latency_ms: f64,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Group {
+    pub samples: Vec<Sample>,
+    pub outcomes: Vec<Outcome>,
+    pub codes: Vec<Option<Code<'static>>>,
+    pub texts: Vec<Option<String>>,
+    pub mean_ms: f64,
+    pub median_ms: f64,
+}
+
+impl Group {
+    fn push(&mut self, sample: Sample) {
+        push_distinct(&mut self.outcomes, sample.outcome);
+        push_distinct(&mut self.codes, sample.code.clone());
+        push_distinct(&mut self.texts, sample.text.clone());
+        self.samples.push(sample);
+    }
+
+    fn latencies(&self) -> Vec<f64> {
+        self.samples
+            .iter()
+            .map(|sample| sample.latency_ms)
+            .collect()
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Enumeration {
+    /// Known-valid username with a wrong password.
+    pub valid: Group,
+    /// Random (nonexistent) usernames with a wrong password.
+    pub invalid: Group,
+    pub outcome_differs: bool,
+    pub code_differs: bool,
+    pub text_differs: bool,
+    /// Mann-Whitney U test over the latencies.
+    pub u: f64,
+    pub z: f64,
+    pub p_value: f64,
+    pub timing_differs: bool,
+    pub distinguishable: bool,
+    /// Why the attempts stopped before all repetitions (see [`stop`]). The reply that made them
+    /// stop is in neither group.
+    #[serde(default)]
+    pub stopped: Option<String>,
+}
+
+fn push_distinct<T: PartialEq>(items: &mut Vec<T>, item: T) {
+    if !items.contains(&item) {
+        items.push(item);
+    }
+}
+
+fn differs<T: PartialEq>(a: &[T], b: &[T]) -> bool {
+    a.iter().any(|item| !b.contains(item)) || b.iter().any(|item| !a.contains(item))
+}
+
+fn random_username() -> String {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u8(0);
+    format!("imap-sec-{:016x}", hasher.finish())
+}
+
+fn mean(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+fn median(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+
+    let middle = sorted.len() / 2;
+    if sorted.len() % 2 == 0 {
+        (sorted[middle - 1] + sorted[middle]) / 2.0
+    } else {
+        sorted[middle]
+    }
+}
+
+/// Complementary error function (Abramowitz and Stegun, 7.1.26).
+fn erfc(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.3275911 * x.abs());
+    let poly = t
+        * (0.254829592
+            + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    let erfc = poly * (-x * x).exp();
+
+    if x >= 0.0 {
+        erfc
+    } else {
+        2.0 - erfc
+    }
+}
+
+/// Two-sided Mann-Whitney U test (normal approximation, ties get average ranks).
+///
+/// Returns `(u, z, p)`.
+pub fn mann_whitney(a: &[f64], b: &[f64]) -> (f64, f64, f64) {
+    let (n1, n2) = (a.len() as f64, b.len() as f64);
+    if a.is_empty() || b.is_empty() {
+        return (0.0, 0.0, 1.0);
+    }
+
+    let mut all = a
+        .iter()
+        .map(|value| (*value, true))
+        .chain(b.iter().map(|value| (*value, false)))
+        .collect::<Vec<_>>();
+    all.sort_by(|x, y| x.0.total_cmp(&y.0));
+
+    let mut rank_sum_a = 0.0;
+    let mut tie_correction = 0.0;
+    let mut index = 0;
+    while index < all.len() {
+        let mut end = index;
+        while end + 1 < all.len() && all[end + 1].0 == all[index].0 {
+            end += 1;
+        }
+
+        let rank = (index + end) as f64 / 2.0 + 1.0;
+        let ties = (end - index + 1) as f64;
+        tie_correction += ties * ties * ties - ties;
+        rank_sum_a += rank * all[index..=end].iter().filter(|(_, in_a)| *in_a).count() as f64;
+
+        index = end + 1;
+    }
+
+    let u = rank_sum_a - n1 * (n1 + 1.0) / 2.0;
+    let n = n1 + n2;
+    let variance = n1 * n2 / 12.0 * ((n + 1.0) - tie_correction / (n * (n - 1.0)));
+    if variance <= 0.0 {
+        return (u, 0.0, 1.0);
+    }
+
+    let z = (u - n1 * n2 / 2.0) / variance.sqrt();
+    let p = erfc(z.abs() / std::f64::consts::SQRT_2);
+
+    (u, z, p)
+}
+
+/// Why to stop after `sample` of a group that started with `first`: a BYE or `[UNAVAILABLE]`
+/// means the server throttles or locked the account, and so does `[AUTHENTICATIONFAILED]` when
+/// the first failed LOGIN came without it (many servers send it with every failed LOGIN).
+fn stop(first: &Sample, sample: &Sample) -> Option<String> {
+    let reason = match (&sample.outcome, &sample.code) {
+        (Outcome::Bye, _) => "BYE",
+        (_, Some(Code::Unavailable)) => "[UNAVAILABLE]",
+        (_, Some(Code::AuthenticationFailed)) if first.code != sample.code => {
+            "[AUTHENTICATIONFAILED]"
+        }
+        _ => return None,
+    };
+
+    Some(format!("{reason} for {}", sample.username))
+}
+
+async fn attempt(
+    host: &str,
+    tls: Tls,
+    username: &str,
+    timeout: Duration,
+) -> Result<Sample, Box<dyn Error>> {
+    let (mut session, _) = Session::connect(host, tls, timeout).await?;
+
+    let started = Instant::now();
+    let reply = Mechanism::Login
+        .authenticate(&mut session, "A", username, "imap-sec-wrong", false)
+        .await?;
+    let latency = started.elapsed();
+
+    if reply.outcome == Outcome::Ok {
+        return Err("wrong password was accepted".into());
+    }
+
+    Ok(Sample {
+        username: username.to_string(),
+        outcome: reply.outcome,
+        code: reply.code,
+        text: reply.text,
+        latency_ms: latency.as_secs_f64() * 1000.0,
+    })
+}
+
+/// Check whether failed LOGINs of `username` can be told apart from failed LOGINs of nonexistent
+/// users.
+///
+/// Both groups get `repetitions` attempts (interleaved, one connection each), at most
+/// [`HARD_CAP`] together. The attempts stop early when the server appears to throttle or lock
+/// the account (see [`stop`]).
+pub async fn enumeration(
+    host: &str,
+    tls: Tls,
+    username: &str,
+    repetitions: usize,
+    timeout: Duration,
+) -> Result<Enumeration, Box<dyn Error>> {
+    if repetitions > HARD_CAP / 2 {
+        return Err(format!("repetitions must not exceed {}", HARD_CAP / 2).into());
+    }
+
+    let mut valid = Group::default();
+    let mut invalid = Group::default();
+    let mut stopped = None;
+
+    'repetitions: for repetition in 0..repetitions {
+        info!(repetition);
+        let usernames = [username.to_string(), random_username()];
+
+        for (group, username) in [&mut valid, &mut invalid].into_iter().zip(usernames) {
+            let sample = attempt(host, tls, &username, timeout).await?;
+
+            // The sample that made us stop stays out of the comparison: a lockout only hits the
+            // known-valid user.
+            stopped = stop(group.samples.first().unwrap_or(&sample), &sample);
+            if let Some(reason) = &stopped {
+                warn!(%reason, "stopping early");
+                break 'repetitions;
+            }
+
+            group.push(sample);
+        }
+    }
+
+    for group in [&mut valid, &mut invalid] {
+        let latencies = group.latencies();
+        group.mean_ms = mean(&latencies);
+        group.median_ms = median(&latencies);
+    }
+
+    let (u, z, p_value) = mann_whitney(&valid.latencies(), &invalid.latencies());
+
+    let outcome_differs = differs(&valid.outcomes, &invalid.outcomes);
+    let code_differs = differs(&valid.codes, &invalid.codes);
+    // Texts might contain the username, so the nonexistent users could all differ from each other.
+    let text_differs = invalid.texts.len() == 1 && differs(&valid.texts, &invalid.texts);
+    let timing_differs = p_value < ALPHA;
+
+    Ok(Enumeration {
+        valid,
+        invalid,
+        outcome_differs,
+        code_differs,
+        text_differs,
+        u,
+        z,
+        p_value,
+        timing_differs,
+        distinguishable: outcome_differs || code_differs || text_differs || timing_differs,
+        stopped,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{enumeration, mann_whitney, median};
+    use crate::{
+        mock::{self, Config, Quirks},
+        throttle::HARD_CAP,
+        transport::Tls,
+    };
+
+    #[test]
+    fn test_median() {
+        assert_eq!(median(&[]), 0.0);
+        assert_eq!(median(&[3.0, 1.0, 2.0]), 2.0);
+        assert_eq!(median(&[4.0, 1.0, 3.0, 2.0]), 2.5);
+    }
+
+    #[test]
+    fn test_mann_whitney() {
+        // Identical samples are indistinguishable.
+        let same = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0];
+        let (_, z, p) = mann_whitney(&same, &same);
+        assert_eq!(z, 0.0);
+        assert!(p > 0.99);
+
+        // Fully separated samples are distinguishable.
+        let fast = [1.0, 1.1, 1.2, 1.3, 1.4, 1.5, 1.6, 1.7, 1.8, 1.9];
+        let slow = [5.0, 5.1, 5.2, 5.3, 5.4, 5.5, 5.6, 5.7, 5.8, 5.9];
+        let (u, z, p) = mann_whitney(&fast, &slow);
+        assert_eq!(u, 0.0);
+        assert!(z < 0.0);
+        assert!(p < 0.001);
+
+        // Constant samples have no variance.
+        let (_, _, p) = mann_whitney(&[1.0, 1.0], &[1.0, 1.0]);
+        assert_eq!(p, 1.0);
+    }
+
+    #[tokio::test]
+    async fn test_enumeration() {
+        let timeout = Duration::from_secs(5);
+
+        let host = mock::spawn(Config::default()).await;
+        let result = enumeration(&host, Tls::None, "alice", 10, timeout)
+            .await
+            .unwrap();
+        assert!(!result.outcome_differs);
+        assert!(!result.code_differs);
+        assert!(!result.text_differs);
+
+        let host = mock::spawn(Config {
+            quirks: Quirks {
+                reveal_unknown_users: true,
+                known_user_delay: Duration::from_millis(20),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .await;
+        let result = enumeration(&host, Tls::None, "alice", 10, timeout)
+            .await
+            .unwrap();
+        assert_eq!(result.invalid.texts, [Some("unknown user".into())]);
+        assert!(result.text_differs);
+        assert!(result.timing_differs);
+        assert!(result.distinguishable);
+        assert_eq!(result.stopped, None);
+
+        // The known-valid user is locked after two failures, so the third attempt stops.
+        let host = mock::spawn(Config {
+            quirks: Quirks {
+                lockout_after: Some(2),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .await;
+        let result = enumeration(&host, Tls::None, "alice", 10, timeout)
+            .await
+            .unwrap();
+        assert_eq!(result.stopped.as_deref(), Some("[UNAVAILABLE] for alice"));
+        assert_eq!(result.valid.samples.len(), 2);
+        assert_eq!(result.invalid.samples.len(), 2);
+        assert!(!result.code_differs);
+
+        assert!(
+            enumeration(&host, Tls::None, "alice", HARD_CAP / 2 + 1, timeout)
+                .await
+                .is_err()
+        );
+    }
+}
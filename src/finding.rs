@@ -0,0 +1,793 @@
+use std::{collections::BTreeMap, fmt};
+
+use imap_types::utils::escape_byte_string;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    complexity::Complexity,
+    conformance::Matrix,
+    enumeration::Enumeration,
+    exploit::Oom,
+    fingerprint::Fingerprint,
+    learn::{AllowedResult, InfoSimple, Limit, NonSyncLiteral, LITERAL_MINUS_MAX},
+    replay::Replay,
+    supervisor::Usage,
+    throttle::Throttle,
+    timeline::{Phase, Source},
+    transcript,
+};
+
+/// Pre-auth literals of this size (or larger) are a medium risk.
+const LITERAL_MEDIUM: u64 = 64 * 1024 * 1024;
+/// Pre-auth literals of this size (or larger) are a high risk.
+const LITERAL_HIGH: u64 = 1024 * 1024 * 1024;
+/// Tags of this length (or longer) are a low risk.
+const TAG_LOW: u64 = 8 * 1024;
+/// Tags of this length (or longer) are a medium risk.
+const TAG_MEDIUM: u64 = 1024 * 1024;
+/// SEARCH literals of this total size (or larger) are a high risk.
+const OOM_HIGH: u64 = 64 * 1024 * 1024;
+/// Servers retaining this many bytes per client byte (or more) are a medium risk.
+const AMPLIFICATION_MEDIUM: f64 = 10.0;
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Info,
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Info => "INFO",
+            Self::Low => "LOW",
+            Self::Medium => "MEDIUM",
+            Self::High => "HIGH",
+            Self::Critical => "CRITICAL",
+        })
+    }
+}
+
+/// An interpreted result of a probe.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Finding {
+    /// Stable identifier, e.g., `preauth-literal`.
+    pub id: String,
+    pub title: String,
+    pub severity: Severity,
+    pub host: String,
+    /// What shows the issue: raw excerpts of recorded exchanges (`C:` client, `S:` server), and
+    /// measurements (`Summary:` when they stand in for an exchange that wasn't recorded).
+    ///
+    /// May contain control characters, so render each line through [`escape_byte_string`].
+    pub evidence: String,
+    pub remediation: String,
+}
+
+impl Finding {
+    fn new(id: &str, title: &str, severity: Severity, host: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            title: title.to_string(),
+            severity,
+            host: host.to_string(),
+            evidence: String::new(),
+            remediation: String::new(),
+        }
+    }
+
+    fn evidence(mut self, evidence: impl Into<String>) -> Self {
+        self.evidence = evidence.into();
+        self
+    }
+
+    fn remediation(mut self, remediation: impl Into<String>) -> Self {
+        self.remediation = remediation.into();
+        self
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{}] {}: {} ({})",
+            self.severity, self.id, self.title, self.host
+        )?;
+        for line in self.evidence.lines() {
+            write!(f, "\n    {}", escape_byte_string(line))?;
+        }
+        if !self.remediation.is_empty() {
+            write!(f, "\n  Remediation: {}", self.remediation)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Number of findings per severity.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Summary {
+    pub total: usize,
+    pub highest: Option<Severity>,
+    pub by_severity: BTreeMap<Severity, usize>,
+}
+
+impl Summary {
+    pub fn new(findings: &[Finding]) -> Self {
+        let mut summary = Self {
+            total: findings.len(),
+            highest: findings.iter().map(|finding| finding.severity).max(),
+            by_severity: BTreeMap::new(),
+        };
+
+        for finding in findings {
+            *summary.by_severity.entry(finding.severity).or_default() += 1;
+        }
+
+        summary
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} finding(s)", self.total)?;
+
+        let counts = self
+            .by_severity
+            .iter()
+            .rev()
+            .map(|(severity, count)| format!("{count} {}", severity.to_string().to_lowercase()))
+            .collect::<Vec<_>>();
+        if !counts.is_empty() {
+            write!(f, ": {}", counts.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Recorded bisection steps around the limit (the largest accepted and the smallest rejected
+/// value), with `command` rendering what was sent for a value.
+fn bisection(limit: &Limit, command: impl Fn(u64) -> String) -> Vec<String> {
+    let accepted = limit
+        .steps
+        .iter()
+        .filter(|step| step.accepted)
+        .max_by_key(|step| step.tested);
+    let rejected = limit
+        .steps
+        .iter()
+        .filter(|step| !step.accepted)
+        .min_by_key(|step| step.tested);
+
+    accepted
+        .into_iter()
+        .chain(rejected)
+        .map(|step| format!("C: {}\nS: {}", command(step.tested), step.response))
+        .collect()
+}
+
+/// For probes without an interpretation.
+pub fn none<T>(_: &str, _: &T) -> Vec<Finding> {
+    Vec::new()
+}
+
+pub fn max_literal(host: &str, limit: &Limit) -> Vec<Finding> {
+    let severity = match limit.limit {
+        length if length >= LITERAL_HIGH => Severity::High,
+        length if length >= LITERAL_MEDIUM => Severity::Medium,
+        _ => return Vec::new(),
+    };
+
+    let at_least = if limit.limit == limit.max {
+        " (upper bound of the search, the server may accept more)"
+    } else {
+        ""
+    };
+
+    let mut evidence = bisection(limit, |length| format!("A LOGIN {{{length}}}"));
+    evidence.push(format!(
+        "Summary: {} byte(s) accepted before authentication{at_least}",
+        limit.limit
+    ));
+
+    vec![Finding::new(
+        "preauth-literal",
+        "Pre-auth memory exhaustion risk",
+        severity,
+        host,
+    )
+    .evidence(evidence.join("\n"))
+    .remediation(
+        "Limit the size of literals before authentication (e.g., to a few kilobytes) and reject \
+         larger ones with a tagged BAD or BYE.",
+    )]
+}
+
+pub fn max_tag(host: &str, limit: &Limit) -> Vec<Finding> {
+    let severity = match limit.limit {
+        length if length >= TAG_MEDIUM => Severity::Medium,
+        length if length >= TAG_LOW => Severity::Low,
+        _ => return Vec::new(),
+    };
+
+    let mut evidence = bisection(limit, |length| {
+        format!("<tag> NOOP (<tag> is {length} times \"A\")")
+    });
+    evidence.push(format!("Summary: tags of {} byte(s) accepted", limit.limit));
+
+    vec![
+        Finding::new("preauth-tag", "Overlong tags accepted", severity, host)
+            .evidence(evidence.join("\n"))
+            .remediation("Limit the length of a command line before authentication."),
+    ]
+}
+
+/// Characters RFC 3501 does not allow in a tag.
+pub(crate) fn forbidden_in_tag(byte: u8) -> bool {
+    byte <= 0x20
+        || byte >= 0x7f
+        || matches!(byte, b'(' | b')' | b'{' | b'%' | b'*' | b'"' | b'\\' | b'+')
+}
+
+pub fn allowed_tag(host: &str, results: &[(u8, char, Option<AllowedResult>)]) -> Vec<Finding> {
+    let accepted = results
+        .iter()
+        .filter(|(dec, _, result)| {
+            forbidden_in_tag(*dec) && matches!(result, Some(AllowedResult::Reflected))
+        })
+        .map(|(dec, _, _)| *dec)
+        .collect::<Vec<_>>();
+
+    if accepted.is_empty() {
+        return Vec::new();
+    }
+
+    let control = accepted.iter().any(|dec| *dec < 0x20 || *dec == 0x7f);
+
+    vec![Finding::new(
+        "tag-characters",
+        "Tags with forbidden characters are accepted",
+        if control {
+            Severity::Medium
+        } else {
+            Severity::Low
+        },
+        host,
+    )
+    .evidence(format!(
+        "Summary: tags \"A\" followed by these bytes were answered with the same tag: {}",
+        accepted
+            .iter()
+            .map(|dec| format!("0x{dec:02x}"))
+            .collect::<Vec<_>>()
+            .join(" ")
+    ))
+    .remediation(
+        "Reject tags that contain control characters, 8-bit bytes, or atom-specials to avoid \
+         response injection and parser confusion.",
+    )]
+}
+
+/// Findings about non-synchronizing literals, given the advertised `capability` (`LITERAL+` or
+/// `LITERAL-`).
+pub fn non_sync_literal(host: &str, capability: &str, results: &[NonSyncLiteral]) -> Vec<Finding> {
+    let minus = capability.eq_ignore_ascii_case("LITERAL-");
+    let evidence = |result: &NonSyncLiteral| {
+        format!("C: A LOGIN {{{}+}}\nS: {}", result.length, result.response)
+    };
+    let mut findings = Vec::new();
+
+    if let Some(rejected) = results
+        .iter()
+        .find(|result| result.length <= LITERAL_MINUS_MAX && !result.accepted)
+    {
+        findings.push(
+            Finding::new(
+                "non-sync-literal-rejected",
+                "Advertised non-synchronizing literals are rejected",
+                Severity::Low,
+                host,
+            )
+            .evidence(evidence(rejected))
+            .remediation(format!(
+                "Accept non-synchronizing literals, or don't advertise {capability}."
+            )),
+        );
+    }
+
+    if let Some(accepted) = results
+        .iter()
+        .find(|result| minus && result.length > LITERAL_MINUS_MAX && result.accepted)
+    {
+        findings.push(
+            Finding::new(
+                "literal-minus-limit",
+                "Non-synchronizing literals over 4096 bytes are accepted despite LITERAL-",
+                Severity::Medium,
+                host,
+            )
+            .evidence(evidence(accepted))
+            .remediation(
+                "Reject non-synchronizing literals over 4096 bytes with a tagged BAD (RFC 7888).",
+            ),
+        );
+    }
+
+    findings
+}
+
+pub fn info(host: &str, info: &InfoSimple) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let plaintext = &info.plaintext;
+
+    if plaintext.exposed {
+        let mut evidence = Vec::new();
+        // The capabilities the assessment is based on, as received.
+        let snapshot =
+            info.timeline.snapshots.iter().find(|snapshot| {
+                snapshot.phase == Phase::PreAuth || snapshot.phase == Phase::Greeting
+            });
+        if let Some(snapshot) = snapshot {
+            let capabilities = snapshot
+                .capabilities
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(" ");
+            evidence.push(match snapshot.source {
+                Source::Untagged => format!("S: * CAPABILITY {capabilities}"),
+                Source::Code => format!("S: [CAPABILITY {capabilities}]"),
+            });
+        }
+        if !plaintext.login_disabled {
+            evidence.push("Summary: LOGINDISABLED is not advertised".to_string());
+        }
+        if let (Some(true), Some(mechanism)) = (plaintext.login_accepted, plaintext.mechanism) {
+            evidence.push(format!("Summary: {mechanism} succeeded without TLS"));
+        }
+
+        findings.push(
+            Finding::new(
+                "plaintext-credentials",
+                "Credentials are accepted without TLS",
+                Severity::High,
+                host,
+            )
+            .evidence(evidence.join("\n"))
+            .remediation(if plaintext.starttls {
+                "Advertise LOGINDISABLED and no AUTH=PLAIN until STARTTLS completed."
+            } else {
+                "Offer STARTTLS (or implicit TLS) and disable authentication without it."
+            }),
+        );
+    }
+
+    let inconsistent = info
+        .timeline
+        .changes
+        .iter()
+        .filter(|change| change.conflict || !change.unexpected.is_empty())
+        .collect::<Vec<_>>();
+
+    if !inconsistent.is_empty() {
+        findings.push(
+            Finding::new(
+                "capability-inconsistency",
+                "Capabilities change unexpectedly between session states",
+                Severity::Low,
+                host,
+            )
+            .evidence(
+                inconsistent
+                    .iter()
+                    .map(|change| {
+                        let unexpected = change
+                            .unexpected
+                            .iter()
+                            .map(ToString::to_string)
+                            .collect::<Vec<_>>();
+                        format!(
+                            "Summary: {:?} -> {:?}: {}{}",
+                            change.from,
+                            change.to,
+                            unexpected.join(" "),
+                            if change.conflict { " (conflict)" } else { "" }
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            )
+            .remediation("Advertise the same capabilities for the same session state."),
+        );
+    }
+
+    findings
+}
+
+pub fn oom(host: &str, oom: &Oom) -> Vec<Finding> {
+    if oom.bytes_accepted < OOM_HIGH {
+        return Vec::new();
+    }
+
+    let size = oom
+        .chunks
+        .first()
+        .map(|chunk| chunk.size)
+        .unwrap_or_default();
+
+    let mut evidence = Vec::new();
+    // Every chunk was sent after a continuation request.
+    if !oom.chunks.is_empty() {
+        evidence.push(format!("C: A2 SEARCH BODY {{{size}}}\nS: +"));
+    }
+    evidence.push(format!(
+        "Summary: {} chunk(s), {} byte(s) accepted, stopped: {}",
+        oom.chunks.len(),
+        oom.bytes_accepted,
+        oom.stopped
+    ));
+
+    vec![Finding::new(
+        "search-literal",
+        "Memory exhaustion through SEARCH literals",
+        Severity::High,
+        host,
+    )
+    .evidence(evidence.join("\n"))
+    .remediation("Limit the total size of a command, including all of its literals.")]
+}
+
+/// Findings about the local server process that was watched while `probe` ran.
+pub fn server(host: &str, probe: &str, usage: &Usage) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    if let Some(exit) = &usage.exit {
+        findings.push(
+            Finding::new(
+                "server-crash",
+                "Server process exited during a probe",
+                Severity::High,
+                host,
+            )
+            .evidence(format!(
+                "probe: {probe}\nexit: {exit}\npeak memory: {} byte(s)",
+                usage.peak_rss
+            ))
+            .remediation("Find the cause in the server log; clients must not be able to crash it."),
+        );
+    }
+
+    if let Some(amplification) = usage
+        .amplification
+        .filter(|amplification| *amplification >= AMPLIFICATION_MEDIUM)
+    {
+        findings.push(
+            Finding::new(
+                "memory-amplification",
+                "Server retains much more memory than the client sends",
+                Severity::Medium,
+                host,
+            )
+            .evidence(format!(
+                "probe: {probe}\nmemory: {} -> {} byte(s) ({amplification:.1} byte(s) per client byte)",
+                usage.before.rss, usage.peak_rss
+            ))
+            .remediation("Bound the memory a single command or connection can make the server allocate."),
+        );
+    }
+
+    findings
+}
+
+pub fn complexity(host: &str, complexity: &Complexity) -> Vec<Finding> {
+    complexity
+        .series
+        .iter()
+        .filter(|series| series.super_linear())
+        .map(|series| {
+            let measurements = series
+                .measurements
+                .iter()
+                .map(|measurement| {
+                    format!(
+                        "{} element(s): {}{} us",
+                        measurement.size,
+                        if measurement.lower_bound { ">= " } else { "" },
+                        measurement.micros
+                    )
+                })
+                .collect::<Vec<_>>();
+
+            Finding::new(
+                "algorithmic-complexity",
+                "Response time grows super-linearly with command size",
+                Severity::Medium,
+                host,
+            )
+            .evidence(format!(
+                "Summary: time ~ size^{:.2} for commands like {}\n{}",
+                series.exponent.unwrap_or_default(),
+                series.shape.command(2),
+                measurements.join("\n")
+            ))
+            .remediation(
+                "Bound the number of elements per command, or process them in linear time.",
+            )
+        })
+        .collect()
+}
+
+pub fn conformance(host: &str, matrix: &Matrix) -> Vec<Finding> {
+    let violations = matrix
+        .cells
+        .iter()
+        .filter(|cell| !cell.conforming)
+        .collect::<Vec<_>>();
+
+    if violations.is_empty() {
+        return Vec::new();
+    }
+
+    vec![Finding::new(
+        "state-machine",
+        "Commands are accepted in the wrong session state",
+        Severity::Medium,
+        host,
+    )
+    .evidence(
+        violations
+            .iter()
+            .map(|cell| {
+                format!(
+                    "{:?}: {} answered with {:?}",
+                    cell.state, cell.command, cell.outcome
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+    )
+    .remediation("Reject commands that are not valid in the current state with a tagged BAD.")]
+}
+
+pub fn auth_throttle(host: &str, throttle: &Throttle) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    let closed = throttle.attempts_per_connection.len() > 1;
+    if throttle.delayed_from.is_none() && !closed && throttle.locked_out != Some(true) {
+        findings.push(
+            Finding::new(
+                "auth-throttling",
+                "Failed authentication attempts are not throttled",
+                Severity::Medium,
+                host,
+            )
+            .evidence(format!(
+                "Summary: {} failed {} attempt(s) for {} on one connection without delay or lockout",
+                throttle.attempts.len(),
+                throttle.mechanism,
+                throttle.username
+            ))
+            .remediation(
+                "Delay responses after failed attempts and close the connection after a few.",
+            ),
+        );
+    }
+
+    if throttle.locked_out == Some(true) {
+        findings.push(
+            Finding::new(
+                "account-lockout",
+                "Failed attempts lock the account",
+                Severity::Low,
+                host,
+            )
+            .evidence(format!(
+                "Summary: {} with the correct password for {} failed after {} failed attempt(s)",
+                throttle.mechanism,
+                throttle.username,
+                throttle.attempts.len()
+            ))
+            .remediation(
+                "Prefer throttling by client address over locking accounts, which lets anyone \
+                 lock out a user.",
+            ),
+        );
+    }
+
+    findings
+}
+
+pub fn enumeration(host: &str, enumeration: &Enumeration) -> Vec<Finding> {
+    if !enumeration.distinguishable {
+        return Vec::new();
+    }
+
+    let mut evidence = Vec::new();
+    if enumeration.outcome_differs {
+        evidence.push(format!(
+            "outcomes: {:?} (valid) vs. {:?} (invalid)",
+            enumeration.valid.outcomes, enumeration.invalid.outcomes
+        ));
+    }
+    if enumeration.code_differs {
+        evidence.push(format!(
+            "codes: {:?} (valid) vs. {:?} (invalid)",
+            enumeration.valid.codes, enumeration.invalid.codes
+        ));
+    }
+    if enumeration.text_differs {
+        evidence.push(format!(
+            "texts: {:?} (valid) vs. {:?} (invalid)",
+            enumeration.valid.texts, enumeration.invalid.texts
+        ));
+    }
+    if enumeration.timing_differs {
+        evidence.push(format!(
+            "median latency: {:.1} ms (valid) vs. {:.1} ms (invalid), p = {:.4}",
+            enumeration.valid.median_ms, enumeration.invalid.median_ms, enumeration.p_value
+        ));
+    }
+
+    vec![Finding::new(
+        "username-enumeration",
+        "Existing usernames can be told apart",
+        Severity::Medium,
+        host,
+    )
+    .evidence(evidence.join("\n"))
+    .remediation(
+        "Answer failed logins identically (outcome, code, text, and timing) whether or not the \
+         user exists.",
+    )]
+}
+
+pub fn fingerprint(host: &str, fingerprint: &Fingerprint) -> Vec<Finding> {
+    let Some(best) = &fingerprint.best else {
+        return Vec::new();
+    };
+
+    fingerprint
+        .advisories
+        .iter()
+        .map(|advisory| {
+            Finding::new(
+                &advisory.id,
+                &advisory.title,
+                if advisory.certain {
+                    Severity::High
+                } else {
+                    Severity::Medium
+                },
+                host,
+            )
+            .evidence(format!(
+                "Identified as {} {} (confidence {:.2}): {}{}",
+                best.server,
+                best.versions,
+                best.confidence,
+                best.matched.join(", "),
+                if advisory.certain {
+                    ""
+                } else {
+                    "\nThe version could not be determined."
+                }
+            ))
+            .remediation(match &advisory.url {
+                Some(url) => format!("Update the server, see {url}."),
+                None => "Update the server.".to_string(),
+            })
+        })
+        .collect()
+}
+
+pub fn replay(host: &str, replay: &Replay) -> Vec<Finding> {
+    // Exchanges are escaped, but evidence is raw.
+    let raw = |data: &str| {
+        String::from_utf8_lossy(&transcript::unescape(data).unwrap_or_default()).into_owned()
+    };
+    let mut evidence = Vec::new();
+
+    for connection in &replay.connections {
+        if let Some(error) = &connection.error {
+            evidence.push(format!("connection {}: {error}", connection.connection));
+        }
+
+        for exchange in connection
+            .exchanges
+            .iter()
+            .filter(|exchange| !exchange.matches)
+        {
+            evidence.push(format!(
+                "connection {}:\nC: {}\nS (recorded): {}\nS (now): {}{}",
+                connection.connection,
+                raw(&exchange.sent),
+                raw(&exchange.expected),
+                raw(&exchange.received),
+                match (exchange.expected_close, exchange.closed) {
+                    (true, false) => " (not closed)",
+                    (false, true) => " (closed)",
+                    _ => "",
+                }
+            ));
+        }
+    }
+
+    if evidence.is_empty() {
+        return Vec::new();
+    }
+
+    vec![Finding::new(
+        "replay-mismatch",
+        "Server responses differ from the transcript",
+        Severity::Info,
+        host,
+    )
+    .evidence(evidence.join("\n"))
+    .remediation("Check whether the difference is expected (e.g., a fixed issue) or a regression.")]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{max_literal, Severity, Summary};
+    use crate::learn::{Limit, Step};
+
+    fn limit(limit: u64) -> Limit {
+        Limit {
+            min: 0,
+            max: u64::from(u32::MAX),
+            limit,
+            steps: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_max_literal() {
+        assert!(max_literal("localhost:143", &limit(4096)).is_empty());
+
+        let findings = max_literal("localhost:143", &limit(100 * 1024 * 1024));
+        assert_eq!(findings[0].severity, Severity::Medium);
+
+        let findings = max_literal("localhost:143", &limit(u64::from(u32::MAX)));
+        assert_eq!(findings[0].id, "preauth-literal");
+        assert_eq!(findings[0].severity, Severity::High);
+        assert!(findings[0].evidence.contains("may accept more"));
+
+        // Evidence quotes the recorded steps around the limit.
+        let mut limit = limit(100 * 1024 * 1024);
+        limit.steps = [(200, false, "* BAD literal too long"), (100, true, "+")]
+            .map(|(megabytes, accepted, response)| Step {
+                tested: megabytes * 1024 * 1024,
+                accepted,
+                response: response.into(),
+            })
+            .into();
+        let findings = max_literal("localhost:143", &limit);
+        assert_eq!(
+            findings[0].evidence.lines().take(4).collect::<Vec<_>>(),
+            [
+                "C: A LOGIN {104857600}",
+                "S: +",
+                "C: A LOGIN {209715200}",
+                "S: * BAD literal too long"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_summary() {
+        let mut findings = max_literal("a", &limit(u64::from(u32::MAX)));
+        findings.extend(max_literal("b", &limit(100 * 1024 * 1024)));
+        findings.extend(max_literal("c", &limit(u64::from(u32::MAX))));
+
+        let summary = Summary::new(&findings);
+        assert_eq!(summary.total, 3);
+        assert_eq!(summary.highest, Some(Severity::High));
+        assert_eq!(summary.to_string(), "3 finding(s): 2 high, 1 medium");
+    }
+}
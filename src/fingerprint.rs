@@ -0,0 +1,363 @@
+use std::{cmp::Ordering, error::Error, fmt, time::Duration};
+
+use imap_types::{
+    command::CommandBody,
+    core::{IString, NString},
+    response::Data,
+};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::{
+    learn::{self, AllowedResult},
+    session::Session,
+    signatures::{Advisory, AdvisoryMatch},
+    transport::Tls,
+};
+
+/// Upper bound for learning the maximum tag length.
+pub const MAX_TAG: u64 = 65536;
+
+/// Everything we learned about a server that may tell implementations apart.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Features {
+    pub greeting: Option<String>,
+    /// Pre-auth capabilities in the order the server sent them.
+    pub capabilities: Vec<String>,
+    pub id: Option<Vec<(String, Option<String>)>>,
+    /// Text of the response to an unknown command.
+    pub unknown_command: Option<String>,
+    /// Tag characters that were not reflected (only with probes).
+    pub rejected_tag_characters: Option<Vec<u8>>,
+    /// Maximum tag length (only with probes).
+    pub max_tag: Option<u64>,
+}
+
+impl Features {
+    fn id_value(&self, key: &str) -> Option<&str> {
+        self.id
+            .as_ref()?
+            .iter()
+            .find(|(candidate, _)| candidate.eq_ignore_ascii_case(key))?
+            .1
+            .as_deref()
+    }
+}
+
+/// A single check over [`Features`].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Matcher {
+    GreetingContains(String),
+    CapabilityPresent(String),
+    /// Capabilities appear in this (relative) order.
+    CapabilityOrder(Vec<String>),
+    IdContains {
+        key: String,
+        value: String,
+    },
+    /// The ID `version` is within `min..=max` (compared numerically per component).
+    IdVersion {
+        min: Option<String>,
+        max: Option<String>,
+    },
+    UnknownCommandContains(String),
+    TagCharacterRejected(u8),
+    MaxTagAtLeast(u64),
+    MaxTagAtMost(u64),
+}
+
+impl fmt::Display for Matcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::GreetingContains(text) => write!(f, "greeting contains {text:?}"),
+            Self::CapabilityPresent(name) => write!(f, "capability {name}"),
+            Self::CapabilityOrder(names) => write!(f, "capability order {}", names.join(" ")),
+            Self::IdContains { key, value } => write!(f, "ID {key} contains {value:?}"),
+            Self::IdVersion { min, max } => write!(
+                f,
+                "ID version in {}..={}",
+                min.as_deref().unwrap_or(""),
+                max.as_deref().unwrap_or("")
+            ),
+            Self::UnknownCommandContains(text) => {
+                write!(f, "unknown command response contains {text:?}")
+            }
+            Self::TagCharacterRejected(byte) => write!(f, "tag character 0x{byte:02x} rejected"),
+            Self::MaxTagAtLeast(length) => write!(f, "max tag >= {length}"),
+            Self::MaxTagAtMost(length) => write!(f, "max tag <= {length}"),
+        }
+    }
+}
+
+fn contains(haystack: &str, needle: &str) -> bool {
+    haystack
+        .to_ascii_lowercase()
+        .contains(&needle.to_ascii_lowercase())
+}
+
+/// Numeric components of a version string, e.g., `2.3.16 (7e2e900c1a)` becomes `[2, 3, 16]`.
+pub fn version(text: &str) -> Vec<u64> {
+    text.split(|c: char| !c.is_ascii_digit() && c != '.')
+        .find(|part| part.starts_with(|c: char| c.is_ascii_digit()))
+        .unwrap_or_default()
+        .split('.')
+        .map_while(|component| component.parse().ok())
+        .collect()
+}
+
+pub fn compare_versions(a: &[u64], b: &[u64]) -> Ordering {
+    for index in 0..a.len().max(b.len()) {
+        let ordering = a.get(index).unwrap_or(&0).cmp(b.get(index).unwrap_or(&0));
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+
+    Ordering::Equal
+}
+
+impl Matcher {
+    /// `None` when the features don't allow to decide (e.g., probes were skipped).
+    pub fn matches(&self, features: &Features) -> Option<bool> {
+        match self {
+            Self::GreetingContains(text) => Some(contains(features.greeting.as_ref()?, text)),
+            Self::CapabilityPresent(name) => Some(
+                features
+                    .capabilities
+                    .iter()
+                    .any(|capability| capability.eq_ignore_ascii_case(name)),
+            ),
+            Self::CapabilityOrder(names) => {
+                let positions = names
+                    .iter()
+                    .map(|name| {
+                        features
+                            .capabilities
+                            .iter()
+                            .position(|capability| capability.eq_ignore_ascii_case(name))
+                    })
+                    .collect::<Option<Vec<_>>>();
+
+                Some(positions.is_some_and(|positions| positions.windows(2).all(|w| w[0] < w[1])))
+            }
+            Self::IdContains { key, value } => {
+                features.id.as_ref()?;
+                Some(
+                    features
+                        .id_value(key)
+                        .is_some_and(|got| contains(got, value)),
+                )
+            }
+            Self::IdVersion { min, max } => {
+                let got = version(features.id_value("version")?);
+                if got.is_empty() {
+                    return None;
+                }
+
+                let above = min
+                    .as_ref()
+                    .is_none_or(|min| compare_versions(&got, &version(min)).is_ge());
+                let below = max
+                    .as_ref()
+                    .is_none_or(|max| compare_versions(&got, &version(max)).is_le());
+
+                Some(above && below)
+            }
+            Self::UnknownCommandContains(text) => {
+                Some(contains(features.unknown_command.as_ref()?, text))
+            }
+            Self::TagCharacterRejected(byte) => {
+                Some(features.rejected_tag_characters.as_ref()?.contains(byte))
+            }
+            Self::MaxTagAtLeast(length) => Some(features.max_tag? >= *length),
+            Self::MaxTagAtMost(length) => Some(features.max_tag? <= *length),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    pub matcher: Matcher,
+    pub weight: u32,
+}
+
+/// A known server implementation.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Signature {
+    pub server: String,
+    /// Versions this signature applies to (informational, e.g., `2.3.x`).
+    pub versions: String,
+    pub rules: Vec<Rule>,
+    #[serde(default)]
+    pub advisories: Vec<Advisory>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Candidate {
+    pub server: String,
+    pub versions: String,
+    /// Matched weight divided by the total weight of the signature.
+    pub confidence: f64,
+    /// Rules that matched.
+    pub matched: Vec<String>,
+    /// Rules that could be evaluated but did not match.
+    pub mismatched: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Fingerprint {
+    pub features: Features,
+    pub best: Option<Candidate>,
+    pub candidates: Vec<Candidate>,
+    /// Advisories of the best candidate.
+    pub advisories: Vec<AdvisoryMatch>,
+}
+
+impl Signature {
+    pub fn evaluate(&self, features: &Features) -> Candidate {
+        let mut candidate = Candidate {
+            server: self.server.clone(),
+            versions: self.versions.clone(),
+            confidence: 0.0,
+            matched: Vec::new(),
+            mismatched: Vec::new(),
+        };
+
+        let mut matched = 0;
+        let mut total = 0;
+
+        for rule in &self.rules {
+            total += rule.weight;
+
+            match rule.matcher.matches(features) {
+                Some(true) => {
+                    matched += rule.weight;
+                    candidate.matched.push(rule.matcher.to_string());
+                }
+                Some(false) => candidate.mismatched.push(rule.matcher.to_string()),
+                None => {}
+            }
+        }
+
+        if total > 0 {
+            candidate.confidence = f64::from(matched) / f64::from(total);
+        }
+
+        candidate
+    }
+}
+
+fn simple_id(
+    parameters: Vec<(IString<'static>, NString<'static>)>,
+) -> Vec<(String, Option<String>)> {
+    parameters
+        .into_iter()
+        .map(|(k, NString(v))| {
+            (
+                String::from_utf8_lossy(k.as_ref()).into_owned(),
+                v.map(|v| String::from_utf8_lossy(v.as_ref()).into_owned()),
+            )
+        })
+        .collect()
+}
+
+/// Learn the [`Features`] of a server.
+///
+/// `probes` additionally runs the (slower) tag probes.
+pub async fn features(
+    host: &str,
+    tls: Tls,
+    probes: bool,
+    timeout: Duration,
+) -> Result<Features, Box<dyn Error>> {
+    let (mut session, greeting) = Session::connect(host, tls, timeout).await?;
+
+    let mut features = Features {
+        greeting: Some(greeting.text.as_ref().to_string()),
+        ..Default::default()
+    };
+
+    let reply = session.command("X", CommandBody::Capability).await;
+    if let Some(capabilities) = reply.data.iter().find_map(|data| match data {
+        Data::Capability(capabilities) => Some(capabilities),
+        _ => None,
+    }) {
+        features.capabilities = capabilities
+            .as_ref()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+    }
+
+    let reply = session
+        .command(
+            "A",
+            CommandBody::Id {
+                parameters: Some(vec![(
+                    IString::try_from("name").unwrap(),
+                    NString(Some(IString::try_from("imap-sec").unwrap())),
+                )]),
+            },
+        )
+        .await;
+    features.id = reply.data.into_iter().find_map(|data| match data {
+        Data::Id { parameters } => Some(parameters.map(simple_id).unwrap_or_default()),
+        _ => None,
+    });
+
+    session.write_raw(b"F IMAPSECUNKNOWN\r\n").await?;
+    features.unknown_command = session.wait("F").await.text;
+
+    if probes {
+        info!("learning allowed tag characters");
+        features.rejected_tag_characters = Some(
+            learn::allowed_tag(host, tls, timeout)
+                .await?
+                .into_iter()
+                .filter(|(_, _, result)| !matches!(result, Some(AllowedResult::Reflected)))
+                .map(|(dec, _, _)| dec)
+                .collect(),
+        );
+
+        info!("learning maximum tag length");
+        features.max_tag = Some(learn::max_tag(host, tls, 0, MAX_TAG, timeout).await?.limit);
+    }
+
+    Ok(features)
+}
+
+/// Match `features` against `signatures`, best candidate first.
+pub fn identify(features: Features, signatures: &[Signature]) -> Fingerprint {
+    let mut evaluated = signatures
+        .iter()
+        .map(|signature| (signature, signature.evaluate(&features)))
+        .filter(|(_, candidate)| candidate.confidence > 0.0)
+        .collect::<Vec<_>>();
+    evaluated.sort_by(|(_, a), (_, b)| b.confidence.total_cmp(&a.confidence));
+
+    let advisories = evaluated
+        .first()
+        .map(|(signature, _)| {
+            signature
+                .advisories
+                .iter()
+                .filter_map(|advisory| advisory.evaluate(&features))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let candidates = evaluated
+        .into_iter()
+        .map(|(_, candidate)| candidate)
+        .collect::<Vec<_>>();
+
+    Fingerprint {
+        best: candidates.first().cloned(),
+        features,
+        candidates,
+        advisories,
+    }
+}
@@ -1,37 +1,84 @@
-use std::error::Error;
+use std::{error::Error, future::Future, time::Duration};
 
-use imap_next::{
-    client::{Client, Event, Options},
-    stream::Stream,
-};
 use imap_types::{
-    command::{Command, CommandBody},
-    core::{IString, NString, Tag, Vec1},
-    response::{Capability, Code, Data, Status, StatusBody, StatusKind, Tagged},
-    utils::escape_byte_string,
+    auth::AuthMechanism,
+    command::CommandBody,
+    core::{IString, NString, Vec1},
+    response::{Capability, Code, Data},
 };
 use serde::{Deserialize, Serialize};
-use tokio::{io::AsyncWriteExt, net::TcpStream};
-use tracing::{error, info, trace, warn};
-
-use crate::bisect;
+use tracing::{info, warn};
+
+use crate::{
+    bisect,
+    session::{Outcome, Reply, Session},
+    throttle::Mechanism,
+    timeline::{Phase, Source, Timeline},
+    tls,
+    transport::Tls,
+};
 
 #[derive(Debug)]
-pub(crate) struct Info {
+pub struct Info {
     greeting_capability: Option<Vec1<Capability<'static>>>,
     pre_auth_capability: Option<Vec1<Capability<'static>>>,
     pre_auth_id: Option<Option<Vec<(IString<'static>, NString<'static>)>>>,
     post_auth_capability: Option<Vec1<Capability<'static>>>,
     post_auth_id: Option<Option<Vec<(IString<'static>, NString<'static>)>>>,
+    plaintext: PlaintextExposure,
+    timeline: Timeline,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
-pub(crate) struct InfoSimple {
+pub struct InfoSimple {
     greeting_capability: Vec<Capability<'static>>,
     pre_auth_capability: Vec<Capability<'static>>,
     pre_auth_id: Option<Vec<(String, Option<String>)>>,
     post_auth_capability: Vec<Capability<'static>>,
     post_auth_id: Option<Vec<(String, Option<String>)>>,
+    pub plaintext: PlaintextExposure,
+    pub timeline: Timeline,
+}
+
+/// Whether credentials can be sent over the unencrypted connection.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct PlaintextExposure {
+    /// `LOGINDISABLED` was advertised before authentication.
+    pub login_disabled: bool,
+    /// `AUTH=PLAIN` was advertised before authentication.
+    pub auth_plain: bool,
+    /// `STARTTLS` was advertised before authentication.
+    pub starttls: bool,
+    /// LOGIN succeeded over the unencrypted connection (only known when credentials were given).
+    pub login_accepted: Option<bool>,
+    /// How the credentials were sent (only when credentials were given).
+    #[serde(default)]
+    pub mechanism: Option<Mechanism>,
+    /// Credentials are (or would be) accepted without TLS (never when `tls` protects them).
+    pub exposed: bool,
+}
+
+impl PlaintextExposure {
+    /// Assess `capabilities` of a connection with `tls`. The capability flags are kept over TLS
+    /// as well, but nothing is exposed there.
+    fn assess(
+        capabilities: &[Capability<'static>],
+        login_accepted: Option<bool>,
+        tls: Tls,
+    ) -> Self {
+        let login_disabled = capabilities.contains(&Capability::LoginDisabled);
+        let auth_plain = capabilities.contains(&Capability::Auth(AuthMechanism::Plain));
+
+        Self {
+            login_disabled,
+            auth_plain,
+            starttls: capabilities.contains(&Capability::StartTls),
+            login_accepted,
+            mechanism: None,
+            exposed: tls == Tls::None
+                && (login_accepted == Some(true) || !login_disabled || auth_plain),
+        }
+    }
 }
 
 impl From<Info> for InfoSimple {
@@ -50,8 +97,8 @@ impl From<Info> for InfoSimple {
                     .into_iter()
                     .map(|(k, NString(v))| {
                         (
-                            String::from_utf8(k.as_ref().to_vec()).unwrap(),
-                            v.map(|v| String::from_utf8(v.as_ref().to_vec()).unwrap()),
+                            String::from_utf8_lossy(k.as_ref()).into_owned(),
+                            v.map(|v| String::from_utf8_lossy(v.as_ref()).into_owned()),
                         )
                     })
                     .collect()
@@ -65,31 +112,76 @@ impl From<Info> for InfoSimple {
                     .into_iter()
                     .map(|(k, NString(v))| {
                         (
-                            String::from_utf8(k.as_ref().to_vec()).unwrap(),
-                            v.map(|v| String::from_utf8(v.as_ref().to_vec()).unwrap()),
+                            String::from_utf8_lossy(k.as_ref()).into_owned(),
+                            v.map(|v| String::from_utf8_lossy(v.as_ref()).into_owned()),
                         )
                     })
                     .collect::<Vec<_>>()
             }),
+            plaintext: value.plaintext,
+            timeline: value.timeline,
         }
     }
 }
 
-// TODO: Too much copy&paste ...
-pub(crate) async fn info(
+/// Capabilities from untagged CAPABILITY data and from the response code of the tagged response.
+fn capabilities(
+    reply: &Reply,
+) -> (
+    Option<Vec1<Capability<'static>>>,
+    Option<Vec1<Capability<'static>>>,
+) {
+    let untagged = reply.data.iter().rev().find_map(|data| match data {
+        Data::Capability(capabilities) => Some(capabilities.clone()),
+        _ => None,
+    });
+
+    let code = match &reply.code {
+        Some(Code::Capability(capabilities)) => Some(capabilities.clone()),
+        _ => None,
+    };
+
+    (untagged, code)
+}
+
+/// Record the capabilities of `reply` (untagged data and response code) at `phase`, and return
+/// them (the untagged data when both were sent).
+fn record(
+    timeline: &mut Timeline,
+    phase: Phase,
+    reply: &Reply,
+) -> Option<Vec1<Capability<'static>>> {
+    let (untagged, code) = capabilities(reply);
+
+    if let Some(capabilities) = &untagged {
+        timeline.push(phase, Source::Untagged, capabilities.as_ref());
+    }
+    if let Some(capabilities) = &code {
+        timeline.push(phase, Source::Code, capabilities.as_ref());
+    }
+
+    untagged.or(code)
+}
+
+fn id(reply: &Reply) -> Option<Option<Vec<(IString<'static>, NString<'static>)>>> {
+    reply.data.iter().rev().find_map(|data| match data {
+        Data::Id { parameters } => Some(parameters.clone()),
+        _ => None,
+    })
+}
+
+/// Learn capabilities and ID before and (with credentials) after authentication.
+///
+/// When LOGIN fails, only the capabilities and ID before authentication are learned. `timeout`
+/// bounds each response.
+pub async fn info(
     host: String,
+    tls: Tls,
     username: Option<String>,
     password: Option<String>,
+    timeout: Duration,
 ) -> Result<InfoSimple, Box<dyn Error>> {
-    let mut stream = Stream::insecure(TcpStream::connect(host).await.unwrap());
-    let mut client = Client::new(Options::default());
-
-    let greeting = loop {
-        match stream.next(&mut client).await.unwrap() {
-            Event::GreetingReceived { greeting } => break greeting,
-            event => println!("unexpected event: {event:?}"),
-        }
-    };
+    let (mut session, greeting) = Session::connect(&host, tls, timeout).await?;
 
     let mut result = Info {
         greeting_capability: if let Some(Code::Capability(capabilities)) = greeting.code {
@@ -101,34 +193,34 @@ pub(crate) async fn info(
         pre_auth_id: None,
         post_auth_capability: None,
         post_auth_id: None,
+        plaintext: PlaintextExposure::default(),
+        timeline: Timeline::default(),
     };
 
-    client.enqueue_command(Command::new("X", CommandBody::Capability)?);
-
-    loop {
-        match stream.next(&mut client).await? {
-            Event::CommandSent { .. } => {}
-            Event::DataReceived {
-                data: Data::Capability(capabilities),
-            } => result.pre_auth_capability = Some(capabilities),
-            Event::StatusReceived {
-                status:
-                    Status::Tagged(Tagged {
-                        tag,
-                        body: StatusBody { code, .. },
-                    }),
-            } => {
-                if let Some(Code::Capability(capabilities)) = code {
-                    result.pre_auth_capability = Some(capabilities);
-                }
+    if let Some(capabilities) = &result.greeting_capability {
+        result
+            .timeline
+            .push(Phase::Greeting, Source::Code, capabilities.as_ref());
+    }
 
-                if tag.as_ref() == "X" {
-                    break;
-                }
-            }
-            event => {
-                warn!(?event, "unexpected event");
-            }
+    let reply = session.command("X", CommandBody::Capability).await;
+    result.pre_auth_capability = record(&mut result.timeline, Phase::PreAuth, &reply);
+
+    let pre_auth = result
+        .pre_auth_capability
+        .as_ref()
+        .or(result.greeting_capability.as_ref())
+        .map(|inner| inner.as_ref().to_vec())
+        .unwrap_or_default();
+    result.plaintext = PlaintextExposure::assess(&pre_auth, None, tls);
+
+    // Over TLS, the capabilities above already are the ones after the handshake.
+    if tls == Tls::None && pre_auth.contains(&Capability::StartTls) {
+        match tls::capabilities_after_starttls(&host, timeout).await {
+            Ok(after) => result
+                .timeline
+                .push(Phase::StartTls, Source::Untagged, &after),
+            Err(error) => warn!(%error, "could not learn capabilities after STARTTLS"),
         }
     }
 
@@ -139,219 +231,227 @@ pub(crate) async fn info(
         )]),
     };
 
-    client.enqueue_command(Command::new("A", id_body.clone())?);
-
-    loop {
-        match stream.next(&mut client).await? {
-            Event::CommandSent { .. } => {}
-            Event::DataReceived {
-                data: Data::Id { parameters },
-            } => result.pre_auth_id = Some(parameters),
-            Event::StatusReceived {
-                status: Status::Tagged(Tagged { tag, .. }),
-            } if tag.as_ref() == "A" => {
-                break;
-            }
-            event => {
-                warn!(?event, "unexpected event");
-            }
-        }
-    }
+    let reply = session.command("A", id_body.clone()).await;
+    result.pre_auth_id = id(&reply);
 
     if let (Some(username), Some(password)) = (username, password) {
-        client.enqueue_command(Command::new("B", CommandBody::login(username, password)?)?);
-
-        loop {
-            match stream.next(&mut client).await? {
-                Event::CommandSent { .. } => {}
-                Event::StatusReceived {
-                    status:
-                        Status::Tagged(Tagged {
-                            tag,
-                            body: StatusBody { kind, code, text },
-                        }),
-                } if tag.as_ref() == "B" => {
-                    if kind == StatusKind::Ok {
-                        break;
-                    } else {
-                        error!(?code, ?text, "LOGIN failed");
-                        return Err("LOGIN failed".into());
-                    }
-                }
-                event => {
-                    warn!(?event, "unexpected event");
-                }
-            }
+        // LOGINDISABLED doesn't affect AUTHENTICATE.
+        let mechanism = if pre_auth.contains(&Capability::LoginDisabled)
+            && pre_auth.contains(&Capability::Auth(AuthMechanism::Plain))
+        {
+            Mechanism::Plain
+        } else {
+            Mechanism::Login
+        };
+        let sasl_ir = pre_auth
+            .iter()
+            .any(|capability| capability.to_string().eq_ignore_ascii_case("SASL-IR"));
+        let reply = mechanism
+            .authenticate(&mut session, "B", &username, &password, sasl_ir)
+            .await?;
+
+        result.plaintext =
+            PlaintextExposure::assess(&pre_auth, Some(reply.outcome == Outcome::Ok), tls);
+        result.plaintext.mechanism = Some(mechanism);
+
+        // Rejected credentials are a result (`login_accepted`), not an error.
+        if reply.outcome != Outcome::Ok {
+            warn!(code = ?reply.code, text = ?reply.text, "LOGIN failed");
+            result.timeline.diff();
+            return Ok(InfoSimple::from(result));
         }
 
-        client.enqueue_command(Command::new("X2", CommandBody::Capability)?);
-
-        loop {
-            match stream.next(&mut client).await? {
-                Event::CommandSent { .. } => {}
-                Event::DataReceived {
-                    data: Data::Capability(capabilities),
-                } => result.pre_auth_capability = Some(capabilities),
-                Event::StatusReceived {
-                    status:
-                        Status::Tagged(Tagged {
-                            tag,
-                            body: StatusBody { code, .. },
-                        }),
-                } => {
-                    if let Some(Code::Capability(capabilities)) = code {
-                        result.pre_auth_capability = Some(capabilities);
-                    }
-
-                    if tag.as_ref() == "X2" {
-                        break;
-                    }
-                }
-                event => {
-                    warn!(?event, "unexpected event");
-                }
-            }
-        }
+        record(&mut result.timeline, Phase::Login, &reply);
 
-        client.enqueue_command(Command::new("C", id_body.clone())?);
-
-        loop {
-            match stream.next(&mut client).await? {
-                Event::CommandSent { .. } => {}
-                Event::DataReceived {
-                    data: Data::Id { parameters },
-                } => result.post_auth_id = Some(parameters),
-                Event::StatusReceived {
-                    status: Status::Tagged(Tagged { tag, .. }),
-                } if tag.as_ref() == "C" => {
-                    break;
-                }
-                event => {
-                    warn!(?event, "unexpected event");
-                }
-            }
-        }
-    }
+        let reply = session.command("X2", CommandBody::Capability).await;
+        result.post_auth_capability = record(&mut result.timeline, Phase::PostAuth, &reply);
 
-    Ok(InfoSimple::from(result))
-}
+        let reply = session.command("C", id_body).await;
+        result.post_auth_id = id(&reply);
 
-pub(crate) async fn max_literal(host: &str, min: u64, max: u64) -> u64 {
-    async fn _max_literal(host: &str, test: u64) -> bool {
-        let mut stream = Stream::insecure(TcpStream::connect(host).await.unwrap());
-        let mut client = Client::new(Options::default());
-
-        let _ = loop {
-            match stream.next(&mut client).await.unwrap() {
-                Event::GreetingReceived { greeting } => break greeting,
-                event => println!("unexpected event: {event:?}"),
-            }
+        let post_auth = result
+            .post_auth_capability
+            .as_ref()
+            .map(|inner| inner.as_ref().to_vec())
+            .unwrap_or_default();
+        let advertised = |name: &str| {
+            post_auth
+                .iter()
+                .any(|capability| capability.to_string().eq_ignore_ascii_case(name))
         };
 
-        stream
-            .stream_mut()
-            .write_all(format!("A LOGIN {{{}}}\r\n", test).as_bytes())
-            .await
-            .unwrap();
+        if advertised("ENABLE") {
+            session
+                .write_raw(b"E ENABLE UTF8=ACCEPT CONDSTORE\r\n")
+                .await?;
+            session.wait("E").await;
 
-        loop {
-            match stream.next(&mut client).await {
-                Ok(Event::StatusReceived {
-                    status: Status::Tagged(Tagged { tag, .. }),
-                }) if tag.as_ref() == "A" => {
-                    return false;
-                }
-                Ok(Event::StatusReceived {
-                    status: Status::Bye(_),
-                }) => {
-                    return false;
-                }
-                Ok(Event::ContinuationRequestReceived { .. }) => {
-                    return true;
-                }
-                Ok(event) => warn!(?event, "unexpected event"),
-                Err(error) => {
-                    error!(?error);
-                    return false;
-                }
-            }
+            let reply = session.command("X3", CommandBody::Capability).await;
+            record(&mut result.timeline, Phase::PostEnable, &reply);
         }
+
+        result.timeline.skip(
+            Phase::PostCompress,
+            if advertised("COMPRESS=DEFLATE") {
+                "not attempted: the client doesn't support compression"
+            } else {
+                "COMPRESS=DEFLATE not advertised"
+            },
+        );
     }
 
+    result.timeline.diff();
+
+    Ok(InfoSimple::from(result))
+}
+
+/// A single bisection step.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Step {
+    pub tested: u64,
+    pub accepted: bool,
+    /// Server response the decision is based on (see [`Reply::line`]).
+    #[serde(default)]
+    pub response: String,
+}
+
+/// Result of learning a limit through bisection.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Limit {
+    pub min: u64,
+    pub max: u64,
+    pub limit: u64,
+    pub steps: Vec<Step>,
+}
+
+/// Learn a limit between `min` and `max` through bisection, with `accepts` testing a value (and
+/// returning the response it decided on).
+async fn bisect<F: Future<Output = Result<(bool, String), Box<dyn Error>>>>(
+    min: u64,
+    max: u64,
+    mut accepts: impl FnMut(u64) -> F,
+) -> Result<Limit, Box<dyn Error>> {
     let mut bisect = bisect::Bisect::new(min, max);
+    let mut steps = Vec::new();
     info!(min = bisect.min(), max = bisect.max());
 
     while let Some(next) = bisect.next() {
-        if _max_literal(host, next).await {
+        let (accepted, response) = accepts(next).await?;
+        if accepted {
             bisect.accept();
         } else {
             bisect.reject();
         }
+        steps.push(Step {
+            tested: next,
+            accepted,
+            response,
+        });
         info!(min = bisect.min(), max = bisect.max());
     }
 
-    bisect.finish().unwrap()
+    Ok(Limit {
+        min,
+        max,
+        limit: bisect.finish().ok_or("bisection did not finish")?,
+        steps,
+    })
 }
 
-pub(crate) async fn max_tag(host: &str, min: u64, max: u64) -> u64 {
-    async fn _max_tag(host: &str, test: u32) -> bool {
-        let mut stream = Stream::insecure(TcpStream::connect(host).await.unwrap());
-        let mut client = Client::new(Options::default());
-
-        let _ = loop {
-            match stream.next(&mut client).await.unwrap() {
-                Event::GreetingReceived { greeting } => break greeting,
-                event => println!("unexpected event: {event:?}"),
-            }
-        };
+/// Learn the longest literal accepted before authentication, between `min` and `max`.
+pub async fn max_literal(
+    host: &str,
+    tls: Tls,
+    min: u64,
+    max: u64,
+    timeout: Duration,
+) -> Result<Limit, Box<dyn Error>> {
+    bisect(min, max, |test| async move {
+        let (mut session, _) = Session::connect(host, tls, timeout).await?;
+        session
+            .write_raw(format!("A LOGIN {{{test}}}\r\n").as_bytes())
+            .await?;
+
+        let reply = session.wait_any().await;
+        Ok((reply.outcome == Outcome::Continue, reply.line()))
+    })
+    .await
+}
 
-        let test = "A".repeat(test as usize);
-        let tag = Tag::unvalidated(test.clone());
+/// Largest non-synchronizing literal `LITERAL-` allows (RFC 7888).
+pub const LITERAL_MINUS_MAX: u64 = 4096;
 
-        client.enqueue_command(Command::new(tag, CommandBody::Noop).unwrap());
+/// Response to a LOGIN with a non-synchronizing literal.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct NonSyncLiteral {
+    pub length: u64,
+    /// The server read the literal as part of the command (i.e., answered the LOGIN with OK or
+    /// NO).
+    pub accepted: bool,
+    /// See [`Reply::line`].
+    pub response: String,
+}
 
-        loop {
-            match stream.next(&mut client).await {
-                Ok(Event::CommandSent { .. }) => {}
-                Ok(Event::StatusReceived {
-                    status: Status::Tagged(Tagged { tag, .. }),
-                }) if tag.as_ref() == test.as_str() => {
-                    return true;
-                }
-                Ok(Event::StatusReceived {
-                    status: Status::Bye(_),
-                }) => {
-                    return false;
-                }
-                Ok(Event::StatusReceived {
-                    status: Status::Untagged(StatusBody { kind, .. }),
-                }) if kind == StatusKind::Bad => {
-                    return false;
-                }
-                Ok(event) => warn!(?event, "unexpected event"),
-                Err(error) => {
-                    error!(?error);
-                    return false;
-                }
-            }
-        }
+/// Send LOGIN with a small non-synchronizing literal, and with one larger than `LITERAL-`
+/// allows, each on its own connection.
+pub async fn non_sync_literal(
+    host: &str,
+    tls: Tls,
+    timeout: Duration,
+) -> Result<Vec<NonSyncLiteral>, Box<dyn Error>> {
+    let mut results = Vec::new();
+
+    for length in [1, LITERAL_MINUS_MAX + 1] {
+        let (mut session, _) = Session::connect(host, tls, timeout).await?;
+
+        let mut data = format!("A LOGIN {{{length}+}}\r\n").into_bytes();
+        data.extend(std::iter::repeat(b'a').take(usize::try_from(length)?));
+        data.extend_from_slice(b" imap-sec\r\n");
+        session.write_raw(&data).await?;
+
+        let reply = session.wait_any().await;
+        info!(length, outcome = ?reply.outcome);
+
+        results.push(NonSyncLiteral {
+            length,
+            accepted: reply.tag.as_deref() == Some("A")
+                && matches!(reply.outcome, Outcome::Ok | Outcome::No),
+            response: reply.line(),
+        });
     }
 
-    let mut bisect = bisect::Bisect::new(min, max);
-
-    while let Some(next) = bisect.next() {
-        if _max_tag(host, u32::try_from(next).unwrap()).await {
-            bisect.accept();
-        } else {
-            bisect.reject();
-        }
-    }
+    Ok(results)
+}
 
-    bisect.finish().unwrap()
+/// Learn the longest tag accepted, between `min` and `max`.
+pub async fn max_tag(
+    host: &str,
+    tls: Tls,
+    min: u64,
+    max: u64,
+    timeout: Duration,
+) -> Result<Limit, Box<dyn Error>> {
+    bisect(min, max, |test| async move {
+        let (mut session, _) = Session::connect(host, tls, timeout).await?;
+        let tag = "A".repeat(usize::try_from(test)?);
+        session
+            .write_raw(format!("{tag} NOOP\r\n").as_bytes())
+            .await?;
+
+        let reply = session.wait_any().await;
+        // Tags are up to `max` bytes long.
+        let response = match reply.line().strip_prefix(tag.as_str()) {
+            Some(rest) if !tag.is_empty() => format!("<tag>{rest}"),
+            _ => reply.line(),
+        };
+        Ok((reply.tag == Some(tag), response))
+    })
+    .await
 }
 
-#[derive(Debug)]
-pub(crate) enum AllowedResult {
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AllowedResult {
     Reflected,
     ReflectedBroken,
     Bad,
@@ -359,68 +459,385 @@ pub(crate) enum AllowedResult {
     Error,
 }
 
-pub(crate) async fn allowed_tag(host: &str) -> Vec<(u8, char, Option<AllowedResult>)> {
+/// Test every byte as the second tag character.
+pub async fn allowed_tag(
+    host: &str,
+    tls: Tls,
+    timeout: Duration,
+) -> Result<Vec<(u8, char, Option<AllowedResult>)>, Box<dyn Error>> {
     let mut tests = (0..=255u8)
-        .into_iter()
         .map(|dec| (dec, dec as char, None))
         .collect::<Vec<_>>();
 
     for (dec, _, res) in tests.iter_mut() {
-        let mut stream = Stream::insecure(TcpStream::connect(host).await.unwrap());
-        let mut client = Client::new(Options::default());
+        let (mut session, _) = Session::connect(host, tls, timeout).await?;
 
-        let _ = loop {
-            match stream.next(&mut client).await.unwrap() {
-                Event::GreetingReceived { greeting } => break greeting,
-                event => println!("unexpected event: {event:?}"),
+        let test = [b'A', *dec];
+        let mut data = test.to_vec();
+        data.extend_from_slice(b" NOOP\r\n");
+        session.write_raw(&data).await?;
+
+        let reply = session.wait_any().await;
+        *res = Some(match (reply.outcome, reply.tag) {
+            (Outcome::Ok | Outcome::No | Outcome::Bad, Some(tag)) => {
+                if tag.as_bytes() == test {
+                    AllowedResult::Reflected
+                } else {
+                    AllowedResult::ReflectedBroken
+                }
             }
-        };
+            (Outcome::Bad, None) => AllowedResult::Bad,
+            (Outcome::Bye, _) => AllowedResult::Bye,
+            (outcome, _) => {
+                warn!(dec, ?outcome, "no response to the tag");
+                AllowedResult::Error
+            }
+        });
+    }
 
-        let mut test = Vec::new();
-        test.push(b'A');
-        test.push(*dec);
+    Ok(tests)
+}
 
-        let mut data = Vec::new();
-        data.extend_from_slice(&test);
-        data.extend_from_slice(b" NOOP\r\n");
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
 
-        // TODO: Hack
-        trace!(data = escape_byte_string(&data), "io/write/raw");
-        stream.stream_mut().write_all(&data).await.unwrap();
-
-        loop {
-            match stream.next(&mut client).await {
-                Ok(Event::StatusReceived {
-                    status: Status::Tagged(Tagged { tag, .. }),
-                }) => {
-                    *res = Some(if test == tag.as_ref().as_bytes() {
-                        AllowedResult::Reflected
-                    } else {
-                        AllowedResult::ReflectedBroken
-                    });
-                    break;
-                }
-                Ok(Event::StatusReceived {
-                    status: Status::Untagged(StatusBody { kind, .. }),
-                }) if kind == StatusKind::Bad => {
-                    *res = Some(AllowedResult::Bad);
-                    break;
-                }
-                Ok(Event::StatusReceived {
-                    status: Status::Bye(_),
-                }) => {
-                    *res = Some(AllowedResult::Bye);
-                    break;
-                }
-                Ok(event) => warn!(?event, "unexpected event"),
-                Err(error) => {
-                    error!(?error);
-                    *res = Some(AllowedResult::Error);
-                    break;
-                }
+    use imap_types::{auth::AuthMechanism, response::Capability};
+
+    use super::{
+        allowed_tag, info, max_literal, max_tag, non_sync_literal, AllowedResult, PlaintextExposure,
+    };
+    use crate::{
+        finding,
+        mock::{self, Config, Quirks, Rejection, StartTls},
+        timeline::{Phase, Source},
+        transport::Tls,
+    };
+
+    const TIMEOUT: Duration = Duration::from_secs(30);
+
+    fn names(capabilities: &[Capability<'static>]) -> Vec<String> {
+        capabilities
+            .iter()
+            .map(|capability| capability.to_string().to_uppercase())
+            .collect()
+    }
+
+    #[test]
+    fn test_plaintext_exposure() {
+        let exposure = PlaintextExposure::assess(&[Capability::Imap4Rev1], None, Tls::None);
+        assert!(exposure.exposed);
+
+        for tls in [Tls::StartTls, Tls::Implicit] {
+            let exposure = PlaintextExposure::assess(&[Capability::Imap4Rev1], Some(true), tls);
+            assert!(!exposure.login_disabled);
+            assert!(!exposure.exposed, "{tls:?}");
+        }
+
+        let exposure = PlaintextExposure::assess(
+            &[
+                Capability::Imap4Rev1,
+                Capability::StartTls,
+                Capability::LoginDisabled,
+            ],
+            None,
+            Tls::None,
+        );
+        assert!(exposure.starttls);
+        assert!(!exposure.exposed);
+
+        let exposure = PlaintextExposure::assess(
+            &[
+                Capability::LoginDisabled,
+                Capability::Auth(AuthMechanism::Plain),
+            ],
+            None,
+            Tls::None,
+        );
+        assert!(exposure.auth_plain);
+        assert!(exposure.exposed);
+
+        let exposure =
+            PlaintextExposure::assess(&[Capability::LoginDisabled], Some(true), Tls::None);
+        assert!(exposure.exposed);
+    }
+
+    #[tokio::test]
+    async fn test_max_tag() {
+        for (limit, rejection) in [
+            (0, Rejection::Bad),
+            (1, Rejection::Bye),
+            (37, Rejection::Close),
+            (100, Rejection::Bad),
+        ] {
+            let host = mock::spawn(Config {
+                max_tag: limit,
+                rejection,
+                ..Default::default()
+            })
+            .await;
+
+            let result = max_tag(&host, Tls::None, 0, 100, TIMEOUT).await.unwrap();
+            assert_eq!(result.limit, limit as u64, "{rejection:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_max_literal() {
+        for (limit, rejection) in [
+            (0, Rejection::Bad),
+            (1, Rejection::Bye),
+            (1000, Rejection::Close),
+            (4096, Rejection::Bad),
+        ] {
+            let host = mock::spawn(Config {
+                max_literal: limit,
+                rejection,
+                ..Default::default()
+            })
+            .await;
+
+            let result = max_literal(&host, Tls::None, 0, 4096, TIMEOUT)
+                .await
+                .unwrap();
+            assert_eq!(result.limit, limit, "{rejection:?}");
+            assert!(result
+                .steps
+                .iter()
+                .all(|step| step.accepted == (step.tested <= limit)));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_non_sync_literal() {
+        let host = mock::spawn(Config::default()).await;
+        let results = non_sync_literal(&host, Tls::None, TIMEOUT).await.unwrap();
+        assert_eq!(
+            results
+                .iter()
+                .map(|result| (result.length, result.accepted))
+                .collect::<Vec<_>>(),
+            [(1, true), (4097, false)]
+        );
+        assert_eq!(results[0].response, "A NO invalid credentials");
+
+        let host = mock::spawn(Config {
+            max_literal: 8192,
+            ..Default::default()
+        })
+        .await;
+        let results = non_sync_literal(&host, Tls::None, TIMEOUT).await.unwrap();
+        assert!(results.iter().all(|result| result.accepted));
+    }
+
+    #[tokio::test]
+    async fn test_allowed_tag() {
+        for (rejection, expected) in [
+            (Rejection::Bad, AllowedResult::Bad),
+            (Rejection::Bye, AllowedResult::Bye),
+            (Rejection::Close, AllowedResult::Error),
+        ] {
+            let host = mock::spawn(Config {
+                rejection,
+                ..Default::default()
+            })
+            .await;
+
+            for (dec, _, result) in allowed_tag(&host, Tls::None, TIMEOUT).await.unwrap() {
+                let expected = match dec {
+                    // The tag ends before the byte, so the server answers with tag "A".
+                    b' ' | b'\n' => AllowedResult::ReflectedBroken,
+                    dec if finding::forbidden_in_tag(dec) => expected,
+                    _ => AllowedResult::Reflected,
+                };
+                assert_eq!(result, Some(expected), "0x{dec:02x} ({rejection:?})");
             }
         }
     }
 
-    tests
+    #[tokio::test]
+    async fn test_info() {
+        let host = mock::spawn(Config::default()).await;
+
+        let result = info(
+            host,
+            Tls::None,
+            Some("alice".into()),
+            Some("password".into()),
+            TIMEOUT,
+        )
+        .await
+        .unwrap();
+
+        let pre_auth = [
+            "IMAP4REV1",
+            "LITERAL+",
+            "ID",
+            "ENABLE",
+            "IDLE",
+            "AUTH=PLAIN",
+        ];
+        assert_eq!(names(&result.greeting_capability), pre_auth);
+        assert_eq!(names(&result.pre_auth_capability), pre_auth);
+        assert_eq!(
+            names(&result.post_auth_capability),
+            ["IMAP4REV1", "LITERAL+", "ID", "ENABLE", "IDLE", "MOVE"]
+        );
+        assert_eq!(
+            result.pre_auth_id,
+            Some(vec![("name".into(), Some("imap-sec-mock".into()))])
+        );
+        assert_eq!(result.post_auth_id, result.pre_auth_id);
+        assert_eq!(result.plaintext.login_accepted, Some(true));
+        assert!(result.plaintext.auth_plain);
+        assert!(result.plaintext.exposed);
+
+        let host = mock::spawn(Config::default()).await;
+        let result = info(
+            host,
+            Tls::None,
+            Some("alice".into()),
+            Some("wrong".into()),
+            TIMEOUT,
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.plaintext.login_accepted, Some(false));
+        assert_eq!(names(&result.pre_auth_capability), pre_auth);
+        assert!(result.post_auth_capability.is_empty());
+
+        let host = mock::spawn(Config {
+            capabilities: vec!["IMAP4rev1".into()],
+            login_disabled: true,
+            quirks: Quirks {
+                greeting_capabilities: Some(vec!["IMAP4rev1".into(), "AUTH=PLAIN".into()]),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .await;
+
+        let result = info(host, Tls::None, None, None, TIMEOUT).await.unwrap();
+        assert_eq!(
+            names(&result.greeting_capability),
+            ["IMAP4REV1", "AUTH=PLAIN"]
+        );
+        assert_eq!(
+            names(&result.pre_auth_capability),
+            ["IMAP4REV1", "LOGINDISABLED"]
+        );
+        assert!(result.post_auth_capability.is_empty());
+        assert!(result.plaintext.login_disabled);
+        assert_eq!(result.plaintext.login_accepted, None);
+        assert!(!result.plaintext.exposed);
+
+        // The greeting disagrees with the CAPABILITY response.
+        let change = &result.timeline.changes[0];
+        assert_eq!((change.from, change.to), (Phase::Greeting, Phase::PreAuth));
+        assert!(change.conflict);
+
+        // LOGINDISABLED, so AUTHENTICATE, whose response code disagrees with the CAPABILITY
+        // response after it.
+        let host = mock::spawn(Config {
+            login_disabled: true,
+            quirks: Quirks {
+                login_capabilities: Some(vec!["IMAP4rev1".into(), "IDLE".into()]),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .await;
+
+        let result = info(
+            host,
+            Tls::None,
+            Some("alice".into()),
+            Some("password".into()),
+            TIMEOUT,
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.plaintext.login_accepted, Some(true));
+        let sources = result
+            .timeline
+            .snapshots
+            .iter()
+            .map(|snapshot| (snapshot.phase, snapshot.source))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sources,
+            [
+                (Phase::Greeting, Source::Code),
+                (Phase::PreAuth, Source::Untagged),
+                (Phase::Login, Source::Code),
+                (Phase::PostAuth, Source::Untagged),
+                (Phase::PostEnable, Source::Untagged),
+            ]
+        );
+        assert_eq!(result.timeline.skipped[0].phase, Phase::PostCompress);
+        assert_eq!(
+            result.timeline.skipped[0].reason,
+            "COMPRESS=DEFLATE not advertised"
+        );
+
+        let host = mock::spawn(Config {
+            post_auth_capabilities: vec!["IMAP4rev1".into(), "COMPRESS=DEFLATE".into()],
+            ..Default::default()
+        })
+        .await;
+        let result = info(
+            host,
+            Tls::None,
+            Some("alice".into()),
+            Some("password".into()),
+            TIMEOUT,
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.timeline.skipped[0].phase, Phase::PostCompress);
+        assert!(result.timeline.skipped[0]
+            .reason
+            .starts_with("not attempted"));
+        let change = result
+            .timeline
+            .changes
+            .iter()
+            .find(|change| change.to == Phase::PostAuth)
+            .unwrap();
+        assert_eq!(change.from, Phase::Login);
+        assert!(change.conflict);
+
+        // Over STARTTLS, the credentials aren't exposed although AUTH=PLAIN is advertised and
+        // LOGIN is accepted.
+        let host = mock::spawn(Config {
+            starttls: StartTls::Working,
+            login_disabled: true,
+            ..Default::default()
+        })
+        .await;
+
+        let result = info(
+            host.clone(),
+            Tls::StartTls,
+            Some("alice".into()),
+            Some("password".into()),
+            TIMEOUT,
+        )
+        .await
+        .unwrap();
+        assert_eq!(names(&result.pre_auth_capability), pre_auth);
+        assert_eq!(result.plaintext.login_accepted, Some(true));
+        assert!(result.plaintext.auth_plain);
+        assert!(!result.plaintext.exposed);
+
+        // Without TLS, the same server advertises STARTTLS and LOGINDISABLED.
+        let result = info(host, Tls::None, None, None, TIMEOUT).await.unwrap();
+        assert!(result.plaintext.starttls);
+        assert!(result.plaintext.exposed);
+        assert!(result
+            .timeline
+            .snapshots
+            .iter()
+            .any(|snapshot| snapshot.phase == Phase::StartTls));
+    }
 }
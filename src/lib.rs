@@ -0,0 +1,76 @@
+//! Security probes for IMAP servers.
+//!
+//! Every probe connects to a host (`host:port`), runs its checks, and returns a serializable
+//! result. [`finding`] turns results into findings, [`report`] wraps them with metadata for
+//! output, and [`session`] is the connection layer the probes share.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use imap_sec::{finding, learn, transport::Tls};
+//!
+//! # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! let timeout = Duration::from_secs(30);
+//! let limit = learn::max_literal("localhost:143", Tls::None, 0, u32::MAX.into(), timeout).await?;
+//!
+//! for finding in finding::max_literal("localhost:143", &limit) {
+//!     println!("{finding}");
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+/// Bisection over an integer range, used to learn limits.
+pub mod bisect;
+/// Growth of response times with command size.
+pub mod complexity;
+/// Configuration file.
+pub mod config;
+/// IMAP state machine conformance.
+pub mod conformance;
+/// Comparison of two scan results.
+pub mod diff;
+/// Username enumeration through response and timing differences.
+pub mod enumeration;
+/// Memory exhaustion through a never-ending SEARCH literal.
+pub mod exploit;
+/// SARIF and HTML rendering of reports.
+pub mod export;
+/// Findings and their severity.
+pub mod finding;
+/// Server identification from greeting, capabilities, ID, and error texts.
+pub mod fingerprint;
+/// Capabilities, limits, and tag characters.
+pub mod learn;
+#[cfg(test)]
+mod mock;
+/// Assertions over scan results (for CI gates).
+pub mod policy;
+/// Probe trait and registry.
+pub mod probe;
+/// Resource usage of local processes.
+pub mod process;
+/// Replay of recorded transcripts.
+pub mod replay;
+/// Probe results with metadata and findings.
+pub mod report;
+/// Full profile of probes against one target.
+pub mod scan;
+/// Connection layer shared by the probes.
+pub mod session;
+/// Known server signatures and advisories.
+pub mod signatures;
+/// Local server processes watched during probes.
+pub mod supervisor;
+/// Lists of targets to scan.
+pub mod targets;
+/// Authentication throttling and lockout.
+pub mod throttle;
+/// Capability changes across the phases of a connection.
+pub mod timeline;
+/// STARTTLS.
+pub mod tls;
+/// Byte-exact recording of connections.
+pub mod transcript;
+/// TLS modes of targets.
+pub mod transport;
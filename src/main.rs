@@ -1,17 +1,50 @@
-mod bisect;
-mod exploit;
-mod learn;
-
-use std::error::Error;
+use std::{error::Error, path::PathBuf, rc::Rc, time::Duration};
 
 use argh::FromArgs;
-use imap_types::utils::escape_byte_string;
+use imap_sec::{
+    config::Config,
+    diff, finding, policy,
+    probe::{self, Registry},
+    replay,
+    report::{self, Format, Run},
+    scan, signatures,
+    supervisor::Supervisor,
+    targets, transcript,
+    transport::Tls,
+};
+use serde::Serialize;
 use tracing::info;
 use tracing_subscriber::EnvFilter;
 
 #[derive(FromArgs, PartialEq, Debug)]
 /// imap-sec.
 struct Arguments {
+    /// output format: text (default), json, sarif, or html
+    #[argh(option, default = "Format::Text")]
+    format: Format,
+
+    /// record every byte of every connection to this file (JSONL)
+    #[argh(option)]
+    transcript: Option<PathBuf>,
+
+    /// configuration file (TOML) with targets, credentials, timeouts, and probe parameters for
+    /// run and scan
+    #[argh(option)]
+    config: Option<PathBuf>,
+
+    /// confirm that dangerous probes may take the target down
+    #[argh(switch)]
+    i_understand: bool,
+
+    /// target dangerous probes may run against: host, host:port, or *.domain (repeatable)
+    #[argh(option)]
+    allow: Vec<String>,
+
+    /// launch this local server command (e.g., "dovecot -F -c dovecot.conf") and watch its
+    /// memory and CPU during each probe (Linux)
+    #[argh(option)]
+    server: Option<String>,
+
     #[argh(subcommand)]
     subcommand: SubCommand,
 }
@@ -19,92 +52,187 @@ struct Arguments {
 #[derive(FromArgs, PartialEq, Debug)]
 #[argh(subcommand)]
 enum SubCommand {
-    Info(Info),
-    MaxTag(MaxTag),
-    MaxLiteral(MaxLiteral),
-    AllowedTag(AllowedTag),
-    OutOfMemory(OutOfMemory),
+    Fingerprint(Fingerprint),
+    CheckSignatures(CheckSignatures),
+    Diff(Diff),
+    Policy(Policy),
+    Replay(Replay),
+    Probes(Probes),
+    Run(RunProbe),
+    Scan(Scan),
 }
 
-/// Learn capabilities and ID
-#[derive(FromArgs, PartialEq, Debug)]
-#[argh(subcommand, name = "info")]
-struct Info {
+/// Identify the server implementation (same as run fingerprint)
+#[derive(FromArgs, PartialEq, Debug, Serialize)]
+#[argh(subcommand, name = "fingerprint")]
+struct Fingerprint {
     /// host
     #[argh(positional)]
     host: String,
 
-    /// username
+    /// also learn tag characters and max tag length (slow)
+    #[argh(switch)]
+    probes: bool,
+
+    /// signature database (JSON, defaults to the built-in one)
     #[argh(option)]
-    username: Option<String>,
+    signatures: Option<PathBuf>,
 
-    /// password
+    /// seconds to wait for a response (default 30)
     #[argh(option)]
-    password: Option<String>,
+    timeout: Option<u64>,
 }
 
-/// Learn max tag length (through NOOP command)
-#[derive(FromArgs, PartialEq, Debug)]
-#[argh(subcommand, name = "max_tag")]
-struct MaxTag {
-    /// host
+/// Validate a signature database
+#[derive(FromArgs, PartialEq, Debug, Serialize)]
+#[argh(subcommand, name = "check_signatures")]
+struct CheckSignatures {
+    /// signature database (JSON)
     #[argh(positional)]
-    host: String,
+    path: PathBuf,
+}
 
-    /// min
+/// Compare two result files (JSON) and fail on regressions
+#[derive(FromArgs, PartialEq, Debug, Serialize)]
+#[argh(subcommand, name = "diff")]
+struct Diff {
+    /// earlier result file
     #[argh(positional)]
-    min: u64,
+    before: PathBuf,
 
-    /// max
+    /// later result file
     #[argh(positional)]
-    max: u64,
+    after: PathBuf,
 }
 
-/// Learn max literal length (through user astring in LOGIN command)
-#[derive(FromArgs, PartialEq, Debug)]
-#[argh(subcommand, name = "max_literal")]
-struct MaxLiteral {
-    /// host
+/// Check a result file (JSON) against a policy and fail on violations
+#[derive(FromArgs, PartialEq, Debug, Serialize)]
+#[argh(subcommand, name = "policy")]
+struct Policy {
+    /// policy file (JSON)
     #[argh(positional)]
-    host: String,
+    policy: PathBuf,
 
-    /// min
+    /// result file
     #[argh(positional)]
-    min: u64,
+    results: PathBuf,
+}
 
-    /// max
+/// Replay the client side of a transcript and compare the responses
+#[derive(FromArgs, PartialEq, Debug, Serialize)]
+#[argh(subcommand, name = "replay")]
+struct Replay {
+    /// transcript file (JSONL, see --transcript)
     #[argh(positional)]
-    max: u64,
-}
+    transcript: PathBuf,
 
-/// Learn allowed tag characters (through NOOP command)
-#[derive(FromArgs, PartialEq, Debug)]
-#[argh(subcommand, name = "allowed_tag")]
-struct AllowedTag {
     /// host
     #[argh(positional)]
     host: String,
+
+    /// connection to replay (repeatable, defaults to all)
+    #[argh(option)]
+    connection: Vec<u64>,
+
+    /// send with the recorded delays
+    #[argh(switch)]
+    timing: bool,
+
+    /// seconds to wait for each response
+    #[argh(option, default = "5")]
+    timeout: u64,
 }
 
-/// Try to bring server OOM via SEARCH command. WARNING: Don't use in production.
-#[derive(FromArgs, PartialEq, Debug)]
-#[argh(subcommand, name = "oom")]
-struct OutOfMemory {
+/// List the probes that run can execute
+#[derive(FromArgs, PartialEq, Debug, Serialize)]
+#[argh(subcommand, name = "probes")]
+struct Probes {}
+
+/// Run a probe by name (see probes)
+#[derive(FromArgs, PartialEq, Debug, Serialize)]
+#[argh(subcommand, name = "run")]
+struct RunProbe {
+    /// probe
+    #[argh(positional)]
+    probe: String,
+
     /// host
     #[argh(positional)]
     host: String,
 
+    /// parameter as name=value, value in JSON or a plain string (repeatable)
+    #[argh(option, short = 'p')]
+    param: Vec<String>,
+
     /// username
-    #[argh(positional)]
-    username: String,
+    #[argh(option)]
+    username: Option<String>,
 
     /// password
-    #[argh(positional)]
-    password: String,
+    #[argh(option)]
+    #[serde(skip_serializing)]
+    password: Option<String>,
+}
 
-    /// chunk size
+/// Run info, the limit and tag probes, and fingerprint, and combine the results
+#[derive(FromArgs, PartialEq, Debug, Serialize)]
+#[argh(subcommand, name = "scan")]
+struct Scan {
+    /// host (or --targets, or the targets of --config)
     #[argh(positional)]
-    chunk_size: u32,
+    host: Option<String>,
+
+    /// file with one target per line: host:port [tls=MODE] [credentials=NAME], credentials
+    /// from IMAP_SEC_<NAME>_USERNAME and IMAP_SEC_<NAME>_PASSWORD
+    #[argh(option)]
+    targets: Option<PathBuf>,
+
+    /// TLS of host: none (default), starttls, or implicit
+    #[argh(option)]
+    tls: Option<Tls>,
+
+    /// username (enables the authenticated checks)
+    #[argh(option)]
+    username: Option<String>,
+
+    /// password
+    #[argh(option)]
+    #[serde(skip_serializing)]
+    password: Option<String>,
+
+    /// also run dangerous probes (oom, with --i-understand and --allow). WARNING: Don't use in
+    /// production.
+    #[argh(switch)]
+    dangerous: bool,
+
+    /// seconds to wait for a response (default 5)
+    #[argh(option)]
+    timeout: Option<u64>,
+
+    /// probe to run instead of the full profile (repeatable)
+    #[argh(option)]
+    probe: Vec<String>,
+
+    /// targets to scan at the same time (default 4)
+    #[argh(option)]
+    parallel: Option<usize>,
+}
+
+impl SubCommand {
+    /// Host of a command that runs probes against a single host.
+    fn host(&self) -> Option<&str> {
+        match self {
+            Self::Fingerprint(Fingerprint { host, .. }) | Self::Run(RunProbe { host, .. }) => {
+                Some(host.as_str())
+            }
+            Self::Scan(Scan { host, .. }) => host.as_deref(),
+            Self::CheckSignatures(_)
+            | Self::Diff(_)
+            | Self::Policy(_)
+            | Self::Replay(_)
+            | Self::Probes(_) => None,
+        }
+    }
 }
 
 #[tokio::main(flavor = "current_thread")]
@@ -120,44 +248,205 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let args: Arguments = argh::from_env();
     info!(?args);
 
-    match args.subcommand {
-        SubCommand::Info(Info {
+    let format = args.format;
+
+    if let Some(path) = &args.transcript {
+        transcript::record(path)?;
+    }
+
+    let config = match &args.config {
+        Some(path) => Config::load(path)?,
+        None => Config::default(),
+    };
+
+    let mut guard = config.guard(args.i_understand);
+    guard.allowlist.extend(args.allow.iter().cloned());
+
+    let supervisor = match &args.server {
+        Some(command) => {
+            let host = args
+                .subcommand
+                .host()
+                .ok_or("--server requires run or scan with a single host")?;
+            let command = command
+                .split_whitespace()
+                .map(str::to_string)
+                .collect::<Vec<_>>();
+            Some(Rc::new(Supervisor::launch(&command, host).await?))
+        }
+        None => None,
+    };
+
+    let registry = Registry::builtin();
+
+    match &args.subcommand {
+        SubCommand::Probes(parameters @ Probes {}) => {
+            let run = Run::start("probes", "", parameters);
+            run.finish(registry.describe(), finding::none)
+                .print(format, |descriptions| {
+                    descriptions
+                        .iter()
+                        .map(|description| format!("{description}\n"))
+                        .collect()
+                })?;
+        }
+        SubCommand::Run(RunProbe {
+            probe,
             host,
+            param,
             username,
             password,
         }) => {
-            let info = learn::info(host, username, password).await?;
-            println!("{}", serde_json::to_string(&info)?);
+            let mut parameters = config.parameters(probe);
+            for text in param {
+                let (name, value) = probe::parameter(text)?;
+                parameters.insert(name, value);
+            }
+
+            let target = config.target(host, username.clone(), password.clone())?;
+            let report = registry
+                .run(probe, &target, parameters, &guard, supervisor.as_deref())
+                .await?;
+            report.print(format, |_| registry.text(&report))?;
+        }
+        SubCommand::Fingerprint(Fingerprint {
+            host,
+            probes,
+            signatures,
+            timeout,
+        }) => {
+            let mut parameters = config.parameters("fingerprint");
+            if *probes {
+                parameters.insert("probes".into(), true.into());
+            }
+            if let Some(signatures) = signatures {
+                parameters.insert("signatures".into(), signatures.display().to_string().into());
+            }
+            if let Some(timeout) = timeout {
+                parameters.insert("timeout".into(), (*timeout).into());
+            }
+
+            let target = config.target(host, None, None)?;
+            let report = registry
+                .run(
+                    "fingerprint",
+                    &target,
+                    parameters,
+                    &guard,
+                    supervisor.as_deref(),
+                )
+                .await?;
+            report.print(format, |_| registry.text(&report))?;
         }
-        // Does it support CRAM-MD5?
-        // SubCommand::CramMd5(_) => {
-        // }
-        SubCommand::MaxTag(MaxTag { host, min, max }) => {
-            let max_tag = learn::max_tag(&host, min, max).await;
-            println!("Maximum tag length: {max_tag}");
+        SubCommand::Scan(
+            parameters @ Scan {
+                host,
+                targets,
+                tls,
+                username,
+                password,
+                dangerous,
+                timeout,
+                probe,
+                parallel,
+            },
+        ) => {
+            let mut options = config.options();
+            options.dangerous |= *dangerous;
+            if let Some(timeout) = timeout {
+                options.timeout = *timeout;
+            }
+            if !probe.is_empty() {
+                options.probes = probe.clone();
+            }
+            if let Some(parallel) = parallel {
+                options.parallel = *parallel;
+            }
+            options.guard = guard.clone();
+            options.supervisor = supervisor.clone();
+
+            let (run, scan) = match (host, targets, &args.config) {
+                (Some(host), None, _) => {
+                    let run = Run::start("scan", host, parameters);
+                    let mut target = config.target(host, username.clone(), password.clone())?;
+                    if let Some(tls) = tls {
+                        target.tls = *tls;
+                    }
+                    (run, scan::scan(&registry, &target, &options).await)
+                }
+                (None, Some(path), _) => {
+                    let run = Run::start("scan", &path.display().to_string(), parameters);
+                    let entries = targets::load(path)?;
+                    (run, scan::scan_all(&registry, &entries, &options).await)
+                }
+                (None, None, Some(path)) if !config.targets.is_empty() => {
+                    let run = Run::start("scan", &path.display().to_string(), parameters);
+                    (
+                        run,
+                        scan::scan_all(&registry, &config.targets, &options).await,
+                    )
+                }
+                _ => return Err("expected a host, --targets, or targets in --config".into()),
+            };
+            run.finish(scan, |_, scan| scan.findings())
+                .print(format, ToString::to_string)?;
         }
-        SubCommand::MaxLiteral(MaxLiteral { host, min, max }) => {
-            let max_literal = learn::max_literal(&host, min, max).await;
-            println!("Maximum literal length: {max_literal} (0x{max_literal:x})");
+        SubCommand::CheckSignatures(parameters @ CheckSignatures { path }) => {
+            let run = Run::start("check_signatures", &path.display().to_string(), parameters);
+            let database = signatures::Database::load(path)?;
+            run.finish(database, finding::none)
+                .print(format, |database| {
+                    format!(
+                        "{}: {} signature(s), format version {}",
+                        path.display(),
+                        database.signatures.len(),
+                        database.version
+                    )
+                })?;
         }
-        SubCommand::AllowedTag(AllowedTag { host }) => {
-            let allowed_tag_characters = learn::allowed_tag(&host).await;
-            println!("Allowed tag characters:");
-            for (dec, char, result) in allowed_tag_characters {
-                println!(
-                    "{dec}: \"A{}\" => {:?}",
-                    escape_byte_string(&[char as u8]),
-                    result.unwrap()
-                );
+        SubCommand::Diff(parameters @ Diff { before, after }) => {
+            let run = Run::start("diff", &after.display().to_string(), parameters);
+            let diff = diff::diff(&report::load(before)?, &report::load(after)?);
+            let regression = diff.regression;
+            run.finish(diff, finding::none)
+                .print(format, ToString::to_string)?;
+
+            if regression {
+                std::process::exit(1);
             }
         }
-        SubCommand::OutOfMemory(OutOfMemory {
-            host,
-            username,
-            password,
-            chunk_size,
-        }) => {
-            exploit::oom(&host, &username, &password, chunk_size).await;
+        SubCommand::Policy(parameters @ Policy { policy, results }) => {
+            let run = Run::start("policy", &results.display().to_string(), parameters);
+            let evaluation = policy::Policy::load(policy)?.evaluate(&report::load(results)?);
+            let violations = evaluation.violations;
+            run.finish(evaluation, finding::none)
+                .print(format, ToString::to_string)?;
+
+            if violations > 0 {
+                std::process::exit(1);
+            }
+        }
+        SubCommand::Replay(
+            parameters @ Replay {
+                transcript,
+                host,
+                connection,
+                timing,
+                timeout,
+            },
+        ) => {
+            let run = Run::start("replay", host, parameters);
+            let entries = transcript::load(transcript)?;
+            let replay = replay::replay(
+                &entries,
+                host,
+                connection,
+                *timing,
+                Duration::from_secs(*timeout),
+            )
+            .await?;
+            run.finish(replay, finding::replay)
+                .print(format, report::json)?;
         }
     }
 
@@ -0,0 +1,959 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    io,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use imap_codec::{encode::Encoder, CommandCodec};
+use imap_next::{
+    server::{Error, Event, Options, Server},
+    stream::{self, Stream},
+};
+use imap_types::{
+    auth::{AuthMechanism, AuthenticateData},
+    command::{Command, CommandBody},
+    core::{Atom, IString, NString, Tag, Vec1},
+    mailbox::Mailbox,
+    response::{Capability, Code, CommandContinuationRequest, Data, Greeting, Status},
+    secret::Secret,
+};
+use rustls::{
+    pki_types::{CertificateDer, PrivatePkcs8KeyDer},
+    ServerConfig,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    time::{sleep, timeout},
+};
+use tokio_rustls::TlsAcceptor;
+
+use crate::conformance::State;
+
+/// How the server reacts when a limit is exceeded.
+///
+/// Commands are parsed by imap-next, so everything the codec rejects (e.g., forbidden tag
+/// characters) is rejected like an exceeded limit.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Rejection {
+    /// Tagged `BAD` (untagged when the tag itself is the problem).
+    Bad,
+    /// Untagged `BYE`, then close.
+    Bye,
+    /// Close without a response.
+    Close,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum StartTls {
+    /// Not advertised, STARTTLS is an unknown command.
+    Unavailable,
+    /// Advertised, but STARTTLS fails with `NO`.
+    Refused,
+    /// Advertised, STARTTLS succeeds, and the connection is closed instead of a handshake.
+    Broken,
+    /// Advertised, and STARTTLS upgrades the connection (with a self-signed certificate).
+    Working,
+}
+
+/// Deviations from a well-behaved server.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Quirks {
+    /// Accept every command in every state.
+    pub(crate) ignore_state: bool,
+    /// Accept LOGIN although LOGINDISABLED is advertised.
+    pub(crate) ignore_login_disabled: bool,
+    /// Answer failed logins of unknown users with a different text.
+    pub(crate) reveal_unknown_users: bool,
+    /// Delay of failed authentications (see `delay_after`).
+    pub(crate) failed_login_delay: Duration,
+    /// Failed authentications on a connection before `failed_login_delay` applies.
+    pub(crate) delay_after: usize,
+    /// Additional delay of failed authentications of existing users.
+    pub(crate) known_user_delay: Duration,
+    /// Close the connection after this many failed authentications.
+    pub(crate) max_failed_logins: Option<usize>,
+    /// Lock the account after this many failed authentications (across connections), answering
+    /// with `[UNAVAILABLE]`.
+    pub(crate) lockout_after: Option<usize>,
+    /// Capabilities in the greeting that differ from the CAPABILITY response.
+    pub(crate) greeting_capabilities: Option<Vec<String>>,
+    /// Capabilities in the response to a successful authentication that differ from the
+    /// CAPABILITY response.
+    pub(crate) login_capabilities: Option<Vec<String>>,
+    /// Delay of every command, times the square of its number of arguments.
+    pub(crate) quadratic_delay: Duration,
+}
+
+/// Behavior of the mock server.
+#[derive(Clone, Debug)]
+pub(crate) struct Config {
+    pub(crate) greeting: String,
+    /// Capabilities before authentication (without STARTTLS and LOGINDISABLED).
+    pub(crate) capabilities: Vec<String>,
+    pub(crate) post_auth_capabilities: Vec<String>,
+    pub(crate) id: Vec<(String, String)>,
+    pub(crate) unknown_command: String,
+    pub(crate) users: Vec<(String, String)>,
+    pub(crate) max_tag: usize,
+    pub(crate) max_literal: u64,
+    /// Longest command, including its literals.
+    pub(crate) max_command: u32,
+    pub(crate) rejection: Rejection,
+    /// Close (with `BYE`) when the client is silent for this long.
+    pub(crate) timeout: Option<Duration>,
+    /// Concurrent sessions; more are greeted with `BYE`.
+    pub(crate) max_sessions: Option<usize>,
+    pub(crate) starttls: StartTls,
+    pub(crate) login_disabled: bool,
+    pub(crate) quirks: Quirks,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let capabilities = [
+            "IMAP4rev1",
+            "LITERAL+",
+            "ID",
+            "ENABLE",
+            "IDLE",
+            "AUTH=PLAIN",
+        ];
+
+        Self {
+            greeting: "imap-sec mock ready".into(),
+            capabilities: capabilities.map(String::from).to_vec(),
+            post_auth_capabilities: capabilities[..5]
+                .iter()
+                .chain(&["MOVE"])
+                .map(|capability| capability.to_string())
+                .collect(),
+            id: vec![("name".into(), "imap-sec-mock".into())],
+            unknown_command: "Unknown command".into(),
+            users: vec![("alice".into(), "password".into())],
+            max_tag: 64,
+            max_literal: 4096,
+            max_command: 8192,
+            rejection: Rejection::Bad,
+            timeout: Some(Duration::from_secs(10)),
+            max_sessions: None,
+            starttls: StartTls::Unavailable,
+            login_disabled: false,
+            quirks: Quirks::default(),
+        }
+    }
+}
+
+struct Shared {
+    config: Config,
+    sessions: AtomicUsize,
+    /// Failed authentications per user.
+    failures: Mutex<HashMap<Vec<u8>, usize>>,
+    /// Mailboxes created by clients (INBOX always exists).
+    mailboxes: Mutex<Vec<String>>,
+}
+
+/// Start a mock server on a random local port and return its address.
+pub(crate) async fn spawn(config: Config) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let host = listener.local_addr().unwrap().to_string();
+
+    let shared = Arc::new(Shared {
+        config,
+        sessions: AtomicUsize::new(0),
+        failures: Mutex::new(HashMap::new()),
+        mailboxes: Mutex::new(Vec::new()),
+    });
+
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            let shared = shared.clone();
+
+            let sessions = shared.sessions.fetch_add(1, Ordering::SeqCst) + 1;
+
+            tokio::spawn(async move {
+                if shared
+                    .config
+                    .max_sessions
+                    .is_some_and(|max_sessions| sessions > max_sessions)
+                {
+                    let _ = socket.write_all(b"* BYE too many sessions\r\n").await;
+                } else {
+                    let tls = Arc::new(AtomicBool::new(false));
+                    let socket = match shared.config.starttls {
+                        StartTls::Working => front(socket, tls.clone()).await,
+                        _ => Ok(socket),
+                    };
+                    if let Ok(socket) = socket {
+                        Connection::new(socket, shared.clone(), tls).run().await;
+                    }
+                }
+                shared.sessions.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+    });
+
+    host
+}
+
+/// Acceptor with the self-signed certificate (for `localhost`) of [`StartTls::Working`].
+fn acceptor() -> TlsAcceptor {
+    let certificate = CertificateDer::from(include_bytes!("../testdata/mock.crt").to_vec());
+    let key = PrivatePkcs8KeyDer::from(include_bytes!("../testdata/mock.key").to_vec());
+    let config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(vec![certificate], key.into())
+        .unwrap();
+
+    TlsAcceptor::from(Arc::new(config))
+}
+
+/// Tag of `line` if it is a STARTTLS command.
+fn starttls_tag(line: &[u8]) -> Option<String> {
+    let (tag, command) = std::str::from_utf8(line).ok()?.trim_end().split_once(' ')?;
+    command
+        .eq_ignore_ascii_case("STARTTLS")
+        .then(|| tag.to_string())
+}
+
+/// Relay between `socket` and the returned socket the connection runs on, answering STARTTLS
+/// on the way and relaying the plain bytes after the handshake. `tls` is set on STARTTLS.
+///
+/// imap-next can't upgrade a connection, so the connection never sees STARTTLS (the server
+/// side of what `transcript::connect` does for clients).
+async fn front(socket: TcpStream, tls: Arc<AtomicBool>) -> io::Result<TcpStream> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let (inner, accepted) = tokio::join!(
+        TcpStream::connect(listener.local_addr()?),
+        listener.accept()
+    );
+    let (inner, (mut outer, _)) = (inner?, accepted?);
+
+    tokio::spawn(async move {
+        let mut client = BufReader::new(socket);
+        let mut line = Vec::new();
+        let mut buffer = vec![0; 64 * 1024];
+
+        let tag = loop {
+            tokio::select! {
+                read = client.read_until(b'\n', &mut line) => {
+                    if !matches!(read, Ok(1..)) {
+                        return;
+                    }
+                    if let Some(tag) = starttls_tag(&line) {
+                        break tag;
+                    }
+                    if outer.write_all(&line).await.is_err() {
+                        return;
+                    }
+                    line.clear();
+                }
+                read = outer.read(&mut buffer) => {
+                    let Ok(read @ 1..) = read else {
+                        return;
+                    };
+                    if client.get_mut().write_all(&buffer[..read]).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        };
+
+        tls.store(true, Ordering::SeqCst);
+        let mut socket = client.into_inner();
+        let response = format!("{tag} OK begin TLS\r\n");
+        if socket.write_all(response.as_bytes()).await.is_err() {
+            return;
+        }
+
+        if let Ok(mut stream) = acceptor().accept(socket).await {
+            let _ = tokio::io::copy_bidirectional(&mut stream, &mut outer).await;
+        }
+    });
+
+    Ok(inner)
+}
+
+fn capability(name: &str) -> Capability<'static> {
+    Capability::from(Atom::try_from(name.to_string()).unwrap())
+}
+
+fn capabilities(names: &[String]) -> Vec1<Capability<'static>> {
+    Vec1::try_from(
+        names
+            .iter()
+            .map(|name| capability(name))
+            .collect::<Vec<_>>(),
+    )
+    .unwrap()
+}
+
+/// Capabilities before authentication, including STARTTLS and LOGINDISABLED (unless `tls` is
+/// already established).
+fn pre_auth_capabilities(config: &Config, tls: bool) -> Vec<String> {
+    let mut names = config.capabilities.clone();
+
+    if config.starttls != StartTls::Unavailable && !tls {
+        names.push("STARTTLS".into());
+    }
+    if config.login_disabled && !tls {
+        names.push("LOGINDISABLED".into());
+    }
+
+    names
+}
+
+fn ok(tag: &Tag<'static>, code: Option<Code<'static>>, text: &str) -> Status<'static> {
+    Status::ok(Some(tag.clone()), code, text.to_string()).unwrap()
+}
+
+fn no(tag: &Tag<'static>, code: Option<Code<'static>>, text: &str) -> Status<'static> {
+    Status::no(Some(tag.clone()), code, text.to_string()).unwrap()
+}
+
+fn bad(tag: Option<&Tag<'static>>, text: &str) -> Status<'static> {
+    Status::bad(tag.cloned(), None, text.to_string()).unwrap()
+}
+
+fn bye(text: &str) -> Status<'static> {
+    Status::bye(None, text.to_string()).unwrap()
+}
+
+fn mailbox_name(mailbox: &Mailbox) -> String {
+    match mailbox {
+        Mailbox::Inbox => "INBOX".into(),
+        Mailbox::Other(other) => String::from_utf8_lossy(other.as_ref()).into_owned(),
+    }
+}
+
+/// Number of arguments of `command` (as encoded).
+fn arguments(command: &Command<'static>) -> usize {
+    let encoded = CommandCodec::default().encode(command).dump();
+
+    encoded
+        .iter()
+        .filter(|byte| **byte == b' ')
+        .count()
+        .saturating_sub(1)
+}
+
+struct Connection {
+    stream: Stream,
+    server: Server,
+    shared: Arc<Shared>,
+    state: State,
+    failures: usize,
+    /// Tag of the AUTHENTICATE command in progress.
+    authenticate: Option<Tag<'static>>,
+    /// Tag of the IDLE command in progress.
+    idle: Option<Tag<'static>>,
+    /// Set once the client completed STARTTLS (see [`front`]).
+    tls: Arc<AtomicBool>,
+}
+
+impl Connection {
+    fn new(socket: TcpStream, shared: Arc<Shared>, tls: Arc<AtomicBool>) -> Self {
+        let config = &shared.config;
+
+        let mut options = Options::default();
+        let _ = options.set_max_command_size(config.max_command);
+        let max_literal = u32::try_from(config.max_literal).unwrap_or(u32::MAX);
+        let _ = options.set_max_literal_size(max_literal.min(config.max_command));
+
+        let capabilities = match &config.quirks.greeting_capabilities {
+            Some(names) => capabilities(names),
+            None => capabilities(&pre_auth_capabilities(config, false)),
+        };
+        let greeting = Greeting::ok(
+            Some(Code::Capability(capabilities)),
+            config.greeting.clone(),
+        )
+        .unwrap();
+
+        Self {
+            stream: Stream::insecure(socket),
+            server: Server::new(options, greeting),
+            shared,
+            state: State::NotAuthenticated,
+            failures: 0,
+            authenticate: None,
+            idle: None,
+            tls,
+        }
+    }
+
+    fn config(&self) -> &Config {
+        &self.shared.config
+    }
+
+    fn tls(&self) -> bool {
+        self.tls.load(Ordering::SeqCst)
+    }
+
+    fn capabilities(&self) -> Vec1<Capability<'static>> {
+        if self.state == State::NotAuthenticated {
+            capabilities(&pre_auth_capabilities(self.config(), self.tls()))
+        } else {
+            capabilities(&self.config().post_auth_capabilities)
+        }
+    }
+
+    /// Send `status` (and everything queued before it), then close. Returns `false`.
+    async fn close(&mut self, status: Status<'static>) -> bool {
+        let handle = self.server.enqueue_status(status);
+
+        while let Ok(event) = self.stream.next(&mut self.server).await {
+            if matches!(event, Event::ResponseSent { handle: sent, .. } if sent == handle) {
+                break;
+            }
+        }
+
+        false
+    }
+
+    /// React to an exceeded limit. Returns whether to continue.
+    async fn reject(&mut self, tag: Option<&Tag<'static>>, text: &str) -> bool {
+        match self.config().rejection {
+            Rejection::Bad => {
+                self.server.enqueue_status(bad(tag, text));
+                true
+            }
+            Rejection::Bye => self.close(bye(text)).await,
+            Rejection::Close => false,
+        }
+    }
+
+    async fn run(mut self) {
+        let wait = self.config().timeout;
+
+        loop {
+            let next = self.stream.next(&mut self.server);
+            let event = match wait {
+                Some(wait) => match timeout(wait, next).await {
+                    Ok(event) => event,
+                    Err(_) => {
+                        self.close(bye("autologout")).await;
+                        return;
+                    }
+                },
+                None => next.await,
+            };
+
+            let proceed = match event {
+                Ok(Event::GreetingSent { .. } | Event::ResponseSent { .. }) => true,
+                Ok(Event::CommandReceived { command }) => self.execute(command).await,
+                Ok(Event::CommandAuthenticateReceived {
+                    command_authenticate,
+                }) => {
+                    self.start_authenticate(
+                        command_authenticate.tag,
+                        command_authenticate.mechanism,
+                        command_authenticate.initial_response,
+                    )
+                    .await
+                }
+                Ok(Event::AuthenticateDataReceived { authenticate_data }) => {
+                    let tag = self.authenticate.take().unwrap();
+                    match authenticate_data {
+                        AuthenticateData::Continue(response) => {
+                            self.authenticate_plain(&tag, response.declassify()).await
+                        }
+                        AuthenticateData::Cancel => {
+                            let _ = self.server.authenticate_finish(bad(Some(&tag), "canceled"));
+                            true
+                        }
+                    }
+                }
+                Ok(Event::IdleCommandReceived { tag }) => {
+                    if !self.valid("IDLE") && !self.config().quirks.ignore_state {
+                        let _ = self.server.idle_reject(bad(Some(&tag), "wrong state"));
+                    } else {
+                        self.idle = Some(tag);
+                        let _ = self.server.idle_accept(
+                            CommandContinuationRequest::basic(None, "idling").unwrap(),
+                        );
+                    }
+                    true
+                }
+                Ok(Event::IdleDoneReceived) => {
+                    let tag = self.idle.take().unwrap();
+                    self.server.enqueue_status(ok(&tag, None, "done"));
+                    true
+                }
+                Err(stream::Error::State(error)) => self.error(error).await,
+                Err(_) => false,
+            };
+
+            if !proceed {
+                return;
+            }
+        }
+    }
+
+    /// React to input imap-next rejected. Returns whether to continue.
+    async fn error(&mut self, error: Error) -> bool {
+        match error {
+            // imap-next already answered with a tagged BAD.
+            Error::LiteralTooLong { .. } => match self.config().rejection {
+                Rejection::Bad => true,
+                Rejection::Bye => self.close(bye("literal too long")).await,
+                Rejection::Close => false,
+            },
+            Error::CommandTooLong { .. } => self.close(bye("line too long")).await,
+            Error::ExpectedCrlfGot { .. } | Error::MalformedMessage { .. }
+                if self.idle.is_some() =>
+            {
+                self.close(bye("expected DONE")).await
+            }
+            Error::ExpectedCrlfGot { discarded_bytes }
+            | Error::MalformedMessage { discarded_bytes } => {
+                self.malformed(discarded_bytes.declassify()).await
+            }
+        }
+    }
+
+    /// Answer a line the codec couldn't parse: tagged (as an unknown command) when it starts
+    /// with a valid tag, rejected otherwise.
+    async fn malformed(&mut self, line: &[u8]) -> bool {
+        let token = line.split(|byte| *byte == b' ').next().unwrap_or_default();
+        let token = token
+            .strip_suffix(b"\n")
+            .map(|token| token.strip_suffix(b"\r").unwrap_or(token))
+            .unwrap_or(token);
+        let tag = std::str::from_utf8(token)
+            .ok()
+            .and_then(|token| Tag::try_from(token.to_string()).ok());
+
+        match tag {
+            Some(tag) if tag.as_ref().len() > self.config().max_tag => {
+                self.reject(None, "invalid tag length").await
+            }
+            Some(tag) => {
+                let text = self.config().unknown_command.clone();
+                self.server.enqueue_status(bad(Some(&tag), &text));
+                true
+            }
+            None => self.reject(None, "invalid tag").await,
+        }
+    }
+
+    /// Whether the command `name` is valid in the current state.
+    fn valid(&self, name: &str) -> bool {
+        match name {
+            "CAPABILITY" | "NOOP" | "LOGOUT" | "ID" => true,
+            "STARTTLS" | "AUTHENTICATE" | "LOGIN" => self.state == State::NotAuthenticated,
+            "SELECT" | "EXAMINE" | "CREATE" | "DELETE" | "RENAME" | "SUBSCRIBE" | "UNSUBSCRIBE"
+            | "LIST" | "LSUB" | "STATUS" | "IDLE" | "ENABLE" | "COMPRESS" => {
+                self.state != State::NotAuthenticated
+            }
+            _ => self.state == State::Selected,
+        }
+    }
+
+    /// Execute a command. Returns whether to continue.
+    async fn execute(&mut self, command: Command<'static>) -> bool {
+        if command.tag.as_ref().len() > self.config().max_tag {
+            return self.reject(None, "invalid tag length").await;
+        }
+
+        let delay = self.config().quirks.quadratic_delay * arguments(&command).pow(2) as u32;
+        if !delay.is_zero() {
+            sleep(delay).await;
+        }
+
+        let Command { tag, body } = command;
+        let name = body.name();
+
+        if name == "STARTTLS" && self.config().starttls == StartTls::Unavailable {
+            let text = self.config().unknown_command.clone();
+            self.server.enqueue_status(bad(Some(&tag), &text));
+            return true;
+        }
+
+        if !self.valid(name) && !self.config().quirks.ignore_state {
+            self.server.enqueue_status(bad(Some(&tag), "wrong state"));
+            return true;
+        }
+
+        match body {
+            CommandBody::Capability => {
+                let capabilities = self.capabilities();
+                self.server.enqueue_data(Data::Capability(capabilities));
+            }
+            CommandBody::Logout => {
+                self.server.enqueue_status(bye("logging out"));
+                return self.close(ok(&tag, None, "logout")).await;
+            }
+            CommandBody::Id { .. } => {
+                let parameters = self
+                    .config()
+                    .id
+                    .iter()
+                    .map(|(key, value)| {
+                        (
+                            IString::try_from(key.clone()).unwrap(),
+                            NString(Some(IString::try_from(value.clone()).unwrap())),
+                        )
+                    })
+                    .collect();
+                self.server.enqueue_data(Data::Id {
+                    parameters: Some(parameters),
+                });
+            }
+            CommandBody::StartTLS => match self.config().starttls {
+                StartTls::Refused => {
+                    self.server
+                        .enqueue_status(no(&tag, None, "TLS not available"));
+                    return true;
+                }
+                // The front answers STARTTLS, so this is a second one.
+                StartTls::Working => {
+                    self.server
+                        .enqueue_status(bad(Some(&tag), "TLS already active"));
+                    return true;
+                }
+                _ => return self.close(ok(&tag, None, "begin TLS")).await,
+            },
+            CommandBody::Login { .. }
+                if self.config().login_disabled
+                    && !self.tls()
+                    && !self.config().quirks.ignore_login_disabled =>
+            {
+                let code = Some(Code::PrivacyRequired);
+                self.server.enqueue_status(no(&tag, code, "use STARTTLS"));
+                return true;
+            }
+            CommandBody::Login { username, password } => {
+                let (status, proceed) = self
+                    .authenticate(&tag, username.as_ref(), password.declassify().as_ref())
+                    .await;
+                self.server.enqueue_status(status);
+                return proceed || self.close(bye("too many failures")).await;
+            }
+            CommandBody::Select { mailbox, .. } | CommandBody::Examine { mailbox, .. } => {
+                let mailbox = mailbox_name(&mailbox);
+                if mailbox != "INBOX" && !self.shared.mailboxes.lock().unwrap().contains(&mailbox) {
+                    self.server
+                        .enqueue_status(no(&tag, None, "no such mailbox"));
+                    return true;
+                }
+
+                self.server.enqueue_data(Data::Exists(0));
+                self.server.enqueue_data(Data::Flags(Vec::new()));
+                self.state = State::Selected;
+            }
+            CommandBody::Create { mailbox } => {
+                let mailbox = mailbox_name(&mailbox);
+                let mut mailboxes = self.shared.mailboxes.lock().unwrap();
+                if mailbox == "INBOX" || mailboxes.contains(&mailbox) {
+                    self.server.enqueue_status(no(&tag, None, "mailbox exists"));
+                    return true;
+                }
+                mailboxes.push(mailbox);
+            }
+            CommandBody::Delete { mailbox } => {
+                let mailbox = mailbox_name(&mailbox);
+                let mut mailboxes = self.shared.mailboxes.lock().unwrap();
+                let Some(index) = mailboxes.iter().position(|name| *name == mailbox) else {
+                    self.server
+                        .enqueue_status(no(&tag, None, "no such mailbox"));
+                    return true;
+                };
+                mailboxes.remove(index);
+            }
+            _ if name == "COMPRESS" => {
+                self.server
+                    .enqueue_status(no(&tag, None, "compression not supported"));
+                return true;
+            }
+            _ => {}
+        }
+
+        self.server.enqueue_status(ok(&tag, None, "done"));
+        true
+    }
+
+    /// Start an AUTHENTICATE. Returns whether to continue.
+    async fn start_authenticate(
+        &mut self,
+        tag: Tag<'static>,
+        mechanism: AuthMechanism<'static>,
+        initial_response: Option<Secret<Cow<'static, [u8]>>>,
+    ) -> bool {
+        if !self.valid("AUTHENTICATE") && !self.config().quirks.ignore_state {
+            let _ = self
+                .server
+                .authenticate_finish(bad(Some(&tag), "wrong state"));
+            return true;
+        }
+
+        if mechanism != AuthMechanism::Plain {
+            let _ = self
+                .server
+                .authenticate_finish(no(&tag, None, "unsupported mechanism"));
+            return true;
+        }
+
+        let sasl_ir = self
+            .config()
+            .capabilities
+            .iter()
+            .any(|name| name.eq_ignore_ascii_case("SASL-IR"));
+
+        match initial_response {
+            Some(_) if !sasl_ir => {
+                let _ = self
+                    .server
+                    .authenticate_finish(bad(Some(&tag), "SASL-IR not advertised"));
+                true
+            }
+            Some(response) => self.authenticate_plain(&tag, response.declassify()).await,
+            None => {
+                self.authenticate = Some(tag);
+                let _ = self
+                    .server
+                    .authenticate_continue(CommandContinuationRequest::basic(None, " ").unwrap());
+                true
+            }
+        }
+    }
+
+    /// Finish an AUTHENTICATE PLAIN with `response`. Returns whether to continue.
+    async fn authenticate_plain(&mut self, tag: &Tag<'static>, response: &[u8]) -> bool {
+        let mut fields = response.split(|byte| *byte == 0).skip(1);
+        let username = fields.next().unwrap_or_default().to_vec();
+        let password = fields.next().unwrap_or_default().to_vec();
+
+        let (status, proceed) = self.authenticate(tag, &username, &password).await;
+        let _ = self.server.authenticate_finish(status);
+        proceed || self.close(bye("too many failures")).await
+    }
+
+    /// Check credentials. Returns the tagged status and whether to continue.
+    async fn authenticate(
+        &mut self,
+        tag: &Tag<'static>,
+        username: &[u8],
+        password: &[u8],
+    ) -> (Status<'static>, bool) {
+        let config = self.config();
+
+        let user = config
+            .users
+            .iter()
+            .find(|(candidate, _)| candidate.as_bytes() == username);
+        let locked = config.quirks.lockout_after.is_some_and(|lockout_after| {
+            self.shared
+                .failures
+                .lock()
+                .unwrap()
+                .get(username)
+                .is_some_and(|failures| *failures >= lockout_after)
+        });
+
+        if !locked && user.is_some_and(|(_, expected)| expected.as_bytes() == password) {
+            self.state = State::Authenticated;
+            let capabilities = match &self.config().quirks.login_capabilities {
+                Some(names) => capabilities(names),
+                None => self.capabilities(),
+            };
+            let code = Code::Capability(capabilities);
+            return (ok(tag, Some(code), "logged in"), true);
+        }
+
+        let (code, text) = if locked {
+            (Some(Code::Unavailable), "account locked")
+        } else if user.is_none() && config.quirks.reveal_unknown_users {
+            (None, "unknown user")
+        } else {
+            (None, "invalid credentials")
+        };
+        let max_failed_logins = config.quirks.max_failed_logins;
+        let mut delay = if self.failures >= config.quirks.delay_after {
+            config.quirks.failed_login_delay
+        } else {
+            Duration::ZERO
+        };
+        if user.is_some() {
+            delay += config.quirks.known_user_delay;
+        }
+
+        *self
+            .shared
+            .failures
+            .lock()
+            .unwrap()
+            .entry(username.to_vec())
+            .or_default() += 1;
+        self.failures += 1;
+
+        sleep(delay).await;
+
+        let proceed = !max_failed_logins.is_some_and(|max| self.failures >= max);
+        (no(tag, code, text), proceed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use imap_types::command::CommandBody;
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::TcpStream,
+    };
+
+    use super::{spawn, Config, Rejection, StartTls};
+    use crate::{
+        session::{Outcome, Session},
+        tls,
+        transport::Tls,
+    };
+
+    async fn exchange(host: &str, data: &[u8], lines: usize) -> Vec<String> {
+        let (reader, mut writer) = TcpStream::connect(host).await.unwrap().into_split();
+        let mut reader = BufReader::new(reader).lines();
+
+        let mut received = vec![reader.next_line().await.unwrap().unwrap()];
+        writer.write_all(data).await.unwrap();
+        while received.len() < lines + 1 {
+            match reader.next_line().await {
+                Ok(Some(line)) => received.push(line),
+                _ => break,
+            }
+        }
+
+        received
+    }
+
+    #[tokio::test]
+    async fn test_limits() {
+        let host = spawn(Config {
+            max_tag: 4,
+            max_literal: 5,
+            ..Default::default()
+        })
+        .await;
+
+        let received = exchange(&host, b"AAAA NOOP\r\nAAAAA NOOP\r\nA+ NOOP\r\n", 3).await;
+        assert_eq!(
+            received[1..],
+            [
+                "AAAA OK done",
+                "* BAD invalid tag length",
+                "* BAD invalid tag"
+            ]
+        );
+
+        let received = exchange(&host, b"F IMAPSECUNKNOWN\r\n", 1).await;
+        assert_eq!(received[1..], ["F BAD Unknown command"]);
+
+        let received = exchange(
+            &host,
+            b"A LOGIN {6}\r\nB LOGIN {5}\r\nalice password\r\n",
+            3,
+        )
+        .await;
+        assert!(received[1].starts_with("A BAD "), "{received:?}");
+        assert!(received[2].starts_with("+ "), "{received:?}");
+        assert_eq!(
+            received[3],
+            "B OK [CAPABILITY IMAP4REV1 LITERAL+ ID ENABLE IDLE MOVE] logged in"
+        );
+
+        let host = spawn(Config {
+            max_tag: 4,
+            rejection: Rejection::Bye,
+            ..Default::default()
+        })
+        .await;
+
+        let received = exchange(&host, b"AAAAA NOOP\r\n", 1).await;
+        assert_eq!(received[1..], ["* BYE invalid tag length"]);
+    }
+
+    #[tokio::test]
+    async fn test_sessions() {
+        let host = spawn(Config {
+            max_sessions: Some(1),
+            ..Default::default()
+        })
+        .await;
+
+        let _first = TcpStream::connect(&host).await.unwrap();
+        let received = exchange(&host, b"", 0).await;
+        assert_eq!(received, ["* BYE too many sessions"]);
+
+        let host = spawn(Config {
+            timeout: Some(Duration::from_millis(200)),
+            starttls: StartTls::Refused,
+            login_disabled: true,
+            ..Default::default()
+        })
+        .await;
+
+        let received = exchange(&host, b"A STARTTLS\r\nB LOGIN alice password\r\n", 3).await;
+        assert_eq!(
+            received,
+            [
+                "* OK [CAPABILITY IMAP4REV1 LITERAL+ ID ENABLE IDLE AUTH=PLAIN STARTTLS \
+                 LOGINDISABLED] imap-sec mock ready",
+                "A NO TLS not available",
+                "B NO [PRIVACYREQUIRED] use STARTTLS",
+                "* BYE autologout",
+            ]
+        );
+
+        let host = spawn(Config {
+            starttls: StartTls::Broken,
+            rejection: Rejection::Close,
+            ..Default::default()
+        })
+        .await;
+
+        let received = exchange(&host, b"A STARTTLS\r\n", 1).await;
+        assert_eq!(received[1..], ["A OK begin TLS"]);
+
+        let received = exchange(&host, b"A\x01 NOOP\r\n", 1).await;
+        assert_eq!(received.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_starttls() {
+        let host = spawn(Config {
+            starttls: StartTls::Working,
+            login_disabled: true,
+            ..Default::default()
+        })
+        .await;
+
+        let capabilities = tls::capabilities_after_starttls(&host, Duration::from_secs(5))
+            .await
+            .unwrap();
+        let names: Vec<_> = capabilities.iter().map(ToString::to_string).collect();
+        assert!(names.contains(&"AUTH=PLAIN".to_string()), "{names:?}");
+        assert!(!names.contains(&"STARTTLS".to_string()), "{names:?}");
+        assert!(!names.contains(&"LOGINDISABLED".to_string()), "{names:?}");
+
+        let mut session = Session::connect_and_login(
+            &host,
+            Tls::StartTls,
+            "alice",
+            "password",
+            Duration::from_secs(5),
+        )
+        .await
+        .unwrap();
+        let reply = session.command("N", CommandBody::Noop).await;
+        assert_eq!(reply.outcome, Outcome::Ok, "{}", reply.line());
+
+        let received = exchange(&host, b"A LOGIN alice password\r\n", 1).await;
+        assert_eq!(received[1..], ["A NO [PRIVACYREQUIRED] use STARTTLS"]);
+    }
+}
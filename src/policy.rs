@@ -0,0 +1,310 @@
+use std::{error::Error, fmt, path::Path};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{finding::Severity, report::Report};
+
+/// Format version of the policy file this build understands.
+pub const VERSION: u32 = 1;
+
+/// A single assertion over a scan result.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rule {
+    /// Upper bound for the literal length accepted before authentication (`max_literal`).
+    MaxLiteralPreauth(u64),
+    /// Upper bound for the tag length (`max_tag`).
+    MaxTag(u64),
+    /// STARTTLS is advertised before authentication (`info` or `fingerprint`).
+    StarttlsRequired,
+    /// AUTH=PLAIN is not advertised before TLS (`info` or `fingerprint`).
+    NoAuthPlainWithoutTls,
+    /// Credentials are not accepted without TLS (`info`).
+    NoPlaintextCredentials,
+    /// A capability is advertised before authentication (`fingerprint`).
+    CapabilityRequired(String),
+    /// A capability is not advertised before authentication (`fingerprint`).
+    CapabilityForbidden(String),
+    /// All tag characters in `from..=to` are rejected (`allowed_tag`).
+    TagCharactersRejected { from: u8, to: u8 },
+    /// No finding is more severe than this (any probe).
+    MaxSeverity(Severity),
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MaxLiteralPreauth(length) => write!(f, "max_literal_preauth <= {length}"),
+            Self::MaxTag(length) => write!(f, "max_tag <= {length}"),
+            Self::StarttlsRequired => write!(f, "STARTTLS required"),
+            Self::NoAuthPlainWithoutTls => write!(f, "no AUTH=PLAIN without TLS"),
+            Self::NoPlaintextCredentials => write!(f, "no credentials without TLS"),
+            Self::CapabilityRequired(name) => write!(f, "{name} required"),
+            Self::CapabilityForbidden(name) => write!(f, "{name} forbidden"),
+            Self::TagCharactersRejected { from, to } => {
+                write!(f, "tag characters 0x{from:02x}-0x{to:02x} rejected")
+            }
+            Self::MaxSeverity(severity) => write!(f, "no finding above {severity}"),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    pub version: u32,
+    pub rules: Vec<Rule>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Verdict {
+    pub rule: String,
+    pub passed: bool,
+    /// Why the rule failed (one entry per target), or which targets were checked.
+    pub details: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Evaluation {
+    pub verdicts: Vec<Verdict>,
+    pub violations: usize,
+}
+
+impl fmt::Display for Evaluation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for verdict in &self.verdicts {
+            writeln!(
+                f,
+                "{} {}",
+                if verdict.passed { "PASS" } else { "FAIL" },
+                verdict.rule
+            )?;
+            if !verdict.passed {
+                for detail in &verdict.details {
+                    writeln!(f, "  {detail}")?;
+                }
+            }
+        }
+
+        write!(f, "{} violation(s)", self.violations)
+    }
+}
+
+fn capabilities(report: &Report<Value>) -> Option<Vec<&str>> {
+    report
+        .outcome
+        .pointer("/features/capabilities")?
+        .as_array()?
+        .iter()
+        .map(Value::as_str)
+        .collect()
+}
+
+/// Result of a rule for a single report, `None` if the report doesn't tell.
+fn check(rule: &Rule, report: &Report<Value>) -> Option<Result<(), String>> {
+    let limit = |probe: &str, max: u64| {
+        if report.probe != probe {
+            return None;
+        }
+
+        let limit = report.outcome.get("limit")?.as_u64()?;
+        Some(if limit <= max {
+            Ok(())
+        } else {
+            Err(format!("{probe} is {limit}"))
+        })
+    };
+
+    let plaintext = |field: &str| {
+        report
+            .outcome
+            .pointer(&format!("/plaintext/{field}"))?
+            .as_bool()
+    };
+
+    let has = |name: &str| {
+        capabilities(report).map(|capabilities| {
+            capabilities
+                .iter()
+                .any(|capability| capability.eq_ignore_ascii_case(name))
+        })
+    };
+
+    let expect = |ok: bool, violation: &str| {
+        if ok {
+            Ok(())
+        } else {
+            Err(violation.to_string())
+        }
+    };
+
+    match rule {
+        Rule::MaxLiteralPreauth(max) => limit("max_literal", *max),
+        Rule::MaxTag(max) => limit("max_tag", *max),
+        Rule::StarttlsRequired => {
+            let starttls = plaintext("starttls").or_else(|| has("STARTTLS"))?;
+            Some(expect(starttls, "STARTTLS not advertised"))
+        }
+        Rule::NoAuthPlainWithoutTls => {
+            let auth_plain = plaintext("auth_plain").or_else(|| has("AUTH=PLAIN"))?;
+            Some(expect(!auth_plain, "AUTH=PLAIN advertised before TLS"))
+        }
+        Rule::NoPlaintextCredentials => Some(expect(
+            !plaintext("exposed")?,
+            "credentials are accepted without TLS",
+        )),
+        Rule::CapabilityRequired(name) => {
+            Some(expect(has(name)?, &format!("{name} not advertised")))
+        }
+        Rule::CapabilityForbidden(name) => Some(expect(!has(name)?, &format!("{name} advertised"))),
+        Rule::TagCharactersRejected { from, to } => {
+            if report.probe != "allowed_tag" {
+                return None;
+            }
+
+            let accepted = report
+                .outcome
+                .as_array()?
+                .iter()
+                .filter(|test| test.get(2).and_then(Value::as_str) == Some("reflected"))
+                .filter_map(|test| test.get(0)?.as_u64())
+                .filter(|dec| (u64::from(*from)..=u64::from(*to)).contains(dec))
+                .map(|dec| format!("0x{dec:02x}"))
+                .collect::<Vec<_>>();
+
+            Some(expect(
+                accepted.is_empty(),
+                &format!("accepted {}", accepted.join(" ")),
+            ))
+        }
+        Rule::MaxSeverity(max) => {
+            let above = report
+                .findings
+                .iter()
+                .filter(|finding| finding.severity > *max)
+                .map(|finding| format!("[{}] {}", finding.severity, finding.id))
+                .collect::<Vec<_>>();
+
+            Some(expect(above.is_empty(), &above.join(", ")))
+        }
+    }
+}
+
+impl Policy {
+    pub fn parse(data: &str) -> Result<Self, Box<dyn Error>> {
+        let policy: Self = serde_json::from_str(data)?;
+
+        if policy.version != VERSION {
+            return Err(format!(
+                "unsupported policy version {} (expected {VERSION})",
+                policy.version
+            )
+            .into());
+        }
+
+        Ok(policy)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Evaluate all rules against `reports`.
+    ///
+    /// A rule that no report can decide is a violation, so a policy can't pass by accident
+    /// when a probe is missing from the scan.
+    pub fn evaluate(&self, reports: &[Report<Value>]) -> Evaluation {
+        let mut verdicts = Vec::new();
+
+        for rule in &self.rules {
+            let mut checked = Vec::new();
+            let mut violations = Vec::new();
+
+            for report in reports {
+                match check(rule, report) {
+                    Some(Ok(())) => checked.push(format!("{} ({})", report.target, report.probe)),
+                    Some(Err(violation)) => {
+                        violations.push(format!("{}: {violation}", report.target))
+                    }
+                    None => {}
+                }
+            }
+
+            let passed = violations.is_empty() && !checked.is_empty();
+            if checked.is_empty() && violations.is_empty() {
+                violations.push("no result can decide this rule".into());
+            }
+
+            verdicts.push(Verdict {
+                rule: rule.to_string(),
+                passed,
+                details: if passed { checked } else { violations },
+            });
+        }
+
+        Evaluation {
+            violations: verdicts.iter().filter(|verdict| !verdict.passed).count(),
+            verdicts,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Policy;
+    use crate::report::parse;
+
+    const POLICY: &str = r#"{
+        "version": 1,
+        "rules": [
+            { "max_literal_preauth": 65536 },
+            "starttls_required",
+            "no_auth_plain_without_tls",
+            { "tag_characters_rejected": { "from": 0, "to": 31 } },
+            { "max_severity": "medium" },
+            "no_plaintext_credentials"
+        ]
+    }"#;
+
+    const RESULTS: &str = r#"
+        {"probe": "max_literal", "target": "a:143", "started_ms": 0, "finished_ms": 1,
+         "parameters": {}, "outcome": {"min": 0, "max": 4294967295, "limit": 4294967295, "steps": []},
+         "findings": [{"id": "preauth-literal", "title": "", "severity": "high", "host": "a:143",
+                       "evidence": "", "remediation": ""}]}
+        {"probe": "allowed_tag", "target": "a:143", "started_ms": 0, "finished_ms": 1,
+         "parameters": {}, "outcome": [[1, "\u0001", "reflected"], [66, "B", "reflected"]]}
+        {"probe": "fingerprint", "target": "a:143", "started_ms": 0, "finished_ms": 1,
+         "parameters": {}, "outcome": {"features": {"capabilities": ["IMAP4rev1", "STARTTLS"]}}}
+    "#;
+
+    #[test]
+    fn test_evaluate() {
+        let policy = Policy::parse(POLICY).unwrap();
+        let evaluation = policy.evaluate(&parse(RESULTS).unwrap());
+
+        let passed = evaluation
+            .verdicts
+            .iter()
+            .map(|verdict| verdict.passed)
+            .collect::<Vec<_>>();
+        assert_eq!(passed, [false, true, true, false, false, false]);
+        assert_eq!(evaluation.violations, 4);
+
+        assert_eq!(
+            evaluation.verdicts[0].details,
+            ["a:143: max_literal is 4294967295"]
+        );
+        assert_eq!(evaluation.verdicts[3].details, ["a:143: accepted 0x01"]);
+        assert_eq!(
+            evaluation.verdicts[5].details,
+            ["no result can decide this rule"]
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        assert!(Policy::parse(r#"{"version": 2, "rules": []}"#).is_err());
+        assert!(Policy::parse(r#"{"version": 1, "rules": [{"unknown": 1}]}"#).is_err());
+    }
+}
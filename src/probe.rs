@@ -0,0 +1,1296 @@
+use std::{error::Error, fmt, future::Future, path::PathBuf, pin::Pin, time::Duration};
+
+use imap_types::utils::escape_byte_string;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use crate::{
+    complexity::{self, Shape},
+    conformance, enumeration,
+    finding::{self, Finding},
+    fingerprint,
+    learn::{self, AllowedResult},
+    report::{self, Report, Run},
+    signatures,
+    supervisor::{Supervisor, Usage},
+    throttle,
+    transport::Tls,
+};
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + 'a>>;
+
+/// State a probe needs to reach on the target.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RequiredState {
+    NotAuthenticated,
+    /// The target needs a username and a password.
+    Authenticated,
+}
+
+/// A parameter of a probe.
+#[derive(Clone, Debug, Serialize)]
+pub struct Parameter {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Used when the parameter is not given, `None` if it is required.
+    pub default: Option<Value>,
+}
+
+impl Parameter {
+    pub fn new(name: &'static str, description: &'static str, default: impl Into<Value>) -> Self {
+        Self {
+            name,
+            description,
+            default: Some(default.into()),
+        }
+    }
+
+    pub fn required(name: &'static str, description: &'static str) -> Self {
+        Self {
+            name,
+            description,
+            default: None,
+        }
+    }
+}
+
+/// Server a probe runs against.
+#[derive(Clone, Debug, Default)]
+pub struct Target {
+    /// `host:port`
+    pub host: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub tls: Tls,
+}
+
+impl Target {
+    fn credentials(&self) -> Result<(&str, &str), Box<dyn Error>> {
+        match (&self.username, &self.password) {
+            (Some(username), Some(password)) => Ok((username, password)),
+            _ => Err("credentials required".into()),
+        }
+    }
+}
+
+/// Permission to run dangerous probes (see [`Probe::dangerous`]).
+///
+/// The default refuses every target.
+#[derive(Clone, Debug, Default)]
+pub struct Guard {
+    /// The operator confirmed that dangerous probes may take the target down.
+    pub understood: bool,
+    /// Targets dangerous probes may run against: `host`, `host:port`, or `*.domain`.
+    pub allowlist: Vec<String>,
+}
+
+impl Guard {
+    /// Whether dangerous probes may run against `host`, and if not, why.
+    pub fn check(&self, host: &str) -> Result<(), String> {
+        if !self.understood {
+            return Err("dangerous, requires --i-understand".into());
+        }
+
+        if !self.allowlist.iter().any(|pattern| allowed(pattern, host)) {
+            return Err(format!(
+                "dangerous, {host} is not in the allowlist (--allow)"
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+fn allowed(pattern: &str, host: &str) -> bool {
+    let name = match host.rsplit_once(':') {
+        Some((name, port)) if port.parse::<u16>().is_ok() => name,
+        _ => host,
+    };
+
+    if pattern.eq_ignore_ascii_case(host) || pattern.eq_ignore_ascii_case(name) {
+        return true;
+    }
+
+    match pattern.strip_prefix("*.") {
+        Some(domain) => name
+            .to_ascii_lowercase()
+            .ends_with(&format!(".{}", domain.to_ascii_lowercase())),
+        None => false,
+    }
+}
+
+/// What a probe returns: its outcome and the interpretation of it.
+#[derive(Debug)]
+pub struct Output {
+    pub outcome: Value,
+    pub findings: Vec<Finding>,
+}
+
+impl Output {
+    pub fn new(outcome: &impl Serialize, findings: Vec<Finding>) -> Result<Self, Box<dyn Error>> {
+        Ok(Self {
+            outcome: serde_json::to_value(outcome)?,
+            findings,
+        })
+    }
+}
+
+/// A check that can be run through the [`Registry`].
+pub trait Probe {
+    fn name(&self) -> &'static str;
+
+    /// One line, shown when probes are listed.
+    fn description(&self) -> &'static str;
+
+    fn state(&self) -> RequiredState {
+        RequiredState::NotAuthenticated
+    }
+
+    fn parameters(&self) -> Vec<Parameter> {
+        Vec::new()
+    }
+
+    /// Whether the probe can harm the target (e.g., exhaust its memory). Dangerous probes are
+    /// only part of a scan when explicitly enabled, and only run when the [`Guard`] permits.
+    fn dangerous(&self) -> bool {
+        false
+    }
+
+    /// Adjust `parameters` (defaults filled in) to the `supervisor` watching the server, before
+    /// the probe runs.
+    fn supervise(&self, _parameters: &mut Map<String, Value>, _supervisor: &Supervisor) {}
+
+    /// Run against `target`. `parameters` is an object with every parameter (defaults filled in).
+    fn run<'a>(
+        &'a self,
+        target: &'a Target,
+        parameters: &'a Value,
+    ) -> BoxFuture<'a, Result<Output, Box<dyn Error>>>;
+
+    /// Bytes the client sent in its largest message (e.g., a literal), to relate the memory of
+    /// a supervised server to (see [`Usage::amplify`]).
+    fn client_bytes(&self, _outcome: &Value) -> Option<u64> {
+        None
+    }
+
+    /// Text rendering of the outcome.
+    fn text(&self, outcome: &Value) -> String {
+        report::json(outcome)
+    }
+}
+
+/// Description of a probe (for listings).
+#[derive(Debug, Serialize)]
+pub struct Description {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub state: RequiredState,
+    pub dangerous: bool,
+    pub parameters: Vec<Parameter>,
+}
+
+impl fmt::Display for Description {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = match self.state {
+            RequiredState::NotAuthenticated => "",
+            RequiredState::Authenticated => " (requires credentials)",
+        };
+        let dangerous = if self.dangerous { " (dangerous)" } else { "" };
+        write!(f, "{}{state}{dangerous}\n  {}", self.name, self.description)?;
+
+        for parameter in &self.parameters {
+            let default = match &parameter.default {
+                Some(default) => format!("default {default}"),
+                None => "required".into(),
+            };
+            write!(
+                f,
+                "\n  -p {}=...  {} ({default})",
+                parameter.name, parameter.description
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Known probes, in the order they were registered.
+#[derive(Default)]
+pub struct Registry {
+    probes: Vec<Box<dyn Probe>>,
+}
+
+impl Registry {
+    /// Registry with all probes of this crate.
+    pub fn builtin() -> Self {
+        let mut registry = Self::default();
+
+        registry.register(Info);
+        registry.register(MaxTag);
+        registry.register(MaxLiteral);
+        registry.register(AllowedTag);
+        registry.register(NonSyncLiteral);
+        #[cfg(feature = "dangerous")]
+        registry.register(OutOfMemory);
+        registry.register(Conformance);
+        registry.register(AuthThrottle);
+        registry.register(Enumeration);
+        registry.register(Fingerprint);
+        registry.register(Complexity);
+
+        registry
+    }
+
+    /// Add `probe`, replacing a probe with the same name.
+    pub fn register(&mut self, probe: impl Probe + 'static) {
+        self.probes
+            .retain(|registered| registered.name() != probe.name());
+        self.probes.push(Box::new(probe));
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn Probe> {
+        self.probes
+            .iter()
+            .find(|probe| probe.name() == name)
+            .map(|probe| probe.as_ref())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Probe> {
+        self.probes.iter().map(|probe| probe.as_ref())
+    }
+
+    pub fn describe(&self) -> Vec<Description> {
+        self.iter()
+            .map(|probe| Description {
+                name: probe.name(),
+                description: probe.description(),
+                state: probe.state(),
+                dangerous: probe.dangerous(),
+                parameters: probe.parameters(),
+            })
+            .collect()
+    }
+
+    /// Run the probe `name` (see [`run`]).
+    pub async fn run(
+        &self,
+        name: &str,
+        target: &Target,
+        parameters: Map<String, Value>,
+        guard: &Guard,
+        supervisor: Option<&Supervisor>,
+    ) -> Result<Report<Value>, Box<dyn Error>> {
+        let probe = self
+            .get(name)
+            .ok_or_else(|| format!("unknown probe `{name}` (see `imap-sec probes`)"))?;
+
+        run(probe, target, parameters, guard, supervisor).await
+    }
+
+    /// Text rendering of the outcome of `report` (see [`Probe::text`]), followed by the resource
+    /// usage of the server (if watched).
+    pub fn text(&self, report: &Report<Value>) -> String {
+        let text = match self.get(&report.probe) {
+            Some(probe) => probe.text(&report.outcome),
+            None => report::json(&report.outcome),
+        };
+
+        match &report.server {
+            Some(usage) => format!("{text}\n{usage}"),
+            None => text,
+        }
+    }
+}
+
+/// Parse a `name=value` parameter. The value is JSON if it parses as JSON, a string otherwise.
+pub fn parameter(text: &str) -> Result<(String, Value), String> {
+    let (name, value) = text
+        .split_once('=')
+        .ok_or_else(|| format!("invalid parameter `{text}` (expected name=value)"))?;
+    let value = serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.into()));
+
+    Ok((name.to_string(), value))
+}
+
+/// Run `probe` against `target` with `parameters` (missing ones take their default).
+///
+/// Dangerous probes only run when `guard` permits. With a `supervisor`, the report includes the
+/// resource usage of the server process.
+pub async fn run(
+    probe: &dyn Probe,
+    target: &Target,
+    mut parameters: Map<String, Value>,
+    guard: &Guard,
+    supervisor: Option<&Supervisor>,
+) -> Result<Report<Value>, Box<dyn Error>> {
+    let name = probe.name();
+    let known = probe.parameters();
+
+    if probe.dangerous() {
+        guard
+            .check(&target.host)
+            .map_err(|error| format!("{name}: {error}"))?;
+    }
+
+    if let Some(unknown) = parameters.keys().find(|given| {
+        !known
+            .iter()
+            .any(|parameter| parameter.name == given.as_str())
+    }) {
+        return Err(format!("{name}: unknown parameter `{unknown}`").into());
+    }
+
+    for parameter in known {
+        if parameters.contains_key(parameter.name) {
+            continue;
+        }
+
+        let default = parameter
+            .default
+            .ok_or_else(|| format!("{name}: parameter `{}` is required", parameter.name))?;
+        parameters.insert(parameter.name.into(), default);
+    }
+
+    if let Some(supervisor) = supervisor {
+        probe.supervise(&mut parameters, supervisor);
+    }
+
+    if probe.state() == RequiredState::Authenticated {
+        target
+            .credentials()
+            .map_err(|error| format!("{name}: {error}"))?;
+    }
+
+    let mut recorded = parameters.clone();
+    recorded.insert("host".into(), target.host.clone().into());
+    if target.tls != Tls::None {
+        recorded.insert("tls".into(), serde_json::to_value(target.tls)?);
+    }
+    // A `username` parameter (e.g., of auth_throttle) is what the probe used.
+    if let Some(username) = &target.username {
+        recorded
+            .entry("username")
+            .or_insert_with(|| username.clone().into());
+    }
+
+    let parameters = Value::Object(parameters);
+    let run = Run::start(name, &target.host, &recorded);
+    let (output, usage) = match supervisor {
+        Some(supervisor) => {
+            let (output, usage) = supervisor.monitor(probe.run(target, &parameters)).await;
+            (output, Some(usage))
+        }
+        None => (probe.run(target, &parameters).await, None),
+    };
+
+    let Output {
+        outcome,
+        mut findings,
+    } = match (output, &usage) {
+        (Ok(output), _) => output,
+        (
+            Err(error),
+            Some(Usage {
+                exit: Some(exit), ..
+            }),
+        ) => {
+            return Err(format!("{error} (server exited: {exit})").into());
+        }
+        (Err(error), _) => return Err(error),
+    };
+
+    let usage = usage.map(|mut usage| {
+        if let Some(bytes) = probe.client_bytes(&outcome) {
+            usage.amplify(bytes);
+        }
+        findings.extend(finding::server(&target.host, name, &usage));
+        usage
+    });
+
+    let mut report = run.finish(outcome, |_, _| findings);
+    report.server = usage;
+
+    Ok(report)
+}
+
+// ----- Built-in probes
+
+fn timeout(parameters: &Value) -> Result<Duration, Box<dyn Error>> {
+    let seconds = parameters["timeout"]
+        .as_u64()
+        .ok_or("`timeout` must be a number of seconds")?;
+
+    Ok(Duration::from_secs(seconds))
+}
+
+fn timeout_parameter(default: u64) -> Parameter {
+    Parameter::new("timeout", "seconds to wait for a response", default)
+}
+
+#[derive(Deserialize)]
+struct Bounds {
+    min: u64,
+    max: u64,
+}
+
+impl Bounds {
+    /// `min` and `max` of `parameters`, which bisection needs in order.
+    fn parse(parameters: &Value) -> Result<Self, Box<dyn Error>> {
+        let bounds = Self::deserialize(parameters)?;
+        if bounds.min > bounds.max {
+            return Err(format!(
+                "`min` ({}) must not be greater than `max` ({})",
+                bounds.min, bounds.max
+            )
+            .into());
+        }
+
+        Ok(bounds)
+    }
+}
+
+struct Info;
+
+impl Probe for Info {
+    fn name(&self) -> &'static str {
+        "info"
+    }
+
+    fn description(&self) -> &'static str {
+        "Learn capabilities and ID (after authentication when credentials are given)"
+    }
+
+    fn parameters(&self) -> Vec<Parameter> {
+        vec![timeout_parameter(30)]
+    }
+
+    fn run<'a>(
+        &'a self,
+        target: &'a Target,
+        parameters: &'a Value,
+    ) -> BoxFuture<'a, Result<Output, Box<dyn Error>>> {
+        Box::pin(async move {
+            let info = learn::info(
+                target.host.clone(),
+                target.tls,
+                target.username.clone(),
+                target.password.clone(),
+                timeout(parameters)?,
+            )
+            .await?;
+            Output::new(&info, finding::info(&target.host, &info))
+        })
+    }
+}
+
+struct MaxTag;
+
+impl Probe for MaxTag {
+    fn name(&self) -> &'static str {
+        "max_tag"
+    }
+
+    fn description(&self) -> &'static str {
+        "Learn max tag length (through NOOP command)"
+    }
+
+    fn parameters(&self) -> Vec<Parameter> {
+        vec![
+            Parameter::new("min", "shortest tag length to assume", 0),
+            Parameter::new("max", "longest tag length to test", 1024 * 1024),
+            timeout_parameter(30),
+        ]
+    }
+
+    fn run<'a>(
+        &'a self,
+        target: &'a Target,
+        parameters: &'a Value,
+    ) -> BoxFuture<'a, Result<Output, Box<dyn Error>>> {
+        Box::pin(async move {
+            let Bounds { min, max } = Bounds::parse(parameters)?;
+            let limit =
+                learn::max_tag(&target.host, target.tls, min, max, timeout(parameters)?).await?;
+            Output::new(&limit, finding::max_tag(&target.host, &limit))
+        })
+    }
+
+    fn client_bytes(&self, outcome: &Value) -> Option<u64> {
+        outcome["limit"].as_u64()
+    }
+
+    fn text(&self, outcome: &Value) -> String {
+        format!("Maximum tag length: {}", outcome["limit"])
+    }
+}
+
+struct MaxLiteral;
+
+impl Probe for MaxLiteral {
+    fn name(&self) -> &'static str {
+        "max_literal"
+    }
+
+    fn description(&self) -> &'static str {
+        "Learn max literal length (through user astring in LOGIN command)"
+    }
+
+    fn parameters(&self) -> Vec<Parameter> {
+        vec![
+            Parameter::new("min", "shortest literal length to assume", 0),
+            Parameter::new("max", "longest literal length to test", u32::MAX),
+            timeout_parameter(30),
+        ]
+    }
+
+    fn run<'a>(
+        &'a self,
+        target: &'a Target,
+        parameters: &'a Value,
+    ) -> BoxFuture<'a, Result<Output, Box<dyn Error>>> {
+        Box::pin(async move {
+            let Bounds { min, max } = Bounds::parse(parameters)?;
+            let limit =
+                learn::max_literal(&target.host, target.tls, min, max, timeout(parameters)?)
+                    .await?;
+            Output::new(&limit, finding::max_literal(&target.host, &limit))
+        })
+    }
+
+    fn client_bytes(&self, outcome: &Value) -> Option<u64> {
+        outcome["limit"].as_u64()
+    }
+
+    fn text(&self, outcome: &Value) -> String {
+        let max_literal = outcome["limit"].as_u64().unwrap_or_default();
+        format!("Maximum literal length: {max_literal} (0x{max_literal:x})")
+    }
+}
+
+struct AllowedTag;
+
+impl Probe for AllowedTag {
+    fn name(&self) -> &'static str {
+        "allowed_tag"
+    }
+
+    fn description(&self) -> &'static str {
+        "Learn allowed tag characters (through NOOP command)"
+    }
+
+    fn parameters(&self) -> Vec<Parameter> {
+        vec![timeout_parameter(30)]
+    }
+
+    fn run<'a>(
+        &'a self,
+        target: &'a Target,
+        parameters: &'a Value,
+    ) -> BoxFuture<'a, Result<Output, Box<dyn Error>>> {
+        Box::pin(async move {
+            let results =
+                learn::allowed_tag(&target.host, target.tls, timeout(parameters)?).await?;
+            Output::new(&results, finding::allowed_tag(&target.host, &results))
+        })
+    }
+
+    fn text(&self, outcome: &Value) -> String {
+        let mut text = String::from("Allowed tag characters:");
+
+        let results = Vec::<(u8, char, Option<AllowedResult>)>::deserialize(outcome);
+        for (dec, char, result) in results.unwrap_or_default() {
+            if let Some(result) = result {
+                text.push_str(&format!(
+                    "\n{dec}: \"A{}\" => {result:?}",
+                    escape_byte_string(&[char as u8])
+                ));
+            }
+        }
+
+        text
+    }
+}
+
+struct NonSyncLiteral;
+
+impl Probe for NonSyncLiteral {
+    fn name(&self) -> &'static str {
+        "non_sync_literal"
+    }
+
+    fn description(&self) -> &'static str {
+        "Check non-synchronizing literals (LITERAL+ or LITERAL-, through LOGIN command)"
+    }
+
+    fn parameters(&self) -> Vec<Parameter> {
+        vec![
+            Parameter::new(
+                "capability",
+                "advertised capability (LITERAL+ or LITERAL-)",
+                "LITERAL+",
+            ),
+            timeout_parameter(30),
+        ]
+    }
+
+    fn run<'a>(
+        &'a self,
+        target: &'a Target,
+        parameters: &'a Value,
+    ) -> BoxFuture<'a, Result<Output, Box<dyn Error>>> {
+        Box::pin(async move {
+            let capability = String::deserialize(&parameters["capability"])?;
+            if !["LITERAL+", "LITERAL-"]
+                .iter()
+                .any(|known| capability.eq_ignore_ascii_case(known))
+            {
+                return Err(
+                    format!("`capability` must be LITERAL+ or LITERAL-, not {capability}").into(),
+                );
+            }
+            let results =
+                learn::non_sync_literal(&target.host, target.tls, timeout(parameters)?).await?;
+            Output::new(
+                &results,
+                finding::non_sync_literal(&target.host, &capability, &results),
+            )
+        })
+    }
+
+    fn text(&self, outcome: &Value) -> String {
+        let mut text = String::from("Non-synchronizing literals:");
+
+        let results = Vec::<learn::NonSyncLiteral>::deserialize(outcome);
+        for result in results.unwrap_or_default() {
+            text.push_str(&format!(
+                "\n{} byte(s): {} ({})",
+                result.length,
+                if result.accepted {
+                    "accepted"
+                } else {
+                    "rejected"
+                },
+                result.response
+            ));
+        }
+
+        text
+    }
+}
+
+#[cfg(feature = "dangerous")]
+struct OutOfMemory;
+
+#[cfg(feature = "dangerous")]
+#[derive(Deserialize)]
+struct OutOfMemoryParameters {
+    chunk_size: u32,
+    max_bytes: Option<u64>,
+    max_seconds: Option<u64>,
+    pid: Option<u32>,
+    max_rss: Option<u64>,
+}
+
+#[cfg(feature = "dangerous")]
+impl Probe for OutOfMemory {
+    fn name(&self) -> &'static str {
+        "oom"
+    }
+
+    fn description(&self) -> &'static str {
+        "Try to bring server OOM via SEARCH command. WARNING: Don't use in production."
+    }
+
+    fn state(&self) -> RequiredState {
+        RequiredState::Authenticated
+    }
+
+    fn dangerous(&self) -> bool {
+        true
+    }
+
+    fn supervise(&self, parameters: &mut Map<String, Value>, supervisor: &Supervisor) {
+        // The supervised server is the process to watch unless another one is given.
+        if let Some(pid @ Value::Null) = parameters.get_mut("pid") {
+            *pid = supervisor.pid().into();
+        }
+    }
+
+    fn parameters(&self) -> Vec<Parameter> {
+        vec![
+            Parameter::new("chunk_size", "bytes per literal chunk", 1024 * 1024),
+            Parameter::new(
+                "max_bytes",
+                "stop before sending more bytes (null for no limit)",
+                1024 * 1024 * 1024,
+            ),
+            Parameter::new(
+                "max_seconds",
+                "stop after this many seconds (null for no limit)",
+                60,
+            ),
+            Parameter::new(
+                "pid",
+                "local server process to watch (Linux, defaults to the `--server` process)",
+                json!(null),
+            ),
+            Parameter::new(
+                "max_rss",
+                "stop when the watched process uses this many bytes",
+                json!(null),
+            ),
+        ]
+    }
+
+    fn run<'a>(
+        &'a self,
+        target: &'a Target,
+        parameters: &'a Value,
+    ) -> BoxFuture<'a, Result<Output, Box<dyn Error>>> {
+        Box::pin(async move {
+            let (username, password) = target.credentials()?;
+            let OutOfMemoryParameters {
+                chunk_size,
+                max_bytes,
+                max_seconds,
+                pid,
+                max_rss,
+            } = OutOfMemoryParameters::deserialize(parameters)?;
+            if max_rss.is_some() && pid.is_none() {
+                return Err("`max_rss` requires `pid`".into());
+            }
+
+            let limits = crate::exploit::Limits {
+                bytes: max_bytes,
+                duration: max_seconds.map(Duration::from_secs),
+                pid,
+                max_rss,
+            };
+            let oom = crate::exploit::oom(
+                &target.host,
+                target.tls,
+                username,
+                password,
+                chunk_size,
+                &limits,
+            )
+            .await;
+            Output::new(&oom, finding::oom(&target.host, &oom))
+        })
+    }
+
+    fn client_bytes(&self, outcome: &Value) -> Option<u64> {
+        outcome["bytes_sent"].as_u64()
+    }
+
+    fn text(&self, outcome: &Value) -> String {
+        let Ok(oom) = crate::exploit::Oom::deserialize(outcome) else {
+            return report::json(outcome);
+        };
+
+        let mut text = format!(
+            "Sent {} chunk(s), {} byte(s), {} accepted ({})",
+            oom.chunks.len(),
+            oom.bytes_sent,
+            oom.bytes_accepted,
+            oom.stopped
+        );
+        if let Some(max_rss) = oom.max_rss {
+            text.push_str(&format!("\nPeak server memory: {max_rss} byte(s)"));
+        }
+
+        text
+    }
+}
+
+struct Conformance;
+
+impl Probe for Conformance {
+    fn name(&self) -> &'static str {
+        "conformance"
+    }
+
+    fn description(&self) -> &'static str {
+        "Check that the server enforces the IMAP state machine (all states with credentials)"
+    }
+
+    fn parameters(&self) -> Vec<Parameter> {
+        vec![
+            Parameter::new(
+                "extensions",
+                "advertised capabilities whose commands to include (e.g., IDLE, MOVE)",
+                json!([]),
+            ),
+            timeout_parameter(5),
+        ]
+    }
+
+    fn run<'a>(
+        &'a self,
+        target: &'a Target,
+        parameters: &'a Value,
+    ) -> BoxFuture<'a, Result<Output, Box<dyn Error>>> {
+        Box::pin(async move {
+            let extensions = Vec::<String>::deserialize(&parameters["extensions"])?;
+            let matrix = conformance::conformance(
+                &target.host,
+                target.tls,
+                target.username.as_deref(),
+                target.password.as_deref(),
+                &extensions,
+                timeout(parameters)?,
+            )
+            .await;
+            Output::new(&matrix, finding::conformance(&target.host, &matrix))
+        })
+    }
+}
+
+struct AuthThrottle;
+
+#[derive(Deserialize)]
+struct AuthThrottleParameters {
+    username: String,
+    max_attempts: usize,
+    mechanism: throttle::Mechanism,
+}
+
+impl Probe for AuthThrottle {
+    fn name(&self) -> &'static str {
+        "auth_throttle"
+    }
+
+    fn description(&self) -> &'static str {
+        "Measure throttling and lockout of failed authentication attempts. Use a test account."
+    }
+
+    fn parameters(&self) -> Vec<Parameter> {
+        vec![
+            Parameter::required("username", "test account to fail authentication for"),
+            Parameter::new(
+                "max_attempts",
+                "number of failed attempts (at most 100)",
+                10,
+            ),
+            Parameter::new("mechanism", "login or plain", "login"),
+            timeout_parameter(30),
+        ]
+    }
+
+    fn run<'a>(
+        &'a self,
+        target: &'a Target,
+        parameters: &'a Value,
+    ) -> BoxFuture<'a, Result<Output, Box<dyn Error>>> {
+        Box::pin(async move {
+            let AuthThrottleParameters {
+                username,
+                max_attempts,
+                mechanism,
+            } = AuthThrottleParameters::deserialize(parameters)?;
+
+            let throttle = throttle::auth_throttle(
+                &target.host,
+                target.tls,
+                &username,
+                target.password.as_deref(),
+                mechanism,
+                max_attempts,
+                timeout(parameters)?,
+            )
+            .await?;
+            Output::new(&throttle, finding::auth_throttle(&target.host, &throttle))
+        })
+    }
+}
+
+struct Enumeration;
+
+impl Probe for Enumeration {
+    fn name(&self) -> &'static str {
+        "enumeration"
+    }
+
+    fn description(&self) -> &'static str {
+        "Check whether existing usernames can be told apart by failed LOGINs"
+    }
+
+    fn parameters(&self) -> Vec<Parameter> {
+        vec![
+            Parameter::new("repetitions", "attempts per group (at most 50)", 5),
+            timeout_parameter(30),
+        ]
+    }
+
+    fn run<'a>(
+        &'a self,
+        target: &'a Target,
+        parameters: &'a Value,
+    ) -> BoxFuture<'a, Result<Output, Box<dyn Error>>> {
+        Box::pin(async move {
+            let username = target.username.as_deref().ok_or("username required")?;
+            let repetitions = usize::deserialize(&parameters["repetitions"])?;
+
+            let enumeration = enumeration::enumeration(
+                &target.host,
+                target.tls,
+                username,
+                repetitions,
+                timeout(parameters)?,
+            )
+            .await?;
+            Output::new(
+                &enumeration,
+                finding::enumeration(&target.host, &enumeration),
+            )
+        })
+    }
+}
+
+struct Fingerprint;
+
+#[derive(Deserialize)]
+struct FingerprintParameters {
+    probes: bool,
+    signatures: Option<PathBuf>,
+    max_tag: Option<u64>,
+    rejected_tag_characters: Option<Vec<u8>>,
+}
+
+impl Probe for Fingerprint {
+    fn name(&self) -> &'static str {
+        "fingerprint"
+    }
+
+    fn description(&self) -> &'static str {
+        "Identify the server implementation"
+    }
+
+    fn parameters(&self) -> Vec<Parameter> {
+        vec![
+            Parameter::new(
+                "probes",
+                "also learn tag characters and max tag length (slow)",
+                false,
+            ),
+            Parameter::new(
+                "signatures",
+                "signature database (JSON, defaults to the built-in one)",
+                json!(null),
+            ),
+            Parameter::new(
+                "max_tag",
+                "maximum tag length from an earlier max_tag run (not learned again)",
+                json!(null),
+            ),
+            Parameter::new(
+                "rejected_tag_characters",
+                "rejected tag bytes from an earlier allowed_tag run (not learned again)",
+                json!(null),
+            ),
+            timeout_parameter(30),
+        ]
+    }
+
+    fn run<'a>(
+        &'a self,
+        target: &'a Target,
+        parameters: &'a Value,
+    ) -> BoxFuture<'a, Result<Output, Box<dyn Error>>> {
+        Box::pin(async move {
+            let FingerprintParameters {
+                probes,
+                signatures,
+                max_tag,
+                rejected_tag_characters,
+            } = FingerprintParameters::deserialize(parameters)?;
+            let database = match signatures {
+                Some(path) => signatures::Database::load(&path)?,
+                None => signatures::Database::builtin(),
+            };
+
+            let known = max_tag.is_some() && rejected_tag_characters.is_some();
+            let mut features = fingerprint::features(
+                &target.host,
+                target.tls,
+                probes && !known,
+                timeout(parameters)?,
+            )
+            .await?;
+            if known {
+                features.max_tag = max_tag.map(|max_tag| max_tag.min(fingerprint::MAX_TAG));
+                features.rejected_tag_characters = rejected_tag_characters;
+            }
+            let fingerprint = fingerprint::identify(features, &database.signatures);
+            Output::new(
+                &fingerprint,
+                finding::fingerprint(&target.host, &fingerprint),
+            )
+        })
+    }
+}
+
+struct Complexity;
+
+#[derive(Deserialize)]
+struct ComplexityParameters {
+    shapes: Vec<Shape>,
+    max_size: usize,
+    repetitions: usize,
+    max_seconds: u64,
+}
+
+impl Probe for Complexity {
+    fn name(&self) -> &'static str {
+        "complexity"
+    }
+
+    fn description(&self) -> &'static str {
+        "Measure how response times grow with command size and flag super-linear growth"
+    }
+
+    fn state(&self) -> RequiredState {
+        RequiredState::Authenticated
+    }
+
+    fn parameters(&self) -> Vec<Parameter> {
+        vec![
+            Parameter::new(
+                "shapes",
+                "commands to grow: search_keys, list_wildcards, sequence_set",
+                json!(Shape::ALL),
+            ),
+            Parameter::new("max_size", "largest number of elements", 4096),
+            Parameter::new("repetitions", "measurements per size (fastest counts)", 3),
+            Parameter::new("max_seconds", "upper bound for all measurements", 60),
+            timeout_parameter(10),
+        ]
+    }
+
+    fn run<'a>(
+        &'a self,
+        target: &'a Target,
+        parameters: &'a Value,
+    ) -> BoxFuture<'a, Result<Output, Box<dyn Error>>> {
+        Box::pin(async move {
+            let (username, password) = target.credentials()?;
+            let ComplexityParameters {
+                shapes,
+                max_size,
+                repetitions,
+                max_seconds,
+            } = ComplexityParameters::deserialize(parameters)?;
+
+            let options = complexity::Options {
+                shapes,
+                max_size,
+                repetitions,
+                budget: Duration::from_secs(max_seconds),
+                timeout: timeout(parameters)?,
+            };
+            let complexity =
+                complexity::complexity(&target.host, target.tls, username, password, &options)
+                    .await?;
+            Output::new(&complexity, finding::complexity(&target.host, &complexity))
+        })
+    }
+
+    fn text(&self, outcome: &Value) -> String {
+        let Ok(complexity) = complexity::Complexity::deserialize(outcome) else {
+            return report::json(outcome);
+        };
+
+        let mut text = String::new();
+        for series in &complexity.series {
+            let exponent = match series.exponent {
+                Some(exponent) => format!("size^{exponent:.2}"),
+                None => "unknown".into(),
+            };
+            text.push_str(&format!("{:?}: {exponent}", series.shape));
+            if let Some(stopped) = &series.stopped {
+                text.push_str(&format!(" (stopped: {stopped})"));
+            }
+            text.push('\n');
+
+            for measurement in &series.measurements {
+                text.push_str(&format!(
+                    "  {:>6} {:>3}{:>10} us\n",
+                    measurement.size,
+                    if measurement.lower_bound { ">=" } else { "" },
+                    measurement.micros
+                ));
+            }
+        }
+
+        text.trim_end().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Map, Value};
+
+    use super::{allowed, parameter, run, BoxFuture, Guard, Output, Probe, Registry, Target};
+    use crate::mock::{self, Config};
+
+    #[test]
+    fn test_parameter() {
+        assert_eq!(parameter("max=10").unwrap(), ("max".into(), json!(10)));
+        assert_eq!(
+            parameter("mechanism=plain").unwrap(),
+            ("mechanism".into(), json!("plain"))
+        );
+        assert_eq!(
+            parameter("signatures=a=b.json").unwrap(),
+            ("signatures".into(), json!("a=b.json"))
+        );
+        assert!(parameter("max").is_err());
+    }
+
+    #[test]
+    fn test_allowed() {
+        assert!(allowed("imap.example.test", "imap.example.test:143"));
+        assert!(allowed("imap.example.test:143", "imap.example.test:143"));
+        assert!(!allowed("imap.example.test:993", "imap.example.test:143"));
+        assert!(allowed("*.example.test", "IMAP.example.test:143"));
+        assert!(!allowed("*.example.test", "example.test:143"));
+        assert!(!allowed("*.example.test", "imap.example.test.evil:143"));
+        assert!(allowed("[::1]", "[::1]:143"));
+    }
+
+    struct Dangerous;
+
+    impl Probe for Dangerous {
+        fn name(&self) -> &'static str {
+            "dangerous"
+        }
+
+        fn description(&self) -> &'static str {
+            "Does nothing, but claims to be dangerous"
+        }
+
+        fn dangerous(&self) -> bool {
+            true
+        }
+
+        fn run<'a>(
+            &'a self,
+            _: &'a Target,
+            _: &'a Value,
+        ) -> BoxFuture<'a, Result<Output, Box<dyn std::error::Error>>> {
+            Box::pin(async { Output::new(&(), Vec::new()) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run() {
+        let registry = Registry::builtin();
+        let host = mock::spawn(Config {
+            max_tag: 10,
+            ..Default::default()
+        })
+        .await;
+        let target = Target {
+            host: host.clone(),
+            ..Default::default()
+        };
+
+        let parameters = Map::from_iter([("max".to_string(), json!(20))]);
+        let guard = Guard::default();
+        let report = run(
+            registry.get("max_tag").unwrap(),
+            &target,
+            parameters,
+            &guard,
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(report.outcome["limit"], 10);
+        assert_eq!(
+            report.parameters,
+            json!({ "host": host, "min": 0, "max": 20, "timeout": 30 })
+        );
+        assert_eq!(registry.text(&report), "Maximum tag length: 10");
+
+        assert!(registry
+            .run("unknown", &target, Map::new(), &guard, None)
+            .await
+            .is_err());
+
+        let parameters = Map::from_iter([("maximum".to_string(), Value::from(20))]);
+        assert!(run(
+            registry.get("max_tag").unwrap(),
+            &target,
+            parameters,
+            &guard,
+            None
+        )
+        .await
+        .is_err());
+
+        let parameters = Map::from_iter([
+            ("min".to_string(), json!(20)),
+            ("max".to_string(), json!(10)),
+        ]);
+        let error = run(
+            registry.get("max_tag").unwrap(),
+            &target,
+            parameters,
+            &guard,
+            None,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "`min` (20) must not be greater than `max` (10)"
+        );
+
+        // A required parameter fails before connecting.
+        let error = run(
+            registry.get("auth_throttle").unwrap(),
+            &target,
+            Map::new(),
+            &guard,
+            None,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "auth_throttle: parameter `username` is required"
+        );
+
+        // Dangerous probes need both the confirmation and an allowlist entry.
+        assert!(run(&Dangerous, &target, Map::new(), &guard, None)
+            .await
+            .is_err());
+        let guard = Guard {
+            understood: true,
+            allowlist: vec!["imap.example.test".into()],
+        };
+        assert!(run(&Dangerous, &target, Map::new(), &guard, None)
+            .await
+            .is_err());
+        let guard = Guard {
+            allowlist: vec!["127.0.0.1".into()],
+            ..guard
+        };
+        assert!(run(&Dangerous, &target, Map::new(), &guard, None)
+            .await
+            .is_ok());
+
+        #[cfg(feature = "dangerous")]
+        assert!(run(
+            registry.get("oom").unwrap(),
+            &target,
+            Map::new(),
+            &guard,
+            None
+        )
+        .await
+        .is_err());
+    }
+}
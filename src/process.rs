@@ -0,0 +1,156 @@
+use std::io;
+
+use serde::{Deserialize, Serialize};
+
+/// Clock ticks per second of the times in `/proc/<pid>/stat` (`USER_HZ`, 100 on all common
+/// configurations).
+const TICKS_PER_SECOND: u64 = 100;
+
+/// Resource usage of a process and its descendants.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Sample {
+    /// Resident memory in bytes.
+    pub rss: u64,
+    /// User and system CPU time in milliseconds.
+    pub cpu_ms: u64,
+}
+
+/// Value of the `field` line in a `/proc/<pid>/status` file, in bytes (e.g., `VmRSS`).
+fn status_field(status: &str, field: &str) -> Option<u64> {
+    let value = status
+        .lines()
+        .find_map(|line| line.strip_prefix(field)?.strip_prefix(':'))?
+        .trim();
+    let kibibytes = value.strip_suffix("kB")?.trim().parse::<u64>().ok()?;
+
+    Some(kibibytes * 1024)
+}
+
+/// Resident memory of the local process `pid` in bytes (Linux only).
+///
+/// Fails when the process is gone (including zombies, which have no memory).
+pub fn rss(pid: u32) -> io::Result<u64> {
+    let status = std::fs::read_to_string(format!("/proc/{pid}/status"))?;
+
+    status_field(&status, "VmRSS")
+        .ok_or_else(|| io::Error::other(format!("process {pid} has no resident memory")))
+}
+
+/// Parent and CPU time in clock ticks from a `/proc/<pid>/stat` file.
+fn stat_fields(stat: &str) -> Option<(u32, u64)> {
+    // The command name is in parentheses and may contain spaces and parentheses.
+    let fields = stat
+        .get(stat.rfind(')')? + 2..)?
+        .split(' ')
+        .collect::<Vec<_>>();
+
+    let parent = fields.get(1)?.parse().ok()?;
+    let user = fields.get(11)?.parse::<u64>().ok()?;
+    let system = fields.get(12)?.parse::<u64>().ok()?;
+
+    Some((parent, user + system))
+}
+
+/// `pid` and all of its descendants.
+fn tree(pid: u32) -> io::Result<Vec<u32>> {
+    let mut parents = Vec::new();
+
+    for entry in std::fs::read_dir("/proc")? {
+        let Some(child) = entry?
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse().ok())
+        else {
+            continue;
+        };
+        // Processes may exit while we look.
+        let Ok(stat) = std::fs::read_to_string(format!("/proc/{child}/stat")) else {
+            continue;
+        };
+        if let Some((parent, _)) = stat_fields(&stat) {
+            parents.push((child, parent));
+        }
+    }
+
+    let mut tree = vec![pid];
+    let mut index = 0;
+    while let Some(&parent) = tree.get(index) {
+        tree.extend(
+            parents
+                .iter()
+                .filter(|(_, candidate)| *candidate == parent)
+                .map(|(child, _)| *child),
+        );
+        index += 1;
+    }
+
+    Ok(tree)
+}
+
+/// Usage of the local process `pid` and its descendants (e.g., the per-connection processes of
+/// Dovecot), Linux only.
+///
+/// Fails when `pid` is gone.
+pub fn sample(pid: u32) -> io::Result<Sample> {
+    let mut sample = Sample {
+        rss: rss(pid)?,
+        cpu_ms: 0,
+    };
+
+    for (index, pid) in tree(pid)?.into_iter().enumerate() {
+        if index > 0 {
+            sample.rss += rss(pid).unwrap_or_default();
+        }
+        if let Some((_, ticks)) = std::fs::read_to_string(format!("/proc/{pid}/stat"))
+            .ok()
+            .and_then(|stat| stat_fields(&stat))
+        {
+            sample.cpu_ms += ticks * 1000 / TICKS_PER_SECOND;
+        }
+    }
+
+    Ok(sample)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{stat_fields, status_field};
+
+    #[test]
+    fn test_status_field() {
+        let status =
+            "Name:\tdovecot\nState:\tS (sleeping)\nVmPeak:\t   12000 kB\nVmRSS:\t    4096 kB\n";
+
+        assert_eq!(status_field(status, "VmRSS"), Some(4096 * 1024));
+        assert_eq!(status_field(status, "VmPeak"), Some(12000 * 1024));
+        assert_eq!(status_field(status, "VmSwap"), None);
+        assert_eq!(status_field(status, "State"), None);
+    }
+
+    #[test]
+    fn test_stat_fields() {
+        let stat =
+            "4242 (imap (pre) login) S 4200 4200 4200 0 -1 4194560 120 0 0 0 7 3 0 0 20 0 1 0 \
+                    1234 8000000 900 18446744073709551615";
+
+        assert_eq!(stat_fields(stat), Some((4200, 10)));
+        assert_eq!(stat_fields("4242 (imap) S"), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_sample() {
+        assert!(super::rss(std::process::id()).unwrap() > 0);
+
+        let mut child = std::process::Command::new("sleep")
+            .arg("10")
+            .spawn()
+            .unwrap();
+        let sample = super::sample(std::process::id()).unwrap();
+        assert!(sample.rss > super::rss(child.id()).unwrap());
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert!(super::sample(child.id()).is_err());
+    }
+}
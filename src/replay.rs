@@ -0,0 +1,387 @@
+use std::time::Duration;
+
+use imap_types::utils::escape_byte_string;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::{sleep_until, timeout, Instant},
+};
+use tracing::warn;
+
+use crate::{
+    transcript::{self, Entry, Kind},
+    transport::Tls,
+};
+
+/// Consecutive entries of a connection in the same direction.
+#[derive(Debug, PartialEq)]
+enum Segment {
+    /// Bytes to send, microseconds after the connection was opened.
+    Send(Vec<u8>, u64),
+    Receive(Vec<u8>),
+    ClientClosed,
+    ServerClosed,
+}
+
+/// Group the entries of `connection` into segments.
+fn segments(entries: &[Entry], connection: u64) -> Result<Vec<Segment>, String> {
+    let mut segments = Vec::new();
+    let mut opened = None;
+
+    for entry in entries
+        .iter()
+        .filter(|entry| entry.connection == connection)
+    {
+        let offset = entry.elapsed_us - opened.unwrap_or(entry.elapsed_us);
+
+        match (entry.kind, segments.last_mut()) {
+            (Kind::Open, _) => opened = Some(entry.elapsed_us),
+            (Kind::Send, Some(Segment::Send(data, _))) => {
+                data.extend(transcript::unescape(&entry.data)?)
+            }
+            (Kind::Send, _) => {
+                segments.push(Segment::Send(transcript::unescape(&entry.data)?, offset))
+            }
+            (Kind::Receive, Some(Segment::Receive(data))) => {
+                data.extend(transcript::unescape(&entry.data)?)
+            }
+            (Kind::Receive, _) => {
+                segments.push(Segment::Receive(transcript::unescape(&entry.data)?))
+            }
+            (Kind::ClientClosed, _) => segments.push(Segment::ClientClosed),
+            (Kind::ServerClosed, _) => segments.push(Segment::ServerClosed),
+        }
+    }
+
+    Ok(segments)
+}
+
+/// TLS of `connection`, as recorded when it was opened.
+fn tls(entries: &[Entry], connection: u64) -> Tls {
+    entries
+        .iter()
+        .find(|entry| entry.connection == connection && entry.kind == Kind::Open)
+        .and_then(|entry| entry.tls)
+        .unwrap_or_default()
+}
+
+/// Expected and actual server response after a client segment.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Exchange {
+    /// Escaped client bytes sent before this response.
+    pub sent: String,
+    /// Escaped server bytes (see [`escape_byte_string`]).
+    pub expected: String,
+    pub received: String,
+    pub expected_close: bool,
+    pub closed: bool,
+    /// Offset of the first differing byte.
+    pub first_difference: Option<usize>,
+    pub matches: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ConnectionReplay {
+    pub connection: u64,
+    pub exchanges: Vec<Exchange>,
+    pub error: Option<String>,
+    pub matches: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Replay {
+    pub connections: Vec<ConnectionReplay>,
+    pub matches: bool,
+}
+
+fn first_difference(expected: &[u8], received: &[u8]) -> Option<usize> {
+    expected
+        .iter()
+        .zip(received)
+        .position(|(a, b)| a != b)
+        .or_else(|| {
+            (expected.len() != received.len()).then_some(expected.len().min(received.len()))
+        })
+}
+
+/// Read until `expected` bytes arrived, the server closed the connection, or `wait` passed.
+async fn receive(stream: &mut TcpStream, expected: usize, wait: Duration) -> (Vec<u8>, bool) {
+    let mut received = Vec::new();
+    let mut buffer = vec![0; 64 * 1024];
+
+    // Read once more when we don't expect data, to notice a close.
+    while received.len() < expected || expected == 0 {
+        match timeout(wait, stream.read(&mut buffer)).await {
+            Ok(Ok(0)) | Ok(Err(_)) => return (received, true),
+            Ok(Ok(read)) => received.extend_from_slice(&buffer[..read]),
+            Err(_) => break,
+        }
+
+        if expected == 0 {
+            break;
+        }
+    }
+
+    (received, false)
+}
+
+async fn replay_connection(
+    host: &str,
+    tls: Tls,
+    segments: Vec<Segment>,
+    timing: bool,
+    wait: Duration,
+) -> Result<Vec<Exchange>, String> {
+    let mut stream = transcript::connect(host, tls)
+        .await
+        .map_err(|error| format!("connect failed: {error}"))?;
+    let start = Instant::now();
+
+    let mut exchanges = Vec::new();
+    let mut sent = Vec::new();
+
+    for segment in segments {
+        let (expected, expected_close) = match segment {
+            Segment::Send(data, offset) => {
+                if timing {
+                    sleep_until(start + Duration::from_micros(offset)).await;
+                }
+
+                stream
+                    .write_all(&data)
+                    .await
+                    .map_err(|error| format!("write failed: {error}"))?;
+                sent.extend(data);
+                continue;
+            }
+            Segment::ClientClosed => {
+                let _ = stream.shutdown().await;
+                continue;
+            }
+            Segment::Receive(data) => (data, false),
+            Segment::ServerClosed => (Vec::new(), true),
+        };
+
+        let (received, closed) = receive(&mut stream, expected.len(), wait).await;
+        let first_difference = first_difference(&expected, &received);
+
+        exchanges.push(Exchange {
+            sent: escape_byte_string(std::mem::take(&mut sent)),
+            expected: escape_byte_string(&expected),
+            received: escape_byte_string(&received),
+            expected_close,
+            closed,
+            matches: first_difference.is_none() && (!expected_close || closed),
+            first_difference,
+        });
+
+        if closed {
+            break;
+        }
+    }
+
+    Ok(exchanges)
+}
+
+/// Replay the client side of `connections` (all when empty) against `host`, each with the TLS
+/// it was recorded with.
+///
+/// With `timing`, bytes are sent with the recorded delays. `wait` bounds how long to wait for
+/// each server response.
+pub async fn replay(
+    entries: &[Entry],
+    host: &str,
+    connections: &[u64],
+    timing: bool,
+    wait: Duration,
+) -> Result<Replay, String> {
+    let mut recorded = Vec::new();
+    for entry in entries {
+        if !recorded.contains(&entry.connection) {
+            recorded.push(entry.connection);
+        }
+    }
+
+    let selected = if connections.is_empty() {
+        recorded
+    } else {
+        if let Some(missing) = connections.iter().find(|c| !recorded.contains(c)) {
+            return Err(format!("connection {missing} is not in the transcript"));
+        }
+        connections.to_vec()
+    };
+
+    let mut result = Replay {
+        connections: Vec::new(),
+        matches: true,
+    };
+
+    for connection in selected {
+        let segments = segments(entries, connection)?;
+
+        let tls = tls(entries, connection);
+
+        let replay = match replay_connection(host, tls, segments, timing, wait).await {
+            Ok(exchanges) => ConnectionReplay {
+                connection,
+                matches: exchanges.iter().all(|exchange| exchange.matches),
+                exchanges,
+                error: None,
+            },
+            Err(error) => {
+                warn!(connection, %error, "replay failed");
+                ConnectionReplay {
+                    connection,
+                    exchanges: Vec::new(),
+                    error: Some(error),
+                    matches: false,
+                }
+            }
+        };
+
+        result.matches &= replay.matches;
+        result.connections.push(replay);
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+    };
+
+    use super::{first_difference, replay, segments, tls, Segment};
+    use crate::{
+        transcript::{Entry, Kind},
+        transport::Tls,
+    };
+
+    fn entry(connection: u64, elapsed_us: u64, kind: Kind, data: &str) -> Entry {
+        Entry {
+            connection,
+            elapsed_us,
+            kind,
+            data: data.into(),
+            tls: None,
+        }
+    }
+
+    fn transcript() -> Vec<Entry> {
+        vec![
+            entry(1, 100, Kind::Open, "localhost:143"),
+            entry(1, 200, Kind::Receive, "* OK ready\\r\\n"),
+            entry(1, 300, Kind::Send, "A NOOP"),
+            entry(1, 310, Kind::Send, "\\r\\n"),
+            entry(1, 400, Kind::Receive, "A OK "),
+            entry(1, 410, Kind::Receive, "done\\r\\n"),
+            entry(1, 500, Kind::Send, "B LOGOUT\\r\\n"),
+            entry(1, 600, Kind::Receive, "* BYE\\r\\nB OK done\\r\\n"),
+            entry(1, 700, Kind::ServerClosed, ""),
+        ]
+    }
+
+    #[test]
+    fn test_segments() {
+        let segments = segments(&transcript(), 1).unwrap();
+
+        assert_eq!(
+            segments,
+            [
+                Segment::Receive(b"* OK ready\r\n".to_vec()),
+                Segment::Send(b"A NOOP\r\n".to_vec(), 200),
+                Segment::Receive(b"A OK done\r\n".to_vec()),
+                Segment::Send(b"B LOGOUT\r\n".to_vec(), 400),
+                Segment::Receive(b"* BYE\r\nB OK done\r\n".to_vec()),
+                Segment::ServerClosed,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_first_difference() {
+        assert_eq!(first_difference(b"abc", b"abc"), None);
+        assert_eq!(first_difference(b"abc", b"abd"), Some(2));
+        assert_eq!(first_difference(b"abc", b"ab"), Some(2));
+    }
+
+    /// Server that answers like the transcript, except for LOGOUT when `changed` is set.
+    async fn mock(changed: bool) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let host = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = socket.into_split();
+            let mut lines = BufReader::new(reader).lines();
+
+            writer.write_all(b"* OK ready\r\n").await.unwrap();
+
+            while let Ok(Some(line)) = lines.next_line().await {
+                match line.as_str() {
+                    "A NOOP" => writer.write_all(b"A OK done\r\n").await.unwrap(),
+                    "B LOGOUT" if changed => writer.write_all(b"B BAD no\r\n").await.unwrap(),
+                    "B LOGOUT" => {
+                        writer.write_all(b"* BYE\r\nB OK done\r\n").await.unwrap();
+                        return;
+                    }
+                    _ => return,
+                }
+            }
+        });
+
+        host
+    }
+
+    #[tokio::test]
+    async fn test_replay() {
+        let wait = Duration::from_secs(1);
+
+        let host = mock(false).await;
+        let result = replay(&transcript(), &host, &[], false, wait)
+            .await
+            .unwrap();
+        assert!(result.matches, "{result:#?}");
+
+        let host = mock(true).await;
+        let result = replay(&transcript(), &host, &[1], true, wait)
+            .await
+            .unwrap();
+        assert!(!result.matches);
+
+        let exchanges = &result.connections[0].exchanges;
+        assert_eq!(exchanges[2].sent, "B LOGOUT\\r\\n");
+        assert_eq!(exchanges[2].first_difference, Some(0));
+
+        // Connections are replayed with the TLS they were recorded with, so the plaintext server
+        // fails the STARTTLS.
+        let mut entries = transcript();
+        entries[0].tls = Some(Tls::StartTls);
+        let host = mock(false).await;
+        let result = replay(&entries, &host, &[], false, wait).await.unwrap();
+        assert!(!result.matches);
+        let error = result.connections[0].error.as_deref().unwrap();
+        assert!(error.starts_with("connect failed"), "{error}");
+    }
+
+    #[test]
+    fn test_tls() {
+        let mut entries = transcript();
+        assert_eq!(tls(&entries, 1), Tls::None);
+
+        entries[0] = serde_json::from_value(serde_json::json!({
+            "connection": 1,
+            "elapsed_us": 100,
+            "kind": "open",
+            "data": "localhost:993",
+            "tls": "implicit",
+        }))
+        .unwrap();
+        assert_eq!(tls(&entries, 1), Tls::Implicit);
+    }
+}
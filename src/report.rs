@@ -0,0 +1,185 @@
+use std::{
+    error::Error,
+    path::Path,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    export,
+    finding::{Finding, Summary},
+    scan::Scan,
+    supervisor::Usage,
+    transcript::{self, Attachment},
+};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Format {
+    Text,
+    Json,
+    /// SARIF 2.1.0 log.
+    Sarif,
+    /// Self-contained HTML page.
+    Html,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            "sarif" => Ok(Self::Sarif),
+            "html" => Ok(Self::Html),
+            _ => Err(format!(
+                "unknown format `{s}` (expected `text`, `json`, `sarif`, or `html`)"
+            )),
+        }
+    }
+}
+
+/// Milliseconds since the Unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Result of running a single probe against a target.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Report<T> {
+    pub probe: String,
+    pub target: String,
+    pub started_ms: u64,
+    pub finished_ms: u64,
+    /// Parameters the probe was called with (secrets removed).
+    pub parameters: Value,
+    /// Probe-specific result, including per-step evidence.
+    pub outcome: T,
+    /// Interpretation of the outcome.
+    #[serde(default)]
+    pub findings: Vec<Finding>,
+    #[serde(default)]
+    pub summary: Summary,
+    /// Connections of the run (when a transcript was recorded).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transcript: Option<Attachment>,
+    /// Resource usage of the local server process (when it was launched by the tool).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server: Option<Usage>,
+}
+
+/// A probe that is currently running.
+pub struct Run {
+    probe: String,
+    target: String,
+    started_ms: u64,
+    parameters: Value,
+    first_connection: u64,
+}
+
+impl Run {
+    pub fn start(probe: &str, target: &str, parameters: &impl Serialize) -> Self {
+        Self {
+            probe: probe.to_string(),
+            target: target.to_string(),
+            started_ms: now(),
+            parameters: serde_json::to_value(parameters).unwrap_or_default(),
+            first_connection: transcript::next_connection(),
+        }
+    }
+
+    /// Finish the run, interpreting the outcome through `assess` (see [`crate::finding`]).
+    pub fn finish<T>(self, outcome: T, assess: impl FnOnce(&str, &T) -> Vec<Finding>) -> Report<T> {
+        let findings = assess(&self.target, &outcome);
+
+        Report {
+            probe: self.probe,
+            target: self.target,
+            started_ms: self.started_ms,
+            finished_ms: now(),
+            parameters: self.parameters,
+            outcome,
+            summary: Summary::new(&findings),
+            findings,
+            transcript: transcript::attachment(self.first_connection),
+            server: None,
+        }
+    }
+}
+
+impl<T: Serialize> Report<T> {
+    /// Print the outcome rendered by `text` followed by the findings, or the whole report in
+    /// `format`.
+    pub fn print(
+        &self,
+        format: Format,
+        text: impl FnOnce(&T) -> String,
+    ) -> Result<(), serde_json::Error> {
+        match format {
+            Format::Text => {
+                println!("{}", text(&self.outcome));
+
+                if !self.findings.is_empty() {
+                    println!();
+                    for finding in &self.findings {
+                        println!("{finding}");
+                    }
+                }
+                println!("\n{}", self.summary);
+            }
+            Format::Json => println!("{}", serde_json::to_string(self)?),
+            Format::Sarif => println!("{}", serde_json::to_string_pretty(&export::sarif(self))?),
+            Format::Html => print!("{}", export::html(self)),
+        }
+
+        Ok(())
+    }
+}
+
+/// Text rendering for outcomes without a dedicated one.
+pub fn json<T: Serialize>(outcome: &T) -> String {
+    serde_json::to_string(outcome).unwrap_or_default()
+}
+
+fn push(reports: &mut Vec<Report<Value>>, value: Value) -> Result<(), Box<dyn Error>> {
+    let report: Report<Value> = serde_json::from_value(value)?;
+
+    if report.probe == "scan" {
+        let scan: Scan = serde_json::from_value(report.outcome)?;
+        reports.extend(scan.steps.into_iter().filter_map(|step| step.report));
+    } else {
+        reports.push(report);
+    }
+
+    Ok(())
+}
+
+/// Read the reports of a result file (one or more JSON reports, e.g., one per line).
+///
+/// A scan report stands for the reports of its steps.
+pub fn parse(data: &str) -> Result<Vec<Report<Value>>, Box<dyn Error>> {
+    let mut reports = Vec::new();
+
+    for value in serde_json::Deserializer::from_str(data).into_iter::<Value>() {
+        match value? {
+            Value::Array(values) => {
+                for value in values {
+                    push(&mut reports, value)?;
+                }
+            }
+            value => push(&mut reports, value)?,
+        }
+    }
+
+    Ok(reports)
+}
+
+pub fn load(path: &Path) -> Result<Vec<Report<Value>>, Box<dyn Error>> {
+    parse(&std::fs::read_to_string(path)?)
+}
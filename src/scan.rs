@@ -0,0 +1,774 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt,
+    future::{poll_fn, Future},
+    panic::{catch_unwind, AssertUnwindSafe},
+    pin::pin,
+    rc::Rc,
+    task::Poll,
+};
+
+use imap_types::response::Capability;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tracing::{info, warn};
+
+use crate::{
+    finding::Finding,
+    probe::{self, Guard, Registry, RequiredState, Target},
+    report::Report,
+    supervisor::Supervisor,
+    targets::{Entry, Source},
+};
+
+/// Probes of the full profile, in the order they run.
+///
+/// The tag probes run before fingerprint, which reuses their results instead of learning them
+/// again. Probes of extensions only run when `info` found them advertised.
+///
+/// `conformance` is not part of the profile: a server that wrongly executes the commands it
+/// sends can change the account's mail (run it explicitly against a test account).
+pub const PROFILE: &[&str] = &[
+    "info",
+    "max_tag",
+    "max_literal",
+    "non_sync_literal",
+    "allowed_tag",
+    "fingerprint",
+    "oom",
+];
+
+pub struct Options {
+    /// Also run dangerous probes (see [`probe::Probe::dangerous`]).
+    pub dangerous: bool,
+    /// Seconds to wait for a response (for probes with a `timeout` parameter).
+    pub timeout: u64,
+    /// Probes to run instead of the [`PROFILE`] (empty for the profile).
+    pub probes: Vec<String>,
+    /// Targets to scan at the same time (see [`scan_all`]).
+    pub parallel: usize,
+    /// Sources of the credentials the entries refer to (see [`scan_all`]).
+    pub credentials: HashMap<String, Source>,
+    /// Parameters per probe, over the ones the scan chooses.
+    pub parameters: HashMap<String, Map<String, Value>>,
+    /// Permission for the dangerous probes (when enabled).
+    pub guard: Guard,
+    /// Local server process to watch during each probe.
+    pub supervisor: Option<Rc<Supervisor>>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            dangerous: false,
+            timeout: 5,
+            probes: Vec::new(),
+            parallel: 4,
+            credentials: HashMap::new(),
+            parameters: HashMap::new(),
+            guard: Guard::default(),
+            supervisor: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Completed,
+    Failed,
+    Skipped,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Step {
+    /// Host of the target (empty in reports of a single target).
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub target: String,
+    pub probe: String,
+    pub status: Status,
+    /// Why the step failed or was skipped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub report: Option<Report<Value>>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Scan {
+    pub steps: Vec<Step>,
+}
+
+impl Scan {
+    /// Findings of all steps.
+    pub fn findings(&self) -> Vec<Finding> {
+        self.steps
+            .iter()
+            .filter_map(|step| step.report.as_ref())
+            .flat_map(|report| report.findings.iter().cloned())
+            .collect()
+    }
+
+    fn report(&self, probe: &str) -> Option<&Report<Value>> {
+        self.steps
+            .iter()
+            .find(|step| step.probe == probe)?
+            .report
+            .as_ref()
+    }
+
+    /// Names of the capabilities `info` learned (in the greeting, before, and after
+    /// authentication), or `None` when it didn't run.
+    fn capabilities(&self) -> Option<Vec<String>> {
+        let outcome = &self.report("info")?.outcome;
+
+        let mut names = Vec::new();
+        for key in [
+            "greeting_capability",
+            "pre_auth_capability",
+            "post_auth_capability",
+        ] {
+            let capabilities = Vec::<Capability>::deserialize(&outcome[key]).unwrap_or_default();
+            for capability in capabilities {
+                let name = capability.to_string().to_ascii_uppercase();
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+        }
+
+        Some(names)
+    }
+
+    fn push(&mut self, target: &str, probe: &str, status: Status, reason: Option<String>) {
+        info!(target, probe, ?status, ?reason);
+
+        self.steps.push(Step {
+            target: target.to_string(),
+            probe: probe.to_string(),
+            status,
+            reason,
+            report: None,
+        });
+    }
+}
+
+impl fmt::Display for Scan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let multiple = self
+            .steps
+            .iter()
+            .any(|step| step.target != self.steps[0].target);
+
+        for (index, step) in self.steps.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            if multiple && (index == 0 || self.steps[index - 1].target != step.target) {
+                writeln!(f, "{}:", step.target)?;
+            }
+            if multiple {
+                write!(f, "  ")?;
+            }
+
+            let status = match step.status {
+                Status::Completed => "completed",
+                Status::Failed => "failed",
+                Status::Skipped => "skipped",
+            };
+            write!(f, "{}: {status}", step.probe)?;
+
+            if let Some(reason) = &step.reason {
+                write!(f, " ({reason})")?;
+            }
+            if let Some(report) = &step.report {
+                write!(f, ", {}", report.summary)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Parameters for `probe`, reusing the results of earlier steps.
+///
+/// The parameters of the options take precedence.
+fn parameters(
+    probe: &str,
+    known: &[probe::Parameter],
+    scan: &Scan,
+    options: &Options,
+) -> Map<String, Value> {
+    let mut parameters = Map::new();
+
+    if known.iter().any(|parameter| parameter.name == "timeout") {
+        parameters.insert("timeout".into(), options.timeout.into());
+    }
+
+    if probe == "fingerprint" {
+        let max_tag = scan
+            .report("max_tag")
+            .and_then(|report| report.outcome.get("limit").cloned());
+        let rejected = scan.report("allowed_tag").and_then(|report| {
+            let rejected = report
+                .outcome
+                .as_array()?
+                .iter()
+                .filter(|test| test.get(2).and_then(Value::as_str) != Some("reflected"))
+                .filter_map(|test| test.get(0)?.as_u64())
+                .collect::<Vec<_>>();
+            Some(Value::from(rejected))
+        });
+
+        if let (Some(max_tag), Some(rejected)) = (max_tag, rejected) {
+            parameters.insert("max_tag".into(), max_tag);
+            parameters.insert("rejected_tag_characters".into(), rejected);
+        } else {
+            // Don't learn them again when the tag probes failed.
+            parameters.insert("probes".into(), false.into());
+        }
+    }
+
+    if let Some(capabilities) = scan.capabilities() {
+        match probe {
+            "non_sync_literal" => {
+                let capability = if capabilities.iter().any(|name| name == "LITERAL+") {
+                    "LITERAL+"
+                } else {
+                    "LITERAL-"
+                };
+                parameters.insert("capability".into(), capability.into());
+            }
+            "conformance" => {
+                parameters.insert("extensions".into(), capabilities.into());
+            }
+            _ => {}
+        }
+    }
+
+    if let Some(configured) = options.parameters.get(probe) {
+        parameters.extend(configured.clone());
+    }
+
+    parameters
+}
+
+/// Why `probe` doesn't apply to the target, given the capabilities `info` learned (when it
+/// ran).
+fn inapplicable(probe: &str, scan: &Scan) -> Option<&'static str> {
+    let capabilities = scan.capabilities()?;
+    let advertised = |name: &str| capabilities.iter().any(|capability| capability == name);
+
+    match probe {
+        "non_sync_literal" if !advertised("LITERAL+") && !advertised("LITERAL-") => {
+            Some("neither LITERAL+ nor LITERAL- advertised")
+        }
+        _ => None,
+    }
+}
+
+/// Run the [`PROFILE`] (or the probes of the options) against `target`.
+///
+/// A failed (or panicking) step doesn't end the scan, except when `info` can't reach the target.
+/// When the credentials are rejected, the scan continues without them.
+pub async fn scan(registry: &Registry, target: &Target, options: &Options) -> Scan {
+    let mut scan = Scan::default();
+    let mut target = target.clone();
+    let mut unreachable = None;
+    let mut rejected_credentials = None;
+    let host = target.host.clone();
+    let names = if options.probes.is_empty() {
+        PROFILE.to_vec()
+    } else {
+        options.probes.iter().map(String::as_str).collect()
+    };
+
+    for name in &names {
+        let Some(probe) = registry.get(name) else {
+            scan.push(&host, name, Status::Skipped, Some("not registered".into()));
+            continue;
+        };
+
+        if probe.dangerous() && !options.dangerous {
+            let reason = Some("dangerous, not enabled".into());
+            scan.push(&host, name, Status::Skipped, reason);
+            continue;
+        }
+
+        if let Some(error) = &unreachable {
+            let reason = Some(format!("info failed: {error}"));
+            scan.push(&host, name, Status::Skipped, reason);
+            continue;
+        }
+
+        if let Some(reason) = inapplicable(name, &scan) {
+            scan.push(&host, name, Status::Skipped, Some(reason.into()));
+            continue;
+        }
+
+        if probe.dangerous() {
+            if let Err(reason) = options.guard.check(&host) {
+                scan.push(&host, name, Status::Skipped, Some(reason));
+                continue;
+            }
+        }
+
+        if probe.state() == RequiredState::Authenticated
+            && (target.username.is_none() || target.password.is_none())
+        {
+            let reason = rejected_credentials
+                .clone()
+                .unwrap_or_else(|| "requires credentials".into());
+            scan.push(&host, name, Status::Skipped, Some(reason));
+            continue;
+        }
+
+        let parameters = parameters(name, &probe.parameters(), &scan, options);
+        let result = isolated(probe::run(
+            probe,
+            &target,
+            parameters,
+            &options.guard,
+            options.supervisor.as_deref(),
+        ))
+        .await;
+
+        // Continue without credentials the server rejected.
+        if let Ok(report) = &result {
+            if *name == "info" && report.outcome["plaintext"]["login_accepted"] == false {
+                warn!("credentials rejected, continuing without them");
+                rejected_credentials = Some("credentials rejected".to_string());
+                target.username = None;
+                target.password = None;
+            }
+        }
+
+        match result {
+            Ok(report) => {
+                scan.steps.push(Step {
+                    target: host.clone(),
+                    probe: name.to_string(),
+                    status: Status::Completed,
+                    reason: rejected_credentials.clone().filter(|_| *name == "info"),
+                    report: Some(report),
+                });
+            }
+            Err(error) => {
+                if *name == "info" {
+                    unreachable = Some(error.to_string());
+                }
+                scan.push(&host, name, Status::Failed, Some(error.to_string()));
+            }
+        }
+    }
+
+    scan
+}
+
+/// Run a probe, turning a panic into an error of its step.
+async fn isolated(
+    future: impl Future<Output = Result<Report<Value>, Box<dyn Error>>>,
+) -> Result<Report<Value>, Box<dyn Error>> {
+    let mut future = pin!(future);
+
+    poll_fn(
+        |cx| match catch_unwind(AssertUnwindSafe(|| future.as_mut().poll(cx))) {
+            Ok(poll) => poll,
+            Err(_) => Poll::Ready(Err("panicked".into())),
+        },
+    )
+    .await
+}
+
+/// Poll `futures` on the current task, at most `limit` at a time, in order.
+async fn bounded<F: Future>(futures: Vec<F>, limit: usize) -> Vec<F::Output> {
+    let mut futures = futures
+        .into_iter()
+        .map(|future| Some(Box::pin(future)))
+        .collect::<Vec<_>>();
+    let mut outputs = futures.iter().map(|_| None).collect::<Vec<_>>();
+
+    poll_fn(|cx| {
+        let mut running = 0;
+
+        for (slot, output) in futures.iter_mut().zip(outputs.iter_mut()) {
+            let Some(future) = slot.as_mut() else {
+                continue;
+            };
+            if running == limit {
+                return Poll::Pending;
+            }
+
+            match future.as_mut().poll(cx) {
+                Poll::Pending => running += 1,
+                Poll::Ready(value) => {
+                    *output = Some(value);
+                    *slot = None;
+                }
+            }
+        }
+
+        match running {
+            0 => Poll::Ready(()),
+            _ => Poll::Pending,
+        }
+    })
+    .await;
+
+    outputs.into_iter().flatten().collect()
+}
+
+/// Scan all `entries`, [`Options::parallel`] at a time, and combine the steps in the order of
+/// the entries.
+///
+/// A target whose credentials can't be resolved gets a failed step; the other targets aren't
+/// affected.
+pub async fn scan_all(registry: &Registry, entries: &[Entry], options: &Options) -> Scan {
+    let scans = entries.iter().map(|entry| async move {
+        match entry.target(&options.credentials) {
+            Ok(target) => scan(registry, &target, options).await,
+            Err(error) => {
+                let mut scan = Scan::default();
+                scan.push(&entry.host, "credentials", Status::Failed, Some(error));
+                scan
+            }
+        }
+    });
+    let scans = bounded(scans.collect(), options.parallel.max(1)).await;
+
+    let mut combined = Scan::default();
+    for scan in scans {
+        combined.steps.extend(scan.steps);
+    }
+
+    combined
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::{scan, scan_all, Options, Status};
+    #[cfg(feature = "dangerous")]
+    use crate::probe::Guard;
+    use crate::{
+        mock::{self, Config, StartTls},
+        probe::{BoxFuture, Output, Probe, Registry, Target},
+        report::{parse, Run},
+        targets::Entry,
+        transport::Tls,
+    };
+
+    fn statuses(scan: &super::Scan) -> Vec<(&str, Status)> {
+        scan.steps
+            .iter()
+            .map(|step| (step.probe.as_str(), step.status))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_scan() {
+        let registry = Registry::builtin();
+        let options = Options {
+            timeout: 1,
+            ..Default::default()
+        };
+        let host = mock::spawn(Config {
+            max_tag: 100,
+            ..Default::default()
+        })
+        .await;
+
+        let target = Target {
+            host: host.clone(),
+            username: Some("alice".into()),
+            password: Some("password".into()),
+            tls: Tls::None,
+        };
+        let result = scan(&registry, &target, &options).await;
+
+        assert_eq!(
+            statuses(&result),
+            [
+                ("info", Status::Completed),
+                ("max_tag", Status::Completed),
+                ("max_literal", Status::Completed),
+                ("non_sync_literal", Status::Completed),
+                ("allowed_tag", Status::Completed),
+                ("fingerprint", Status::Completed),
+                ("oom", Status::Skipped),
+            ]
+        );
+        let fingerprint = result.report("fingerprint").unwrap();
+        assert_eq!(fingerprint.parameters["max_tag"], 100);
+        assert_eq!(fingerprint.outcome["features"]["max_tag"], 100);
+
+        // Extensions come from the capabilities info learned.
+        let non_sync_literal = result.report("non_sync_literal").unwrap();
+        assert_eq!(non_sync_literal.parameters["capability"], "LITERAL+");
+
+        // The combined report stands for the reports of its steps.
+        let report = Run::start("scan", &host, &()).finish(result, |_, scan| scan.findings());
+        let reports = parse(&serde_json::to_string(&report).unwrap()).unwrap();
+        assert_eq!(reports.len(), 6);
+        assert_eq!(reports[0].probe, "info");
+
+        let target = Target {
+            password: Some("wrong".into()),
+            ..target
+        };
+        let result = scan(&registry, &target, &options).await;
+        assert_eq!(
+            result.steps[0].reason.as_deref(),
+            Some("credentials rejected")
+        );
+
+        // oom is only built with the dangerous feature.
+        #[cfg(not(feature = "dangerous"))]
+        assert_eq!(result.steps[6].reason.as_deref(), Some("not registered"));
+        #[cfg(feature = "dangerous")]
+        {
+            assert_eq!(
+                result.steps[6].reason.as_deref(),
+                Some("dangerous, not enabled")
+            );
+
+            let options = Options {
+                dangerous: true,
+                timeout: 1,
+                ..Default::default()
+            };
+            let result = scan(&registry, &target, &options).await;
+            assert_eq!(
+                result.steps[6].reason.as_deref(),
+                Some("dangerous, requires --i-understand")
+            );
+
+            let options = Options {
+                guard: Guard {
+                    understood: true,
+                    allowlist: vec!["127.0.0.1".into()],
+                },
+                ..options
+            };
+            let result = scan(&registry, &target, &options).await;
+            assert_eq!(
+                result.steps[6].reason.as_deref(),
+                Some("credentials rejected")
+            );
+        }
+
+        let target = Target {
+            host: "127.0.0.1:1".into(),
+            ..Default::default()
+        };
+        let result = scan(&registry, &target, &options).await;
+        assert_eq!(result.steps[0].status, Status::Failed);
+        assert!(result.steps[1..]
+            .iter()
+            .all(|step| step.status == Status::Skipped));
+    }
+
+    struct Panic;
+
+    impl Probe for Panic {
+        fn name(&self) -> &'static str {
+            "panic"
+        }
+
+        fn description(&self) -> &'static str {
+            "Panics while running"
+        }
+
+        fn run<'a>(
+            &'a self,
+            _: &'a Target,
+            _: &'a Value,
+        ) -> BoxFuture<'a, Result<Output, Box<dyn std::error::Error>>> {
+            Box::pin(async { panic!("probe panicked") })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_scan_panic() {
+        let mut registry = Registry::builtin();
+        registry.register(Panic);
+        let options = Options {
+            timeout: 1,
+            probes: vec!["info".into(), "panic".into(), "max_tag".into()],
+            ..Default::default()
+        };
+        let host = mock::spawn(Config {
+            max_tag: 10,
+            ..Default::default()
+        })
+        .await;
+
+        let target = Target {
+            host,
+            ..Default::default()
+        };
+        let result = scan(&registry, &target, &options).await;
+
+        assert_eq!(
+            statuses(&result),
+            [
+                ("info", Status::Completed),
+                ("panic", Status::Failed),
+                ("max_tag", Status::Completed),
+            ]
+        );
+        assert_eq!(result.steps[1].reason.as_deref(), Some("panicked"));
+    }
+
+    #[tokio::test]
+    async fn test_scan_capabilities() {
+        let registry = Registry::builtin();
+        let options = Options {
+            timeout: 1,
+            probes: vec!["info".into(), "non_sync_literal".into()],
+            ..Default::default()
+        };
+        let host = mock::spawn(Config {
+            capabilities: vec!["IMAP4rev1".into()],
+            ..Default::default()
+        })
+        .await;
+
+        let target = Target {
+            host,
+            ..Default::default()
+        };
+        let result = scan(&registry, &target, &options).await;
+
+        assert_eq!(
+            statuses(&result),
+            [
+                ("info", Status::Completed),
+                ("non_sync_literal", Status::Skipped),
+            ]
+        );
+        assert_eq!(
+            result.steps[1].reason.as_deref(),
+            Some("neither LITERAL+ nor LITERAL- advertised")
+        );
+
+        // Only part of a scan when requested.
+        let options = Options {
+            probes: vec!["info".into(), "conformance".into()],
+            ..options
+        };
+        let host = mock::spawn(Config::default()).await;
+        let target = Target {
+            host,
+            username: Some("alice".into()),
+            password: Some("password".into()),
+            tls: Tls::None,
+        };
+        let result = scan(&registry, &target, &options).await;
+        let conformance = result.report("conformance").unwrap();
+        let extensions = conformance.parameters["extensions"].as_array().unwrap();
+        assert!(extensions.contains(&"MOVE".into()));
+
+        // Credentials sent after STARTTLS aren't a plaintext finding, although the server
+        // accepts them and advertises AUTH=PLAIN.
+        let options = Options {
+            probes: vec!["info".into()],
+            ..options
+        };
+        let host = mock::spawn(Config {
+            starttls: StartTls::Working,
+            login_disabled: true,
+            ..Default::default()
+        })
+        .await;
+        let target = Target {
+            host,
+            username: Some("alice".into()),
+            password: Some("password".into()),
+            tls: Tls::StartTls,
+        };
+        let result = scan(&registry, &target, &options).await;
+        let info = result.report("info").unwrap();
+        assert_eq!(info.outcome["plaintext"]["login_accepted"], true);
+        assert!(result
+            .findings()
+            .iter()
+            .all(|finding| finding.id != "plaintext-credentials"));
+
+        let result = scan(
+            &registry,
+            &Target {
+                tls: Tls::None,
+                ..target
+            },
+            &options,
+        )
+        .await;
+        assert!(result
+            .findings()
+            .iter()
+            .any(|finding| finding.id == "plaintext-credentials"));
+    }
+
+    #[tokio::test]
+    async fn test_scan_all() {
+        let registry = Registry::builtin();
+        let options = Options {
+            timeout: 1,
+            probes: vec!["info".into(), "max_tag".into()],
+            parallel: 2,
+            ..Default::default()
+        };
+        let first = mock::spawn(Config {
+            max_tag: 10,
+            ..Default::default()
+        })
+        .await;
+        let second = mock::spawn(Config {
+            max_tag: 20,
+            ..Default::default()
+        })
+        .await;
+
+        let entry = |host: &str, credentials: Option<&str>| Entry {
+            host: host.into(),
+            tls: Tls::None,
+            credentials: credentials.map(Into::into),
+        };
+        let entries = [
+            entry(&first, None),
+            entry("127.0.0.1:1", None),
+            entry(&second, None),
+            entry(&second, Some("imap-sec-test-missing")),
+        ];
+        let result = scan_all(&registry, &entries, &options).await;
+
+        let steps = result
+            .steps
+            .iter()
+            .map(|step| (step.target.as_str(), step.probe.as_str(), step.status))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            steps,
+            [
+                (first.as_str(), "info", Status::Completed),
+                (first.as_str(), "max_tag", Status::Completed),
+                ("127.0.0.1:1", "info", Status::Failed),
+                ("127.0.0.1:1", "max_tag", Status::Skipped),
+                (second.as_str(), "info", Status::Completed),
+                (second.as_str(), "max_tag", Status::Completed),
+                (second.as_str(), "credentials", Status::Failed),
+            ]
+        );
+        assert_eq!(
+            result.steps[1].report.as_ref().unwrap().outcome["limit"],
+            10
+        );
+        assert_eq!(
+            result.steps[5].report.as_ref().unwrap().outcome["limit"],
+            20
+        );
+    }
+}
@@ -0,0 +1,340 @@
+use std::{borrow::Cow, error::Error, time::Duration};
+
+use imap_codec::{encode::Encoder, AuthenticateDataCodec, CommandCodec};
+use imap_next::{
+    client::{self, Client, Event, Options},
+    stream::{self, Stream},
+};
+use imap_types::{
+    auth::{AuthMechanism, AuthenticateData},
+    command::{Command, CommandBody},
+    core::Tag,
+    mailbox::Mailbox,
+    response::{Bye, Code, Data, Greeting, Status, StatusBody, StatusKind, Tagged},
+    secret::Secret,
+    utils::escape_byte_string,
+};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use tracing::{trace, warn};
+
+use crate::{transcript, transport::Tls};
+
+/// How the server reacted to a command.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Ok,
+    No,
+    Bad,
+    Bye,
+    /// The server requested a continuation (e.g., for a literal).
+    Continue,
+    Closed,
+    Timeout,
+    Error,
+}
+
+impl From<StatusKind> for Outcome {
+    fn from(value: StatusKind) -> Self {
+        match value {
+            StatusKind::Ok => Self::Ok,
+            StatusKind::No => Self::No,
+            StatusKind::Bad => Self::Bad,
+        }
+    }
+}
+
+/// Everything the server sent until a command was completed (or not).
+#[derive(Debug)]
+pub struct Reply {
+    pub outcome: Outcome,
+    /// Tag of the completion (`None` for untagged statuses and continuation requests).
+    pub tag: Option<String>,
+    pub code: Option<Code<'static>>,
+    pub text: Option<String>,
+    pub data: Vec<Data<'static>>,
+}
+
+impl Reply {
+    fn new(outcome: Outcome) -> Self {
+        Self {
+            outcome,
+            tag: None,
+            code: None,
+            text: None,
+            data: Vec::new(),
+        }
+    }
+
+    /// The status line the reply ended with (without response code), e.g., `A NO text`.
+    ///
+    /// Continuation requests are shown as `+`, and replies without a status line as their
+    /// outcome in parentheses.
+    pub fn line(&self) -> String {
+        let tag = self.tag.as_deref().unwrap_or("*");
+        let text = self.text.as_deref().unwrap_or_default();
+
+        match self.outcome {
+            Outcome::Ok => format!("{tag} OK {text}"),
+            Outcome::No => format!("{tag} NO {text}"),
+            Outcome::Bad => format!("{tag} BAD {text}"),
+            Outcome::Bye => format!("* BYE {text}"),
+            Outcome::Continue => "+".into(),
+            Outcome::Closed => "(closed)".into(),
+            Outcome::Timeout => "(timeout)".into(),
+            Outcome::Error => "(error)".into(),
+        }
+    }
+}
+
+/// A single connection to a server (see [`transcript::connect`]).
+pub struct Session {
+    stream: Stream,
+    client: Client,
+    timeout: Duration,
+}
+
+impl Session {
+    /// Connect to `host` with `tls` and wait for the greeting.
+    pub async fn connect(
+        host: &str,
+        tls: Tls,
+        timeout: Duration,
+    ) -> Result<(Self, Greeting<'static>), Box<dyn Error>> {
+        let stream = Stream::insecure(transcript::connect(host, tls).await?);
+        let client = Client::new(Options::default());
+
+        let mut session = Self {
+            stream,
+            client,
+            timeout,
+        };
+
+        let greeting = loop {
+            match session.next().await? {
+                Some(Event::GreetingReceived { greeting }) => break greeting,
+                Some(event) => warn!(?event, "unexpected event"),
+                None => return Err("no greeting received".into()),
+            }
+        };
+
+        Ok((session, greeting))
+    }
+
+    /// Connect to `host` and LOGIN with the given credentials.
+    pub async fn connect_and_login(
+        host: &str,
+        tls: Tls,
+        username: &str,
+        password: &str,
+        timeout: Duration,
+    ) -> Result<Self, Box<dyn Error>> {
+        let (mut session, _) = Self::connect(host, tls, timeout).await?;
+
+        let reply = session
+            .command(
+                "L",
+                CommandBody::login(username.to_string(), password.to_string())?,
+            )
+            .await;
+
+        if reply.outcome != Outcome::Ok {
+            return Err(format!("LOGIN failed ({:?})", reply.outcome).into());
+        }
+
+        Ok(session)
+    }
+
+    /// Connect to `host`, LOGIN, and SELECT INBOX (e.g., to send commands of the selected
+    /// state).
+    pub async fn connect_and_select(
+        host: &str,
+        tls: Tls,
+        username: &str,
+        password: &str,
+        timeout: Duration,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut session = Self::connect_and_login(host, tls, username, password, timeout).await?;
+
+        let reply = session
+            .command(
+                "S",
+                CommandBody::Select {
+                    mailbox: Mailbox::Inbox,
+                },
+            )
+            .await;
+
+        if reply.outcome != Outcome::Ok {
+            return Err(format!("SELECT failed ({:?})", reply.outcome).into());
+        }
+
+        Ok(session)
+    }
+
+    /// Change how long [`Session::next`] waits for an event.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Next event, or `None` when the timeout elapsed.
+    pub async fn next(&mut self) -> Result<Option<Event>, stream::Error<client::Error>> {
+        match tokio::time::timeout(self.timeout, self.stream.next(&mut self.client)).await {
+            Ok(result) => result.map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Send a command through the client and wait for its completion.
+    pub async fn command(&mut self, tag: &str, body: CommandBody<'static>) -> Reply {
+        let command = match Command::new(Tag::unvalidated(tag.to_string()), body) {
+            Ok(command) => command,
+            Err(_) => return Reply::new(Outcome::Error),
+        };
+
+        self.client.enqueue_command(command);
+        self.wait(tag).await
+    }
+
+    /// AUTHENTICATE with `mechanism` and its first client `response`: as initial response with
+    /// `sasl_ir` (only allowed when the server advertises SASL-IR), and after the continuation
+    /// request otherwise.
+    ///
+    /// Without SASL-IR, the command and the response are written raw, like the other raw commands
+    /// the client doesn't know about (see [`Session::wait_any`]).
+    pub async fn authenticate(
+        &mut self,
+        tag: &str,
+        mechanism: AuthMechanism<'static>,
+        response: Vec<u8>,
+        sasl_ir: bool,
+    ) -> Reply {
+        if sasl_ir {
+            let body = CommandBody::Authenticate {
+                mechanism,
+                initial_response: Some(Secret::new(Cow::Owned(response))),
+            };
+            return self.command(tag, body).await;
+        }
+
+        let body = CommandBody::Authenticate {
+            mechanism,
+            initial_response: None,
+        };
+        let Ok(command) = Command::new(tag, body) else {
+            return Reply::new(Outcome::Error);
+        };
+        if self
+            .write_raw(&CommandCodec::default().encode(&command).dump())
+            .await
+            .is_err()
+        {
+            return Reply::new(Outcome::Closed);
+        }
+
+        let reply = self.wait_any().await;
+        if reply.outcome != Outcome::Continue {
+            return reply;
+        }
+
+        let data = AuthenticateData::Continue(Secret::new(Cow::Owned(response)));
+        if self
+            .write_raw(&AuthenticateDataCodec::default().encode(&data).dump())
+            .await
+            .is_err()
+        {
+            return Reply::new(Outcome::Closed);
+        }
+
+        self.wait(tag).await
+    }
+
+    /// Write raw bytes, bypassing the client.
+    pub async fn write_raw(&mut self, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        // Not traced by the client (the transcript has a complete record).
+        trace!(data = escape_byte_string(data), "io/write/raw");
+        self.stream.stream_mut().write_all(data).await?;
+        Ok(())
+    }
+
+    /// Collect events until the command tagged `tag` completed.
+    ///
+    /// A BYE is remembered but does not end the command on its own, because a server is
+    /// expected to send the tagged completion after it (e.g., for LOGOUT).
+    pub async fn wait(&mut self, tag: &str) -> Reply {
+        self.collect(Some(tag)).await
+    }
+
+    /// Collect events until any command completed, the server rejected a command it couldn't
+    /// attribute (untagged BAD), or requested a continuation.
+    ///
+    /// For commands written with [`Session::write_raw`] the client doesn't know about.
+    pub async fn wait_any(&mut self) -> Reply {
+        self.collect(None).await
+    }
+
+    async fn collect(&mut self, tag: Option<&str>) -> Reply {
+        let mut data = Vec::new();
+        let mut bye = None;
+
+        let mut reply = loop {
+            match self.next().await {
+                Ok(Some(Event::CommandSent { .. })) => {}
+                Ok(Some(Event::DataReceived { data: received })) => data.push(received),
+                Ok(Some(
+                    Event::StatusReceived { status } | Event::CommandRejected { status, .. },
+                )) => match status {
+                    Status::Tagged(Tagged {
+                        tag: received,
+                        body: StatusBody { kind, code, text },
+                    }) if tag.is_none() || tag == Some(received.as_ref()) => {
+                        break Reply {
+                            outcome: kind.into(),
+                            tag: Some(received.as_ref().to_string()),
+                            code,
+                            text: Some(text.as_ref().to_string()),
+                            data: Vec::new(),
+                        };
+                    }
+                    Status::Untagged(StatusBody {
+                        kind: StatusKind::Bad,
+                        code,
+                        text,
+                    }) if tag.is_none() => {
+                        break Reply {
+                            outcome: Outcome::Bad,
+                            tag: None,
+                            code,
+                            text: Some(text.as_ref().to_string()),
+                            data: Vec::new(),
+                        };
+                    }
+                    Status::Bye(Bye { code, text }) => {
+                        bye = Some(Reply {
+                            outcome: Outcome::Bye,
+                            tag: None,
+                            code,
+                            text: Some(text.as_ref().to_string()),
+                            data: Vec::new(),
+                        });
+                    }
+                    status => trace!(?status, "ignored status"),
+                },
+                Ok(Some(Event::ContinuationRequestReceived { .. })) if tag.is_none() => {
+                    break Reply::new(Outcome::Continue);
+                }
+                Ok(Some(event)) => warn!(?event, "unexpected event"),
+                Ok(None) => break bye.unwrap_or(Reply::new(Outcome::Timeout)),
+                Err(stream::Error::Closed) => break bye.unwrap_or(Reply::new(Outcome::Closed)),
+                Err(error) => {
+                    warn!(?error);
+                    break bye.unwrap_or(Reply::new(Outcome::Error));
+                }
+            }
+        };
+
+        reply.data = data;
+        reply
+    }
+}
@@ -0,0 +1,299 @@
+use std::{error::Error, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::fingerprint::{compare_versions, version, Features, Matcher, Signature};
+
+/// Format version of the signature database this build understands.
+pub const VERSION: u32 = 1;
+
+const BUILTIN: &str = include_str!("signatures.json");
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Database {
+    pub version: u32,
+    pub signatures: Vec<Signature>,
+}
+
+/// A known vulnerability of a server implementation.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Advisory {
+    pub id: String,
+    pub title: String,
+    #[serde(default)]
+    pub url: Option<String>,
+    /// All matchers must match for the advisory to apply.
+    #[serde(default)]
+    pub when: Vec<Matcher>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AdvisoryMatch {
+    pub id: String,
+    pub title: String,
+    pub url: Option<String>,
+    /// `false` when some matcher couldn't be evaluated (e.g., the server hides its version).
+    pub certain: bool,
+}
+
+impl Advisory {
+    /// `None` when a matcher contradicts the advisory.
+    pub fn evaluate(&self, features: &Features) -> Option<AdvisoryMatch> {
+        let mut certain = true;
+
+        for matcher in &self.when {
+            match matcher.matches(features) {
+                Some(true) => {}
+                Some(false) => return None,
+                None => certain = false,
+            }
+        }
+
+        Some(AdvisoryMatch {
+            id: self.id.clone(),
+            title: self.title.clone(),
+            url: self.url.clone(),
+            certain,
+        })
+    }
+}
+
+fn validate_matcher(matcher: &Matcher, context: &str, errors: &mut Vec<String>) {
+    match matcher {
+        Matcher::GreetingContains(text)
+        | Matcher::CapabilityPresent(text)
+        | Matcher::UnknownCommandContains(text)
+            if text.is_empty() =>
+        {
+            errors.push(format!("{context}: empty text"));
+        }
+        Matcher::CapabilityOrder(names) if names.len() < 2 => {
+            errors.push(format!(
+                "{context}: capability order needs two capabilities"
+            ));
+        }
+        Matcher::IdContains { key, .. } if key.is_empty() => {
+            errors.push(format!("{context}: empty ID key"));
+        }
+        Matcher::IdVersion { min, max } => {
+            for bound in [min, max].into_iter().flatten() {
+                if version(bound).is_empty() {
+                    errors.push(format!("{context}: invalid version `{bound}`"));
+                }
+            }
+
+            if let (Some(min), Some(max)) = (min, max) {
+                if compare_versions(&version(min), &version(max)).is_gt() {
+                    errors.push(format!("{context}: min version is above max version"));
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+impl Database {
+    /// Check the database for mistakes that deserialization can't catch.
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+
+        if self.version != VERSION {
+            errors.push(format!(
+                "unsupported version {} (expected {VERSION})",
+                self.version
+            ));
+        }
+
+        let mut advisory_ids = Vec::new();
+
+        for (index, signature) in self.signatures.iter().enumerate() {
+            let context = format!("signature #{index} ({})", signature.server);
+
+            if signature.server.is_empty() {
+                errors.push(format!("{context}: empty server"));
+            }
+
+            if self.signatures[..index].iter().any(|other| {
+                other.server == signature.server && other.versions == signature.versions
+            }) {
+                errors.push(format!("{context}: duplicate server and versions"));
+            }
+
+            if signature.rules.is_empty() {
+                errors.push(format!("{context}: no rules"));
+            }
+
+            for (index, rule) in signature.rules.iter().enumerate() {
+                let context = format!("{context}, rule #{index}");
+
+                if rule.weight == 0 {
+                    errors.push(format!("{context}: weight must be positive"));
+                }
+
+                validate_matcher(&rule.matcher, &context, &mut errors);
+            }
+
+            for advisory in &signature.advisories {
+                let context = format!("{context}, advisory {}", advisory.id);
+
+                if advisory.id.is_empty() || advisory.title.is_empty() {
+                    errors.push(format!("{context}: empty id or title"));
+                }
+
+                if advisory_ids.contains(&&advisory.id) {
+                    errors.push(format!("{context}: duplicate id"));
+                }
+                advisory_ids.push(&advisory.id);
+
+                for matcher in &advisory.when {
+                    validate_matcher(matcher, &context, &mut errors);
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    pub fn parse(data: &str) -> Result<Self, Box<dyn Error>> {
+        let database: Self = serde_json::from_str(data)?;
+
+        if let Err(errors) = database.validate() {
+            return Err(format!("invalid signature database: {}", errors.join("; ")).into());
+        }
+
+        Ok(database)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Database shipped with imap-sec.
+    pub fn builtin() -> Self {
+        Self::parse(BUILTIN).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Database;
+    use crate::fingerprint::{identify, Features, Matcher};
+
+    fn features() -> Features {
+        Features {
+            greeting: Some("Dovecot ready.".into()),
+            capabilities: [
+                "IMAP4rev1",
+                "SASL-IR",
+                "LOGIN-REFERRALS",
+                "ID",
+                "ENABLE",
+                "IDLE",
+                "LITERAL+",
+            ]
+            .map(String::from)
+            .to_vec(),
+            id: Some(vec![
+                ("name".into(), Some("Dovecot".into())),
+                ("version".into(), Some("2.3.4 (abcdef)".into())),
+            ]),
+            unknown_command: Some("Unknown command.".into()),
+            rejected_tag_characters: None,
+            max_tag: None,
+        }
+    }
+
+    #[test]
+    fn test_builtin() {
+        let database = Database::builtin();
+        assert!(!database.signatures.is_empty());
+    }
+
+    #[test]
+    fn test_matchers() {
+        let features = features();
+
+        let tests = [
+            (r#"{"greeting_contains": "dovecot"}"#, Some(true)),
+            (r#"{"greeting_contains": "Cyrus"}"#, Some(false)),
+            (r#"{"capability_present": "idle"}"#, Some(true)),
+            (r#"{"capability_order": ["IMAP4rev1", "IDLE"]}"#, Some(true)),
+            (
+                r#"{"capability_order": ["IDLE", "IMAP4rev1"]}"#,
+                Some(false),
+            ),
+            (
+                r#"{"capability_order": ["IMAP4rev1", "MOVE"]}"#,
+                Some(false),
+            ),
+            (
+                r#"{"id_contains": {"key": "NAME", "value": "dovecot"}}"#,
+                Some(true),
+            ),
+            (
+                r#"{"id_version": {"min": "2.3", "max": "2.3.7.1"}}"#,
+                Some(true),
+            ),
+            (
+                r#"{"id_version": {"min": "2.3.5", "max": null}}"#,
+                Some(false),
+            ),
+            (
+                r#"{"unknown_command_contains": "unknown command"}"#,
+                Some(true),
+            ),
+            (r#"{"tag_character_rejected": 43}"#, None),
+            (r#"{"max_tag_at_least": 10}"#, None),
+        ];
+
+        for (matcher, expected) in tests {
+            let matcher: Matcher = serde_json::from_str(matcher).unwrap();
+            assert_eq!(matcher.matches(&features), expected, "{matcher}");
+        }
+    }
+
+    #[test]
+    fn test_identify() {
+        let database = Database::builtin();
+        let fingerprint = identify(features(), &database.signatures);
+
+        let best = fingerprint.best.unwrap();
+        assert_eq!(best.server, "Dovecot");
+        assert_eq!(best.confidence, 1.0);
+        assert_eq!(fingerprint.advisories.len(), 1);
+        assert!(fingerprint.advisories[0].certain);
+    }
+
+    #[test]
+    fn test_validate() {
+        let data = r#"{
+            "version": 2,
+            "signatures": [
+                {
+                    "server": "",
+                    "versions": "1.x",
+                    "rules": [
+                        { "matcher": { "greeting_contains": "" }, "weight": 0 },
+                        { "matcher": { "id_version": { "min": "2", "max": "1" } }, "weight": 1 }
+                    ],
+                    "advisories": [
+                        { "id": "A", "title": "a" },
+                        { "id": "A", "title": "a", "when": [{ "id_version": { "min": "x" } }] }
+                    ]
+                }
+            ]
+        }"#;
+
+        let database: Database = serde_json::from_str(data).unwrap();
+        let errors = database.validate().unwrap_err();
+        assert_eq!(errors.len(), 7, "{errors:#?}");
+
+        assert!(Database::parse(r#"{"version": 1, "signatures": [], "unknown": 1}"#).is_err());
+    }
+}
@@ -0,0 +1,247 @@
+use std::{
+    error::Error,
+    fmt,
+    future::Future,
+    process::{Child, Command, Stdio},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpStream;
+use tracing::{info, warn};
+
+use crate::process::{self, Sample};
+
+/// Interval between samples while a probe runs.
+const INTERVAL: Duration = Duration::from_millis(100);
+/// Upper bound for the server to accept connections after the launch.
+const STARTUP: Duration = Duration::from_secs(10);
+
+/// A local server process, launched to be probed and watched.
+///
+/// The process is killed when the supervisor is dropped.
+pub struct Supervisor {
+    child: Mutex<Child>,
+    pid: u32,
+}
+
+/// Resource usage of the server while a probe ran.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Usage {
+    pub before: Sample,
+    /// `None` when the server exited.
+    pub after: Option<Sample>,
+    /// Highest resident memory in bytes.
+    pub peak_rss: u64,
+    /// CPU time the server spent during the probe, in milliseconds.
+    pub cpu_ms: u64,
+    pub samples: usize,
+    /// Server bytes retained per client byte (see [`Usage::amplify`]).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub amplification: Option<f64>,
+    /// How the server exited during the probe (e.g., killed by a signal).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit: Option<String>,
+}
+
+impl Usage {
+    /// Set the amplification: resident memory gained during the probe per byte the client sent.
+    pub fn amplify(&mut self, client_bytes: u64) {
+        if client_bytes > 0 {
+            let retained = self.peak_rss.saturating_sub(self.before.rss);
+            self.amplification = Some(retained as f64 / client_bytes as f64);
+        }
+    }
+}
+
+impl fmt::Display for Usage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Server memory: {} -> {} byte(s) (peak), CPU: {} ms",
+            self.before.rss, self.peak_rss, self.cpu_ms
+        )?;
+
+        if let Some(amplification) = self.amplification {
+            write!(f, ", {amplification:.1} byte(s) per client byte")?;
+        }
+        if let Some(exit) = &self.exit {
+            write!(f, "\nServer exited: {exit}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Supervisor {
+    /// Launch `command` (program and arguments) and wait until it accepts connections on
+    /// `host`.
+    ///
+    /// The server must stay in the foreground (e.g., `dovecot -F -c dovecot.conf`).
+    pub async fn launch(command: &[String], host: &str) -> Result<Self, Box<dyn Error>> {
+        let (program, arguments) = command.split_first().ok_or("empty server command")?;
+        let child = Command::new(program)
+            .args(arguments)
+            .stdin(Stdio::null())
+            .spawn()
+            .map_err(|error| format!("{program}: {error}"))?;
+        let supervisor = Self {
+            pid: child.id(),
+            child: Mutex::new(child),
+        };
+
+        let started = Instant::now();
+        loop {
+            if let Some(exit) = supervisor.exit() {
+                return Err(format!("server exited during startup ({exit})").into());
+            }
+            if TcpStream::connect(host).await.is_ok() {
+                break;
+            }
+            if started.elapsed() > STARTUP {
+                return Err(format!("server doesn't accept connections on {host}").into());
+            }
+            tokio::time::sleep(INTERVAL).await;
+        }
+        info!(pid = supervisor.pid, ?command, "server launched");
+
+        Ok(supervisor)
+    }
+
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    /// How the server exited (if it did).
+    pub fn exit(&self) -> Option<String> {
+        let mut child = self.child.lock().unwrap();
+
+        match child.try_wait() {
+            Ok(status) => status.map(|status| status.to_string()),
+            Err(error) => Some(error.to_string()),
+        }
+    }
+
+    /// Run `future` (a probe) while sampling the server.
+    pub async fn monitor<F: Future>(&self, future: F) -> (F::Output, Usage) {
+        let before = process::sample(self.pid).unwrap_or_default();
+        let mut last = before;
+        let mut peak_rss = before.rss;
+        let mut samples = 1;
+
+        let mut interval = tokio::time::interval(INTERVAL);
+        tokio::pin!(future);
+        let output = loop {
+            tokio::select! {
+                output = &mut future => break output,
+                _ = interval.tick() => {
+                    if let Ok(sample) = process::sample(self.pid) {
+                        peak_rss = peak_rss.max(sample.rss);
+                        samples += 1;
+                        last = sample;
+                    }
+                }
+            }
+        };
+
+        let exit = self.exit();
+        let after = match exit {
+            Some(_) => None,
+            None => process::sample(self.pid).ok(),
+        };
+        if let Some(after) = after {
+            peak_rss = peak_rss.max(after.rss);
+            samples += 1;
+            last = after;
+        }
+        if let Some(exit) = &exit {
+            warn!(pid = self.pid, %exit, "server exited");
+        }
+
+        let usage = Usage {
+            before,
+            after,
+            peak_rss,
+            cpu_ms: last.cpu_ms.saturating_sub(before.cpu_ms),
+            samples,
+            amplification: None,
+            exit,
+        };
+
+        (output, usage)
+    }
+}
+
+impl Drop for Supervisor {
+    fn drop(&mut self) {
+        if let Ok(child) = self.child.get_mut() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::Supervisor;
+    use crate::mock::{self, Config};
+
+    #[tokio::test]
+    async fn test_supervisor() {
+        let host = mock::spawn(Config::default()).await;
+        let command = ["sleep".to_string(), "30".to_string()];
+
+        let supervisor = Supervisor::launch(&command, &host).await.unwrap();
+        let (output, usage) = supervisor
+            .monitor(async {
+                tokio::time::sleep(Duration::from_millis(300)).await;
+                42
+            })
+            .await;
+        assert_eq!(output, 42);
+        assert!(usage.samples > 1);
+        assert!(usage.peak_rss > 0);
+        assert_eq!(usage.exit, None);
+
+        // A crash during a probe.
+        let pid = supervisor.pid().to_string();
+        let (_, usage) = supervisor
+            .monitor(async {
+                std::process::Command::new("kill")
+                    .args(["-9", &pid])
+                    .status()
+                    .unwrap();
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            })
+            .await;
+        assert!(usage.exit.is_some());
+        assert_eq!(usage.after, None);
+
+        let command = ["false".to_string()];
+        assert!(Supervisor::launch(&command, "127.0.0.1:1").await.is_err());
+    }
+
+    #[test]
+    fn test_amplify() {
+        let mut usage = super::Usage {
+            before: crate::process::Sample {
+                rss: 1000,
+                cpu_ms: 0,
+            },
+            after: None,
+            peak_rss: 5000,
+            cpu_ms: 0,
+            samples: 2,
+            amplification: None,
+            exit: None,
+        };
+
+        usage.amplify(0);
+        assert_eq!(usage.amplification, None);
+        usage.amplify(400);
+        assert_eq!(usage.amplification, Some(10.0));
+    }
+}
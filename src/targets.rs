@@ -0,0 +1,233 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{probe::Target, transport::Tls};
+
+/// A line of a targets file: `host:port [tls=MODE] [credentials=NAME]`.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Entry {
+    pub host: String,
+    #[serde(default)]
+    pub tls: Tls,
+    /// Name of the credentials (see [`credentials`]).
+    #[serde(default)]
+    pub credentials: Option<String>,
+}
+
+/// Environment variables that hold the credentials `name` refers to.
+pub fn variables(name: &str) -> (String, String) {
+    let name = name.to_ascii_uppercase().replace('-', "_");
+
+    (
+        format!("IMAP_SEC_{name}_USERNAME"),
+        format!("IMAP_SEC_{name}_PASSWORD"),
+    )
+}
+
+/// Where the username and password of credentials come from.
+///
+/// Unset fields fall back to the environment variables of [`variables`]. Targets and
+/// configuration files are shared and checked in, so passwords are never part of them.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Source {
+    pub username: Option<String>,
+    /// Environment variable with the username.
+    pub username_env: Option<String>,
+    /// Environment variable with the password.
+    pub password_env: Option<String>,
+    /// File with the password (without the trailing newline).
+    pub password_file: Option<PathBuf>,
+}
+
+impl Source {
+    fn resolve(&self, name: &str) -> Result<(String, String), String> {
+        let (username, password) = variables(name);
+        let read = |variable: &str| {
+            std::env::var(variable)
+                .map_err(|_| format!("credentials `{name}`: {variable} is not set"))
+        };
+
+        let username = match (&self.username, &self.username_env) {
+            (Some(username), _) => username.clone(),
+            (None, Some(variable)) => read(variable)?,
+            (None, None) => read(&username)?,
+        };
+        let password = match (&self.password_env, &self.password_file) {
+            (Some(variable), _) => read(variable)?,
+            (None, Some(path)) => std::fs::read_to_string(path)
+                .map_err(|error| format!("credentials `{name}`: {}: {error}", path.display()))?
+                .trim_end_matches(['\r', '\n'])
+                .to_string(),
+            (None, None) => read(&password)?,
+        };
+
+        Ok((username, password))
+    }
+}
+
+/// Username and password `name` refers to, from its source in `sources` or the environment
+/// variables of [`variables`].
+pub fn credentials(
+    name: &str,
+    sources: &HashMap<String, Source>,
+) -> Result<(String, String), String> {
+    sources.get(name).cloned().unwrap_or_default().resolve(name)
+}
+
+impl Entry {
+    /// Entry for `host` without TLS or credentials.
+    pub fn new(host: &str) -> Self {
+        Self {
+            host: host.to_string(),
+            tls: Tls::None,
+            credentials: None,
+        }
+    }
+
+    /// The target with credentials resolved from `sources` (see [`credentials`]).
+    pub fn target(&self, sources: &HashMap<String, Source>) -> Result<Target, String> {
+        let (username, password) = match &self.credentials {
+            Some(name) => {
+                let (username, password) = credentials(name, sources)?;
+                (Some(username), Some(password))
+            }
+            None => (None, None),
+        };
+
+        Ok(Target {
+            host: self.host.clone(),
+            username,
+            password,
+            tls: self.tls,
+        })
+    }
+}
+
+fn entry(line: &str) -> Result<Entry, String> {
+    let mut words = line.split_whitespace();
+    let host = words.next().unwrap_or_default();
+
+    match host.rsplit_once(':') {
+        Some((name, port)) if !name.is_empty() && port.parse::<u16>().is_ok() => {}
+        _ => return Err(format!("expected host:port, got `{host}`")),
+    }
+
+    let mut entry = Entry::new(host);
+
+    for word in words {
+        match word.split_once('=') {
+            Some(("tls", mode)) => entry.tls = mode.parse()?,
+            Some(("credentials", name)) if !name.is_empty() => {
+                entry.credentials = Some(name.to_string())
+            }
+            _ => {
+                return Err(format!(
+                    "unknown option `{word}` (expected tls=MODE or credentials=NAME)"
+                ))
+            }
+        }
+    }
+
+    Ok(entry)
+}
+
+/// Parse a targets file: one target per line, `#` starts a comment.
+pub fn parse(data: &str) -> Result<Vec<Entry>, Box<dyn Error>> {
+    let mut entries = Vec::new();
+
+    for (number, line) in data.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        entries.push(entry(line).map_err(|error| format!("line {}: {error}", number + 1))?);
+    }
+
+    Ok(entries)
+}
+
+pub fn load(path: &Path) -> Result<Vec<Entry>, Box<dyn Error>> {
+    parse(&std::fs::read_to_string(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{credentials, parse, variables, Entry, Source};
+    use crate::transport::Tls;
+
+    #[test]
+    fn test_parse() {
+        let entries = parse(
+            "# Production\n\
+             imap.example.org:143 tls=starttls credentials=monitoring\n\
+             \n\
+             imap.example.org:993 tls=implicit # mail relay\n\
+             [::1]:143\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            entries,
+            [
+                Entry {
+                    host: "imap.example.org:143".into(),
+                    tls: Tls::StartTls,
+                    credentials: Some("monitoring".into()),
+                },
+                Entry {
+                    host: "imap.example.org:993".into(),
+                    tls: Tls::Implicit,
+                    credentials: None,
+                },
+                Entry::new("[::1]:143"),
+            ]
+        );
+
+        assert!(parse("imap.example.org").is_err());
+        assert!(parse("imap.example.org:143 tls=ssl").is_err());
+        assert!(parse("imap.example.org:143 user=alice").is_err());
+    }
+
+    #[test]
+    fn test_variables() {
+        assert_eq!(
+            variables("ci-monitoring"),
+            (
+                "IMAP_SEC_CI_MONITORING_USERNAME".into(),
+                "IMAP_SEC_CI_MONITORING_PASSWORD".into()
+            )
+        );
+    }
+
+    #[test]
+    fn test_credentials() {
+        let path = std::env::temp_dir().join("imap-sec-test-credentials");
+        std::fs::write(&path, "secret\n").unwrap();
+
+        let sources = HashMap::from([(
+            "monitoring".to_string(),
+            Source {
+                username: Some("monitor".into()),
+                password_file: Some(path.clone()),
+                ..Default::default()
+            },
+        )]);
+        assert_eq!(
+            credentials("monitoring", &sources),
+            Ok(("monitor".into(), "secret".into()))
+        );
+        assert!(credentials("imap-sec-test-missing", &sources).is_err());
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
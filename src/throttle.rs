@@ -0,0 +1,354 @@
+use std::{
+    error::Error,
+    fmt,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use imap_types::{
+    auth::AuthMechanism,
+    command::CommandBody,
+    response::{Code, Data},
+};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::{
+    session::{Outcome, Reply, Session},
+    transport::Tls,
+};
+
+/// Upper bound for `max_attempts` so that a typo can't hammer a server.
+pub const HARD_CAP: usize = 100;
+
+/// Extra latency (compared to the first attempt) that counts as a delay.
+const DELAY_THRESHOLD: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Mechanism {
+    Login,
+    Plain,
+}
+
+impl FromStr for Mechanism {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "login" => Ok(Self::Login),
+            "plain" => Ok(Self::Plain),
+            _ => Err(format!(
+                "unknown mechanism `{s}` (expected `login` or `plain`)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Mechanism {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Login => f.write_str("LOGIN"),
+            Self::Plain => f.write_str("AUTHENTICATE PLAIN"),
+        }
+    }
+}
+
+impl Mechanism {
+    /// Authenticate on `session`. PLAIN only sends the credentials with the command when the
+    /// server advertises SASL-IR (`sasl_ir`).
+    pub(crate) async fn authenticate(
+        &self,
+        session: &mut Session,
+        tag: &str,
+        username: &str,
+        password: &str,
+        sasl_ir: bool,
+    ) -> Result<Reply, Box<dyn Error>> {
+        Ok(match self {
+            Self::Login => {
+                let body = CommandBody::login(username.to_string(), password.to_string())?;
+                session.command(tag, body).await
+            }
+            Self::Plain => {
+                let response = format!("\x00{username}\x00{password}").into_bytes();
+                session
+                    .authenticate(tag, AuthMechanism::Plain, response, sasl_ir)
+                    .await
+            }
+        })
+    }
+}
+
+/// Whether the server advertises SASL-IR (learned through CAPABILITY on `session`).
+pub(crate) async fn advertises_sasl_ir(session: &mut Session) -> bool {
+    let reply = session.command("C", CommandBody::Capability).await;
+    reply.data.iter().any(|data| match data {
+        Data::Capability(capabilities) => capabilities
+            .as_ref()
+            .iter()
+            .any(|capability| capability.to_string().eq_ignore_ascii_case("SASL-IR")),
+        _ => false,
+    })
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Attempt {
+    /// Number of the connection (starting at 1).
+    pub connection: usize,
+    /// Number of the attempt on this connection (starting at 1).
+    pub attempt: usize,
+    pub outcome: Outcome,
+    pub code: Option<Code<'static>>,
+    pub text: Option<String>,
+    pub latency_ms: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Throttle {
+    pub username: String,
+    pub mechanism: Mechanism,
+    pub attempts: Vec<Attempt>,
+    /// First attempt (overall, starting at 1) that was delayed noticeably.
+    pub delayed_from: Option<usize>,
+    /// Attempts sent on each connection, in order. When the server closed a connection, this
+    /// includes the attempt that found it closed.
+    pub attempts_per_connection: Vec<usize>,
+    /// Whether the correct password was rejected after the failed attempts (only known when a
+    /// password was given).
+    pub locked_out: Option<bool>,
+}
+
+/// Measure how the server throttles failed authentication attempts for `username`.
+///
+/// Sends exactly `max_attempts` wrong passwords, reconnecting whenever the server closes the
+/// connection. If `password` is given, a final login with the correct password tells whether the
+/// account was locked.
+pub async fn auth_throttle(
+    host: &str,
+    tls: Tls,
+    username: &str,
+    password: Option<&str>,
+    mechanism: Mechanism,
+    max_attempts: usize,
+    timeout: Duration,
+) -> Result<Throttle, Box<dyn Error>> {
+    if max_attempts > HARD_CAP {
+        return Err(format!("max_attempts must not exceed {HARD_CAP}").into());
+    }
+
+    let mut result = Throttle {
+        username: username.to_string(),
+        mechanism,
+        attempts: Vec::new(),
+        delayed_from: None,
+        attempts_per_connection: Vec::new(),
+        locked_out: None,
+    };
+
+    let mut session = None;
+    let mut baseline = None;
+    let mut sasl_ir = None;
+
+    while result.attempts.len() < max_attempts {
+        if session.is_none() {
+            result.attempts_per_connection.push(0);
+            let (mut new, _) = Session::connect(host, tls, timeout).await?;
+            if mechanism == Mechanism::Plain && sasl_ir.is_none() {
+                sasl_ir = Some(advertises_sasl_ir(&mut new).await);
+            }
+            session = Some(new);
+        }
+        let current = session.as_mut().unwrap();
+
+        let wrong = format!("imap-sec-wrong-{}", result.attempts.len());
+        let started = Instant::now();
+        let reply = mechanism
+            .authenticate(current, "A", username, &wrong, sasl_ir.unwrap_or_default())
+            .await?;
+        let latency = started.elapsed();
+
+        let baseline = *baseline.get_or_insert(latency);
+        if result.delayed_from.is_none() && latency > baseline + DELAY_THRESHOLD {
+            result.delayed_from = Some(result.attempts.len() + 1);
+        }
+
+        let connection = result.attempts_per_connection.len();
+        let on_connection = result.attempts_per_connection.last_mut().unwrap();
+        *on_connection += 1;
+
+        info!(connection, attempt = *on_connection, outcome = ?reply.outcome, ?latency);
+
+        result.attempts.push(Attempt {
+            connection,
+            attempt: *on_connection,
+            outcome: reply.outcome,
+            code: reply.code,
+            text: reply.text,
+            latency_ms: latency.as_millis() as u64,
+        });
+
+        match reply.outcome {
+            Outcome::Ok => return Err("wrong password was accepted".into()),
+            Outcome::No | Outcome::Bad => {}
+            Outcome::Bye
+            | Outcome::Continue
+            | Outcome::Closed
+            | Outcome::Timeout
+            | Outcome::Error => session = None,
+        }
+    }
+
+    if let Some(password) = password {
+        let (mut session, _) = Session::connect(host, tls, timeout).await?;
+        let reply = mechanism
+            .authenticate(
+                &mut session,
+                "B",
+                username,
+                password,
+                sasl_ir.unwrap_or_default(),
+            )
+            .await?;
+        result.locked_out = Some(reply.outcome != Outcome::Ok);
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{auth_throttle, Mechanism, HARD_CAP};
+    use crate::{
+        mock::{self, Config, Quirks},
+        session::Outcome,
+        transport::Tls,
+    };
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[tokio::test]
+    async fn test_delay() {
+        let host = mock::spawn(Config {
+            quirks: Quirks {
+                failed_login_delay: Duration::from_millis(1200),
+                delay_after: 2,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .await;
+
+        let result = auth_throttle(
+            &host,
+            Tls::None,
+            "alice",
+            None,
+            Mechanism::Login,
+            3,
+            TIMEOUT,
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.delayed_from, Some(3));
+        assert_eq!(result.attempts_per_connection, [3]);
+        assert_eq!(result.locked_out, None);
+
+        let host = mock::spawn(Config::default()).await;
+        let result = auth_throttle(
+            &host,
+            Tls::None,
+            "alice",
+            None,
+            Mechanism::Plain,
+            3,
+            TIMEOUT,
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.delayed_from, None);
+        assert!(result
+            .attempts
+            .iter()
+            .all(|attempt| attempt.outcome == Outcome::No));
+
+        // With SASL-IR, the credentials go with the command (the mock rejects them otherwise).
+        let mut config = Config::default();
+        config.capabilities.push("SASL-IR".into());
+        let host = mock::spawn(config).await;
+        let result = auth_throttle(
+            &host,
+            Tls::None,
+            "alice",
+            Some("password"),
+            Mechanism::Plain,
+            2,
+            TIMEOUT,
+        )
+        .await
+        .unwrap();
+        assert!(result
+            .attempts
+            .iter()
+            .all(|attempt| attempt.outcome == Outcome::No));
+        assert_eq!(result.locked_out, Some(false));
+    }
+
+    #[tokio::test]
+    async fn test_limits() {
+        let host = mock::spawn(Config::default()).await;
+        let password = Some("password");
+
+        assert!(auth_throttle(
+            &host,
+            Tls::None,
+            "alice",
+            password,
+            Mechanism::Login,
+            HARD_CAP + 1,
+            TIMEOUT
+        )
+        .await
+        .is_err());
+
+        let result = auth_throttle(
+            &host,
+            Tls::None,
+            "alice",
+            password,
+            Mechanism::Login,
+            3,
+            TIMEOUT,
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.locked_out, Some(false));
+
+        let host = mock::spawn(Config {
+            quirks: Quirks {
+                max_failed_logins: Some(2),
+                lockout_after: Some(3),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .await;
+
+        // The third attempt finds the first connection closed.
+        let result = auth_throttle(
+            &host,
+            Tls::None,
+            "alice",
+            password,
+            Mechanism::Plain,
+            5,
+            TIMEOUT,
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.attempts_per_connection, [3, 2]);
+        assert_eq!(result.locked_out, Some(true));
+    }
+}
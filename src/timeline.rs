@@ -0,0 +1,261 @@
+use imap_types::response::Capability;
+use serde::{Deserialize, Serialize};
+
+/// Point in a session at which capabilities were learned.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    Greeting,
+    PreAuth,
+    /// On a separate connection, after STARTTLS.
+    StartTls,
+    /// Response to LOGIN (or AUTHENTICATE).
+    Login,
+    PostAuth,
+    PostEnable,
+    /// After COMPRESS. Never learned, but reported in [`Timeline::skipped`]: a CAPABILITY after
+    /// COMPRESS would have to be compressed, which the client doesn't support.
+    PostCompress,
+}
+
+impl Phase {
+    fn authenticated(&self) -> bool {
+        matches!(
+            self,
+            Self::Login | Self::PostAuth | Self::PostEnable | Self::PostCompress
+        )
+    }
+}
+
+/// Where the capabilities came from.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    Code,
+    Untagged,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Snapshot {
+    pub phase: Phase,
+    pub source: Source,
+    pub capabilities: Vec<Capability<'static>>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Change {
+    pub from: Phase,
+    pub to: Phase,
+    pub added: Vec<Capability<'static>>,
+    pub removed: Vec<Capability<'static>>,
+    /// Added or removed capabilities that should not have changed.
+    pub unexpected: Vec<Capability<'static>>,
+    /// Both snapshots describe the same state (e.g., the LOGIN response code and the following
+    /// CAPABILITY), so any difference is a conflict.
+    pub conflict: bool,
+}
+
+/// A phase that was not learned.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Skipped {
+    pub phase: Phase,
+    pub reason: String,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Timeline {
+    pub snapshots: Vec<Snapshot>,
+    pub changes: Vec<Change>,
+    #[serde(default)]
+    pub skipped: Vec<Skipped>,
+}
+
+fn name(capability: &Capability) -> String {
+    capability.to_string().to_ascii_uppercase()
+}
+
+/// Whether adding (or removing) `capability` between `from` and `to` is expected.
+fn expected(from: Phase, to: Phase, capability: &Capability, added: bool) -> bool {
+    let name = name(capability);
+    let pre_auth_only = name == "STARTTLS"
+        || name == "LOGINDISABLED"
+        || name.starts_with("AUTH=")
+        || name == "SASL-IR";
+
+    match (from, to) {
+        // TLS may unlock authentication mechanisms, but must not offer STARTTLS again.
+        (Phase::PreAuth | Phase::Greeting, Phase::StartTls) => {
+            if added {
+                name.starts_with("AUTH=") || name == "SASL-IR"
+            } else {
+                name == "STARTTLS" || name == "LOGINDISABLED"
+            }
+        }
+        // Authentication may change everything but the base protocol, and must not bring back
+        // capabilities that only make sense before authentication.
+        (from, to) if !from.authenticated() && to.authenticated() => {
+            if added {
+                !pre_auth_only
+            } else {
+                !name.starts_with("IMAP4REV")
+            }
+        }
+        _ => false,
+    }
+}
+
+impl Timeline {
+    pub fn push(&mut self, phase: Phase, source: Source, capabilities: &[Capability<'static>]) {
+        self.snapshots.push(Snapshot {
+            phase,
+            source,
+            capabilities: capabilities.to_vec(),
+        });
+    }
+
+    pub fn skip(&mut self, phase: Phase, reason: &str) {
+        self.skipped.push(Skipped {
+            phase,
+            reason: reason.into(),
+        });
+    }
+
+    /// Compare every snapshot with its predecessor on the same connection.
+    ///
+    /// Within a phase, the untagged CAPABILITY data is compared with the response code.
+    pub fn diff(&mut self) {
+        self.changes.clear();
+
+        for (index, current) in self.snapshots.iter().enumerate() {
+            let previous = self.snapshots[..index].iter().rev().find(|previous| {
+                // The STARTTLS connection branches off after the pre-auth capabilities.
+                current.phase == Phase::StartTls || previous.phase != Phase::StartTls
+            });
+
+            let Some(previous) = previous else {
+                continue;
+            };
+
+            let added = current
+                .capabilities
+                .iter()
+                .filter(|capability| !previous.capabilities.contains(capability))
+                .cloned()
+                .collect::<Vec<_>>();
+            let removed = previous
+                .capabilities
+                .iter()
+                .filter(|capability| !current.capabilities.contains(capability))
+                .cloned()
+                .collect::<Vec<_>>();
+
+            if added.is_empty() && removed.is_empty() {
+                continue;
+            }
+
+            let unexpected = added
+                .iter()
+                .filter(|capability| !expected(previous.phase, current.phase, capability, true))
+                .chain(removed.iter().filter(|capability| {
+                    !expected(previous.phase, current.phase, capability, false)
+                }))
+                .cloned()
+                .collect();
+
+            let conflict = previous.source != current.source
+                && previous.phase.authenticated() == current.phase.authenticated()
+                && current.phase != Phase::StartTls;
+
+            self.changes.push(Change {
+                from: previous.phase,
+                to: current.phase,
+                added,
+                removed,
+                unexpected,
+                conflict,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use imap_types::{auth::AuthMechanism, response::Capability};
+
+    use super::{Phase, Source, Timeline};
+
+    #[test]
+    fn test_diff() {
+        let mut timeline = Timeline::default();
+        timeline.push(
+            Phase::Greeting,
+            Source::Code,
+            &[Capability::Imap4Rev1, Capability::StartTls],
+        );
+        timeline.push(
+            Phase::PreAuth,
+            Source::Untagged,
+            &[
+                Capability::Imap4Rev1,
+                Capability::StartTls,
+                Capability::LoginDisabled,
+            ],
+        );
+        timeline.push(
+            Phase::StartTls,
+            Source::Untagged,
+            &[
+                Capability::Imap4Rev1,
+                Capability::Auth(AuthMechanism::Plain),
+            ],
+        );
+        timeline.push(
+            Phase::Login,
+            Source::Code,
+            &[Capability::Imap4Rev1, Capability::Idle],
+        );
+        timeline.push(
+            Phase::PostAuth,
+            Source::Untagged,
+            &[
+                Capability::Imap4Rev1,
+                Capability::Idle,
+                Capability::StartTls,
+            ],
+        );
+        timeline.diff();
+
+        let changes = timeline
+            .changes
+            .iter()
+            .map(|change| (change.from, change.to))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            changes,
+            vec![
+                (Phase::Greeting, Phase::PreAuth),
+                (Phase::PreAuth, Phase::StartTls),
+                (Phase::PreAuth, Phase::Login),
+                (Phase::Login, Phase::PostAuth),
+            ]
+        );
+
+        // Greeting and CAPABILITY disagree.
+        assert!(timeline.changes[0].conflict);
+        assert_eq!(
+            timeline.changes[0].unexpected,
+            vec![Capability::LoginDisabled]
+        );
+
+        // STARTTLS unlocked AUTH=PLAIN and removed STARTTLS and LOGINDISABLED.
+        assert!(!timeline.changes[1].conflict);
+        assert!(timeline.changes[1].unexpected.is_empty());
+
+        // LOGIN may add IDLE.
+        assert!(timeline.changes[2].unexpected.is_empty());
+
+        // STARTTLS after authentication is unexpected and conflicts with the LOGIN response code.
+        assert!(timeline.changes[3].conflict);
+        assert_eq!(timeline.changes[3].unexpected, vec![Capability::StartTls]);
+    }
+}
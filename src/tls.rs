@@ -0,0 +1,135 @@
+use std::{error::Error, sync::Arc, time::Duration};
+
+use imap_codec::{decode::Decoder, ResponseCodec};
+use imap_types::{
+    bounded_static::IntoBoundedStatic,
+    response::{Capability, Code, Data, Response, Status, StatusBody, Tagged},
+};
+use rustls::{
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    pki_types::{CertificateDer, ServerName, UnixTime},
+    ClientConfig, DigitallySignedStruct, SignatureScheme,
+};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader};
+use tokio_rustls::TlsConnector;
+
+use crate::transport::{self, Tls};
+
+/// Accepts every certificate: we want to talk to the server, not judge its PKI.
+#[derive(Debug)]
+struct NoVerification;
+
+impl ServerCertVerifier for NoVerification {
+    fn verify_server_cert(
+        &self,
+        _: &CertificateDer<'_>,
+        _: &[CertificateDer<'_>],
+        _: &ServerName<'_>,
+        _: &[u8],
+        _: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        _: &[u8],
+        _: &CertificateDer<'_>,
+        _: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        _: &[u8],
+        _: &CertificateDer<'_>,
+        _: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        vec![
+            SignatureScheme::RSA_PKCS1_SHA256,
+            SignatureScheme::RSA_PKCS1_SHA384,
+            SignatureScheme::RSA_PKCS1_SHA512,
+            SignatureScheme::ECDSA_NISTP256_SHA256,
+            SignatureScheme::ECDSA_NISTP384_SHA384,
+            SignatureScheme::RSA_PSS_SHA256,
+            SignatureScheme::RSA_PSS_SHA384,
+            SignatureScheme::RSA_PSS_SHA512,
+            SignatureScheme::ED25519,
+        ]
+    }
+}
+
+/// TLS connector that accepts any certificate (probes must reach misconfigured servers).
+pub fn connector() -> TlsConnector {
+    let config = ClientConfig::builder()
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(NoVerification))
+        .with_no_client_auth();
+
+    TlsConnector::from(Arc::new(config))
+}
+
+/// Server name of `host` (`name:port`) for SNI.
+pub fn server_name(host: &str) -> Result<ServerName<'static>, Box<dyn Error>> {
+    let name = host.rsplit_once(':').map(|(name, _)| name).unwrap_or(host);
+    Ok(ServerName::try_from(name.to_string())?)
+}
+
+/// Read lines until the line tagged with `tag` and collect all capabilities on the way.
+async fn read_until<S: AsyncRead + Unpin>(
+    reader: &mut BufReader<S>,
+    tag: &str,
+    capabilities: &mut Vec<Capability<'static>>,
+) -> Result<String, Box<dyn Error>> {
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await? == 0 {
+            return Err("connection closed".into());
+        }
+
+        if let Ok((_, response)) = ResponseCodec::default().decode(line.as_bytes()) {
+            match response {
+                Response::Data(Data::Capability(received))
+                | Response::Status(Status::Tagged(Tagged {
+                    body:
+                        StatusBody {
+                            code: Some(Code::Capability(received)),
+                            ..
+                        },
+                    ..
+                })) => *capabilities = received.into_static().into_inner(),
+                _ => {}
+            }
+        }
+
+        if line.starts_with(&format!("{tag} ")) {
+            return Ok(line);
+        }
+    }
+}
+
+/// Issue STARTTLS and return the capabilities the server advertises over TLS.
+///
+/// Connects like a probe with [`Tls::StartTls`] (see [`transport::connect`]), but talks to the
+/// server directly (line by line) instead of through the transcript.
+pub async fn capabilities_after_starttls(
+    host: &str,
+    timeout: Duration,
+) -> Result<Vec<Capability<'static>>, Box<dyn Error>> {
+    tokio::time::timeout(timeout, async {
+        let (stream, _) = transport::connect(host, Tls::StartTls).await?;
+        let mut reader = BufReader::new(stream);
+        reader.get_mut().write_all(b"X CAPABILITY\r\n").await?;
+
+        let mut capabilities = Vec::new();
+        read_until(&mut reader, "X", &mut capabilities).await?;
+
+        Ok::<_, Box<dyn Error>>(capabilities)
+    })
+    .await?
+}
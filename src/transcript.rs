@@ -0,0 +1,286 @@
+use std::{
+    error::Error,
+    fs::File,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, OnceLock,
+    },
+    time::Instant,
+};
+
+use imap_types::utils::escape_byte_string;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use tracing::error;
+
+use crate::transport::{self, Tls};
+
+static RECORDER: OnceLock<Recorder> = OnceLock::new();
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    /// A connection to `data` was opened (with [`Entry::tls`]).
+    Open,
+    /// Client to server.
+    Send,
+    /// Server to client.
+    Receive,
+    ClientClosed,
+    ServerClosed,
+}
+
+/// A line of the transcript file.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Entry {
+    /// Number of the connection (starting at 1).
+    pub connection: u64,
+    /// Microseconds since recording started (monotonic).
+    pub elapsed_us: u64,
+    pub kind: Kind,
+    /// Bytes as produced by [`escape_byte_string`] (see [`unescape`]).
+    pub data: String,
+    /// How the connection was made ([`Kind::Open`] only, missing in older transcripts).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<Tls>,
+}
+
+/// Connections of a transcript that belong to a result.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Attachment {
+    pub path: PathBuf,
+    pub connections: Vec<u64>,
+}
+
+struct Recorder {
+    path: PathBuf,
+    start: Instant,
+    connections: AtomicU64,
+    file: Mutex<File>,
+}
+
+impl Recorder {
+    fn record(&self, connection: u64, kind: Kind, data: &[u8]) {
+        self.write(&self.entry(connection, kind, data));
+    }
+
+    fn entry(&self, connection: u64, kind: Kind, data: &[u8]) -> Entry {
+        Entry {
+            connection,
+            elapsed_us: self.start.elapsed().as_micros() as u64,
+            kind,
+            data: escape_byte_string(data),
+            tls: None,
+        }
+    }
+
+    fn write(&self, entry: &Entry) {
+        let mut line = serde_json::to_vec(entry).unwrap();
+        line.push(b'\n');
+
+        if let Err(error) = self.file.lock().unwrap().write_all(&line) {
+            error!(?error, "could not write transcript");
+        }
+    }
+}
+
+/// Record all following connections to `path`.
+pub fn record(path: &Path) -> Result<(), Box<dyn Error>> {
+    let recorder = Recorder {
+        path: path.to_path_buf(),
+        start: Instant::now(),
+        connections: AtomicU64::new(0),
+        file: Mutex::new(File::create(path)?),
+    };
+
+    RECORDER
+        .set(recorder)
+        .map_err(|_| "transcript is already recorded")?;
+
+    Ok(())
+}
+
+/// Number of the next connection, used to tell which connections belong to a result.
+pub fn next_connection() -> u64 {
+    RECORDER
+        .get()
+        .map(|recorder| recorder.connections.load(Ordering::SeqCst) + 1)
+        .unwrap_or_default()
+}
+
+/// Connections opened since `first` (see [`next_connection`]), if recording.
+pub fn attachment(first: u64) -> Option<Attachment> {
+    let recorder = RECORDER.get()?;
+
+    Some(Attachment {
+        path: recorder.path.clone(),
+        connections: (first..next_connection()).collect(),
+    })
+}
+
+/// Copy `from` to `to` after sending `first`, recording everything when a transcript is recorded.
+async fn relay(
+    recorder: Option<&'static Recorder>,
+    connection: u64,
+    kind: Kind,
+    first: Vec<u8>,
+    mut from: impl AsyncRead + Unpin,
+    mut to: impl AsyncWrite + Unpin,
+) {
+    let mut buffer = first;
+    let mut read = buffer.len();
+    buffer.resize(read.max(64 * 1024), 0);
+
+    loop {
+        if read > 0 {
+            if let Some(recorder) = recorder {
+                recorder.record(connection, kind, &buffer[..read]);
+            }
+
+            if to.write_all(&buffer[..read]).await.is_err() {
+                break;
+            }
+        }
+
+        read = match from.read(&mut buffer).await {
+            Ok(0) | Err(_) => break,
+            Ok(read) => read,
+        };
+    }
+
+    let closed = match kind {
+        Kind::Send => Kind::ClientClosed,
+        _ => Kind::ServerClosed,
+    };
+    if let Some(recorder) = recorder {
+        recorder.record(connection, closed, b"");
+    }
+    let _ = to.shutdown().await;
+}
+
+/// Connect to `host` with `tls`, through a local relay if a transcript is recorded or the target
+/// uses TLS (see [`transport::connect`]).
+///
+/// Probes write raw bytes next to the client (e.g., malformed commands), so tracing alone misses
+/// parts of the exchange. The relay sees every byte in both directions. With TLS, it also lets
+/// the probes work on a plain [`TcpStream`].
+pub async fn connect(host: &str, tls: Tls) -> io::Result<TcpStream> {
+    let recorder = RECORDER.get();
+    if recorder.is_none() && tls == Tls::None {
+        return TcpStream::connect(host).await;
+    }
+
+    let (upstream, first) = transport::connect(host, tls).await?;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let (client, accepted) = tokio::join!(
+        TcpStream::connect(listener.local_addr()?),
+        listener.accept()
+    );
+    let (client, (local, peer)) = (client?, accepted?);
+    if peer != client.local_addr()? {
+        return Err(io::Error::other(
+            "unexpected connection to transcript relay",
+        ));
+    }
+
+    let connection = match recorder {
+        Some(recorder) => {
+            let connection = recorder.connections.fetch_add(1, Ordering::SeqCst) + 1;
+            recorder.write(&Entry {
+                tls: Some(tls),
+                ..recorder.entry(connection, Kind::Open, host.as_bytes())
+            });
+            connection
+        }
+        None => 0,
+    };
+
+    let (local_read, local_write) = local.into_split();
+    let (upstream_read, upstream_write) = tokio::io::split(upstream);
+    tokio::spawn(relay(
+        recorder,
+        connection,
+        Kind::Send,
+        Vec::new(),
+        local_read,
+        upstream_write,
+    ));
+    tokio::spawn(relay(
+        recorder,
+        connection,
+        Kind::Receive,
+        first,
+        upstream_read,
+        local_write,
+    ));
+
+    Ok(client)
+}
+
+/// Reverse [`escape_byte_string`].
+pub fn unescape(data: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::with_capacity(data.len());
+    let mut chars = data.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buffer = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+            continue;
+        }
+
+        match chars.next() {
+            Some('t') => bytes.push(b'\t'),
+            Some('n') => bytes.push(b'\n'),
+            Some('r') => bytes.push(b'\r'),
+            Some('"') => bytes.push(b'"'),
+            Some('\\') => bytes.push(b'\\'),
+            Some('x') => {
+                let hex = chars.by_ref().take(2).collect::<String>();
+                bytes.push(
+                    u8::from_str_radix(&hex, 16).map_err(|_| format!("invalid escape \\x{hex}"))?,
+                );
+            }
+            other => return Err(format!("invalid escape {other:?}")),
+        }
+    }
+
+    Ok(bytes)
+}
+
+/// Read a transcript file.
+pub fn load(path: &Path) -> Result<Vec<Entry>, Box<dyn Error>> {
+    let mut entries = Vec::new();
+
+    for line in std::fs::read_to_string(path)?.lines() {
+        if !line.trim().is_empty() {
+            entries.push(serde_json::from_str(line)?);
+        }
+    }
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use imap_types::utils::escape_byte_string;
+
+    use super::unescape;
+
+    #[test]
+    fn test_unescape() {
+        let bytes = (0..=255u8)
+            .chain(*b"A1 LOGIN \"a\\b\" {3}\r\n")
+            .collect::<Vec<_>>();
+        assert_eq!(unescape(&escape_byte_string(&bytes)).unwrap(), bytes);
+
+        assert!(unescape("\\q").is_err());
+        assert!(unescape("\\xzz").is_err());
+    }
+}
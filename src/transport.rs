@@ -0,0 +1,120 @@
+use std::{io, str::FromStr, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
+    net::TcpStream,
+};
+
+use crate::tls;
+
+/// Upper bound for the TLS setup (including STARTTLS).
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How to reach the IMAP protocol of a target.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Tls {
+    /// Plaintext.
+    #[default]
+    None,
+    /// Plaintext greeting, then STARTTLS before the probe starts.
+    #[serde(rename = "starttls")]
+    StartTls,
+    /// TLS from the start (e.g., port 993).
+    Implicit,
+}
+
+impl FromStr for Tls {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "starttls" => Ok(Self::StartTls),
+            "implicit" => Ok(Self::Implicit),
+            _ => Err(format!(
+                "unknown TLS mode `{s}` (expected `none`, `starttls`, or `implicit`)"
+            )),
+        }
+    }
+}
+
+/// A connection, plaintext or TLS.
+pub trait Io: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Io for T {}
+
+async fn starttls(host: &str) -> Result<(Box<dyn Io>, Vec<u8>), Box<dyn std::error::Error>> {
+    let mut reader = BufReader::new(TcpStream::connect(host).await?);
+
+    let mut greeting = Vec::new();
+    reader.read_until(b'\n', &mut greeting).await?;
+
+    reader.get_mut().write_all(b"S STARTTLS\r\n").await?;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await? == 0 {
+            return Err("connection closed during STARTTLS".into());
+        }
+
+        if line.starts_with("S ") {
+            if !line.to_ascii_uppercase().starts_with("S OK") {
+                return Err(format!("STARTTLS rejected: {}", line.trim_end()).into());
+            }
+            break;
+        }
+    }
+
+    let stream = tls::connector()
+        .connect(tls::server_name(host)?, reader.into_inner())
+        .await?;
+
+    Ok((Box::new(stream), greeting))
+}
+
+/// Connect to `host` with `tls`.
+///
+/// Returns the connection and bytes the client must see first: with STARTTLS, the greeting was
+/// already consumed, so the probe gets it replayed.
+pub async fn connect(host: &str, tls: Tls) -> io::Result<(Box<dyn Io>, Vec<u8>)> {
+    let setup = async {
+        match tls {
+            Tls::None => Ok::<_, Box<dyn std::error::Error>>((
+                Box::new(TcpStream::connect(host).await?) as Box<dyn Io>,
+                Vec::new(),
+            )),
+            Tls::StartTls => starttls(host).await,
+            Tls::Implicit => {
+                let stream = tls::connector()
+                    .connect(tls::server_name(host)?, TcpStream::connect(host).await?)
+                    .await?;
+                Ok((Box::new(stream) as Box<dyn Io>, Vec::new()))
+            }
+        }
+    };
+
+    match tokio::time::timeout(TIMEOUT, setup).await {
+        Ok(Ok(connection)) => Ok(connection),
+        Ok(Err(error)) => Err(io::Error::other(error.to_string())),
+        Err(_) => Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "TLS setup timed out",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Tls;
+
+    #[test]
+    fn test_tls() {
+        assert_eq!("starttls".parse(), Ok(Tls::StartTls));
+        assert_eq!(
+            serde_json::to_string(&Tls::StartTls).unwrap(),
+            "\"starttls\""
+        );
+        assert!("ssl".parse::<Tls>().is_err());
+    }
+}